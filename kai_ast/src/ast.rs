@@ -1,25 +1,48 @@
-use kai_common::ops::Opcode;
+use kai_common::diagnostics::Span;
+use kai_common::int_ty::IntTy;
+use kai_common::ops::{Opcode, UnOpcode};
+use kai_common::overflow_mode::OverflowMode;
+use std::fmt;
 use std::fmt::Debug;
 
+#[derive(Debug)]
+pub struct Program {
+  pub functions: Vec<Function>,
+}
+
 #[derive(Debug)]
 pub struct Function {
   pub ident: String,
   pub args: Vec<FuncArg>,
   pub body: Vec<Stmt>,
   pub ret_ty: Type,
+  // set by an `@overflow(mode)` attribute, `--overflow` applies to functions without one
+  pub overflow: Option<OverflowMode>,
+  // covers the signature, `function` up to the return type
+  pub span: Span,
 }
 
 #[derive(Debug)]
 pub struct FuncArg {
   pub ty: Type,
   pub ident: String,
+  pub span: Span,
+}
+
+#[derive(Debug)]
+pub struct Stmt {
+  pub kind: StmtKind,
+  pub span: Span,
 }
 
 #[derive(Debug)]
-pub enum Stmt {
+pub enum StmtKind {
   VarDecl(String, Expr),
   VarAsgn(String, Expr),
   If(Expr, Vec<Stmt>, Box<ElseIf>),
+  While(Expr, Vec<Stmt>),
+  Break,
+  Continue,
   Return(Expr),
   Comment(String),
 }
@@ -32,19 +55,31 @@ pub enum ElseIf {
 }
 
 #[derive(Debug)]
-pub enum Expr {
-  Num(i32),
+pub struct Expr {
+  pub kind: ExprKind,
+  pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum ExprKind {
+  // literals are never negative, `-1` is a negated literal, see `Expr::negated_literal`
+  Num(u64, IntTy),
   Bool(bool),
+  Str(String),
   Ident(String),
   Binop(Opcode, Box<Expr>, Box<Expr>),
+  Unop(UnOpcode, Box<Expr>),
+  Cast(Box<Expr>, Type),
+  Call(String, Vec<Expr>),
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Type {
   Invalid, // used to denote an error in the typechecker (e.g. type of undeclared variable)
   Unit,
-  Int,
+  Int(IntTy),
   Bool,
+  Str,
   FnType(Vec<Box<Type>>, Box<Type>),
 }
 
@@ -54,16 +89,89 @@ impl Default for Type {
   }
 }
 
+impl Stmt {
+  pub fn new(kind: StmtKind, span: Span) -> Stmt {
+    Stmt { kind, span }
+  }
+}
+
+/*
+ * The key of a variable declaration in a function's variable type map.
+ *
+ * Sibling scopes may each declare a variable of the same name, with different
+ * types. The declarations of a name share one variable per type: the first type
+ * is keyed by the name, later ones by `x#1`, `x#2`, ... in source order. `#`
+ * cannot appear in an identifier, so these never clash with another variable.
+ */
+pub fn decl_key(ident: &str, n: usize) -> String {
+  if n == 0 {
+    ident.to_string()
+  } else {
+    format!("{}#{}", ident, n)
+  }
+}
+
+impl Expr {
+  pub fn new(kind: ExprKind, span: Span) -> Expr {
+    Expr { kind, span }
+  }
+
+  // a binop spans from the start of its lhs to the end of its rhs
+  pub fn binop(op: Opcode, lhs: Expr, rhs: Expr) -> Expr {
+    let span = lhs.span.to(rhs.span);
+    Expr::new(ExprKind::Binop(op, Box::new(lhs), Box::new(rhs)), span)
+  }
+
+  // op_span covers the operator, the unop spans from it to the end of the operand
+  pub fn unop(op: UnOpcode, op_span: Span, operand: Expr) -> Expr {
+    let span = op_span.to(operand.span);
+    Expr::new(ExprKind::Unop(op, Box::new(operand)), span)
+  }
+
+  /*
+   * The value of a negated literal of a signed type, e.g. `-1i8`. These are
+   * literals of their own, so that `-2147483648` fits an `int` even though
+   * `2147483648` does not, and is not an overflowing negation.
+   */
+  pub fn negated_literal(&self) -> Option<(i128, IntTy)> {
+    match &self.kind {
+      ExprKind::Unop(UnOpcode::Neg, operand) => match operand.kind {
+        ExprKind::Num(n, int_ty) if int_ty.is_signed() => Some((-(n as i128), int_ty)),
+        _ => None,
+      },
+      _ => None,
+    }
+  }
+}
+
+impl fmt::Display for Type {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Type::Invalid => write!(f, "{{invalid}}"),
+      Type::Unit => write!(f, "unit"),
+      Type::Int(int_ty) => write!(f, "{}", int_ty),
+      Type::Bool => write!(f, "bool"),
+      Type::Str => write!(f, "string"),
+      Type::FnType(arg_tys, ret_ty) => {
+        let arg_tys: Vec<String> = arg_tys.iter().map(|ty| ty.to_string()).collect();
+        write!(f, "function({}): {}", arg_tys.join(", "), ret_ty)
+      }
+    }
+  }
+}
+
 pub trait IsLiteral {
   fn is_literal(&self) -> bool;
 }
 
 impl IsLiteral for Expr {
   fn is_literal(&self) -> bool {
-    match *self {
-      Expr::Num(_) => true,
-      Expr::Bool(_) => true,
-      Expr::Ident(_) => true,
+    match self.kind {
+      ExprKind::Num(_, _) => true,
+      ExprKind::Bool(_) => true,
+      ExprKind::Str(_) => true,
+      ExprKind::Ident(_) => true,
+      ExprKind::Unop(_, _) => self.negated_literal().is_some(),
       _ => false,
     }
   }
@@ -0,0 +1,10 @@
+use crate::ast::Type;
+use kai_common::int_ty::IntTy;
+
+// functions implemented by the Kai runtime, callable from every program
+pub fn builtin_fn_types() -> Vec<(String, Type)> {
+  vec![(
+    "len".to_string(),
+    Type::FnType(vec![Box::new(Type::Str)], Box::new(Type::Int(IntTy::I32))),
+  )]
+}
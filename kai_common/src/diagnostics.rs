@@ -0,0 +1,290 @@
+use std::fmt;
+
+// syntax errors
+pub const E_SYNTAX: &str = "E0001";
+pub const E_UNMATCHED_COMMENT: &str = "E0002";
+pub const E_INVALID_LITERAL: &str = "E0003";
+pub const E_INVALID_ATTR: &str = "E0004";
+
+// type errors
+pub const E_UNDECLARED_VAR: &str = "E0100";
+pub const E_REDECLARED_VAR: &str = "E0101";
+pub const E_MISMATCHED_TYPES: &str = "E0102";
+pub const E_NON_BOOL_COND: &str = "E0103";
+pub const E_MISSING_RETURN: &str = "E0104";
+pub const E_BRANCH_TYPE_MISMATCH: &str = "E0105";
+pub const E_UNDEFINED_FN: &str = "E0106";
+pub const E_ARG_COUNT: &str = "E0107";
+pub const E_DUPLICATE_FN: &str = "E0108";
+pub const E_JUMP_OUTSIDE_LOOP: &str = "E0109";
+pub const E_UNSUPPORTED_OP: &str = "E0110";
+pub const E_INVALID_CAST: &str = "E0111";
+pub const E_LITERAL_OUT_OF_RANGE: &str = "E0112";
+
+// errors writing out the compiled program
+pub const E_EMIT: &str = "E0200";
+pub const E_LINK: &str = "E0201";
+
+// errors running a program in one of the interpreters, or found evaluating it while compiling
+pub const E_NO_MAIN: &str = "E0300";
+pub const E_DIV_BY_ZERO: &str = "E0301";
+pub const E_DIV_OVERFLOW: &str = "E0302";
+pub const E_STACK_OVERFLOW: &str = "E0303";
+pub const E_ARITH_OVERFLOW: &str = "E0304";
+
+// warnings, which do not stop compilation
+pub const W_UNREACHABLE_CODE: &str = "W0001";
+
+// byte range into the source, `lo` inclusive and `hi` exclusive
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+  pub lo: usize,
+  pub hi: usize,
+}
+
+impl Span {
+  pub fn new(lo: usize, hi: usize) -> Span {
+    Span { lo, hi }
+  }
+
+  // smallest span covering both self and other
+  pub fn to(self, other: Span) -> Span {
+    Span::new(self.lo.min(other.lo), self.hi.max(other.hi))
+  }
+
+  // the same range, moved `by` bytes further into the source
+  pub fn shift(self, by: usize) -> Span {
+    Span::new(self.lo + by, self.hi + by)
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+  Error,
+  Warning,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+  pub severity: Severity,
+  pub code: &'static str,
+  pub message: String,
+  // None for errors that are not about any place in the source, e.g. linker errors
+  pub span: Option<Span>,
+  pub notes: Vec<String>,
+}
+
+pub struct SourceFile {
+  pub name: String,
+  pub src: String,
+}
+
+/*
+ * Several source files compiled as one program. The files are laid out one
+ * after another, each followed by a newline, so spans into the combined
+ * program can be traced back to the file they came from.
+ */
+#[derive(Default)]
+pub struct SourceMap {
+  files: Vec<(usize, SourceFile)>,
+  len: usize,
+}
+
+impl Diagnostic {
+  // span is a `Span`, or an `Option<Span>` for where it may not be known
+  pub fn error(code: &'static str, message: String, span: impl Into<Option<Span>>) -> Diagnostic {
+    Diagnostic {
+      severity: Severity::Error,
+      code,
+      message,
+      span: span.into(),
+      notes: vec![],
+    }
+  }
+
+  pub fn warning(code: &'static str, message: String, span: impl Into<Option<Span>>) -> Diagnostic {
+    Diagnostic {
+      severity: Severity::Warning,
+      code,
+      message,
+      span: span.into(),
+      notes: vec![],
+    }
+  }
+
+  // an error that is not about any place in the source, which is rendered plain
+  pub fn without_span(code: &'static str, message: String) -> Diagnostic {
+    Diagnostic::error(code, message, None)
+  }
+
+  pub fn with_note(mut self, note: String) -> Diagnostic {
+    self.notes.push(note);
+    self
+  }
+
+  pub fn is_error(&self) -> bool {
+    self.severity == Severity::Error
+  }
+
+  /*
+   * Renders the diagnostic like rustc does, e.g.
+   *
+   * error[E0101]: variable `x` is already defined in this scope
+   *  --> example/basic/kai_varasgn_fail_redecl.kai:5:3
+   *   |
+   * 5 |   let x = 2;
+   *   |   ^^^^^^^^^
+   *   = note: ...
+   *
+   * spans covering several lines are underlined up to the end of their first line,
+   * and diagnostics without a span are rendered plain
+   */
+  pub fn render(&self, file: &SourceFile) -> String {
+    let span = match self.span {
+      Some(span) => span,
+      None => return self.render_plain(),
+    };
+    let (line, col) = file.line_col(span.lo);
+    let line_text = file.line_text(line);
+    let line_no = line.to_string();
+    let pad = " ".repeat(line_no.len());
+
+    // keep tabs so the carets line up with the source line
+    let indent: String = line_text
+      .chars()
+      .take(col - 1)
+      .map(|c| if c == '\t' { '\t' } else { ' ' })
+      .collect();
+    let span_hi = span.hi.min(file.line_start(line) + line_text.len());
+    let span_width = if span_hi > span.lo {
+      file.src[span.lo..span_hi].chars().count()
+    } else {
+      0
+    };
+    let carets = "^".repeat(span_width.max(1));
+
+    let mut lines = vec![
+      format!("{}[{}]: {}", self.severity, self.code, self.message),
+      format!("{}--> {}:{}:{}", pad, file.name, line, col),
+      format!("{} |", pad),
+      format!("{} | {}", line_no, line_text),
+      format!("{} | {}{}", pad, indent, carets),
+    ];
+    for note in &self.notes {
+      lines.push(format!("{} = note: {}", pad, note));
+    }
+
+    lines.join("\n")
+  }
+
+  // for diagnostics that do not point into the source, e.g. linker errors
+  pub fn render_plain(&self) -> String {
+    let mut lines = vec![self.to_string()];
+    for note in &self.notes {
+      lines.push(format!("  = note: {}", note));
+    }
+
+    lines.join("\n")
+  }
+}
+
+impl SourceFile {
+  pub fn new(name: String, src: String) -> SourceFile {
+    SourceFile { name, src }
+  }
+
+  // 1-indexed line and column (in chars) of a byte offset
+  pub fn line_col(&self, offset: usize) -> (usize, usize) {
+    let offset = offset.min(self.src.len());
+    let line = self.src[..offset].matches('\n').count() + 1;
+    let col = self.src[self.line_start(line)..offset].chars().count() + 1;
+
+    (line, col)
+  }
+
+  fn line_start(&self, line: usize) -> usize {
+    if line <= 1 {
+      return 0;
+    }
+
+    // the line starts right after the (line - 1)th newline
+    self
+      .src
+      .match_indices('\n')
+      .nth(line - 2)
+      .map_or(self.src.len(), |(i, _)| i + 1)
+  }
+
+  fn line_text(&self, line: usize) -> &str {
+    let start = self.line_start(line);
+    let end = self.src[start..]
+      .find('\n')
+      .map_or(self.src.len(), |i| start + i);
+
+    self.src[start..end].trim_end_matches('\r')
+  }
+}
+
+impl SourceMap {
+  pub fn new() -> SourceMap {
+    SourceMap::default()
+  }
+
+  // returns the offset of the file's first byte in the combined program
+  pub fn add_file(&mut self, file: SourceFile) -> usize {
+    let start = self.len;
+    self.len += file.src.len() + 1;
+    self.files.push((start, file));
+
+    start
+  }
+
+  pub fn files(&self) -> impl Iterator<Item = &SourceFile> {
+    self.files.iter().map(|(_, file)| file)
+  }
+
+  // the file a span into the combined program starts in, and its start offset
+  pub fn lookup(&self, span: Span) -> Option<(usize, &SourceFile)> {
+    self
+      .files
+      .iter()
+      .rev()
+      .find(|(start, _)| *start <= span.lo)
+      .map(|(start, file)| (*start, file))
+  }
+
+  // `file:line:col` of where a span starts, e.g. for the compiled program to report errors at
+  pub fn location(&self, span: Span) -> Option<String> {
+    self.lookup(span).map(|(start, file)| {
+      let (line, col) = file.line_col(span.lo - start);
+      format!("{}:{}:{}", file.name, line, col)
+    })
+  }
+
+  // diagnostics without a span, or not from any of the files, are rendered plain
+  pub fn render(&self, diag: &Diagnostic) -> String {
+    match diag.span.and_then(|span| Some((span, self.lookup(span)?))) {
+      Some((span, (start, file))) => {
+        let mut diag = diag.clone();
+        diag.span = Some(Span::new(span.lo - start, span.hi.max(span.lo) - start));
+        diag.render(file)
+      }
+      None => diag.render_plain(),
+    }
+  }
+}
+
+impl fmt::Display for Severity {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Severity::Error => write!(f, "error"),
+      Severity::Warning => write!(f, "warning"),
+    }
+  }
+}
+
+impl fmt::Display for Diagnostic {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}[{}]: {}", self.severity, self.code, self.message)
+  }
+}
@@ -0,0 +1,231 @@
+use crate::ops::{Opcode, UnOpcode};
+use crate::overflow_mode::OverflowMode;
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+// the integer types, `int` being `i32`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IntTy {
+  I8,
+  I16,
+  I32,
+  I64,
+  U8,
+  U16,
+  U32,
+  U64,
+}
+
+impl IntTy {
+  pub fn bits(self) -> u32 {
+    match self {
+      IntTy::I8 | IntTy::U8 => 8,
+      IntTy::I16 | IntTy::U16 => 16,
+      IntTy::I32 | IntTy::U32 => 32,
+      IntTy::I64 | IntTy::U64 => 64,
+    }
+  }
+
+  pub fn is_signed(self) -> bool {
+    match self {
+      IntTy::I8 | IntTy::I16 | IntTy::I32 | IntTy::I64 => true,
+      IntTy::U8 | IntTy::U16 | IntTy::U32 | IntTy::U64 => false,
+    }
+  }
+
+  pub fn min(self) -> i128 {
+    if self.is_signed() {
+      -(1 << (self.bits() - 1))
+    } else {
+      0
+    }
+  }
+
+  pub fn max(self) -> i128 {
+    if self.is_signed() {
+      (1 << (self.bits() - 1)) - 1
+    } else {
+      (1 << self.bits()) - 1
+    }
+  }
+
+  /*
+   * Ints of every type are kept in an i64: sign-extended for signed types,
+   * zero-extended for unsigned ones, and the bits as they are for `u64`.
+   * This truncates n to the type's bits and extends it back, so wraps any
+   * result computed on the i64 around like the compiled program would.
+   */
+  pub fn wrap(self, n: i64) -> i64 {
+    let shift = 64 - self.bits();
+    if self.is_signed() {
+      (n << shift) >> shift
+    } else {
+      self.zero_extend(n) as i64
+    }
+  }
+
+  // the bits of n as the type has them, with the ones above zeroed
+  fn zero_extend(self, n: i64) -> u64 {
+    let shift = 64 - self.bits();
+    (n as u64) << shift >> shift
+  }
+
+  // the value n stands for, which for `u64` may not fit an i64
+  fn value(self, n: i64) -> i128 {
+    if self.is_signed() {
+      n as i128
+    } else {
+      n as u64 as i128
+    }
+  }
+
+  /*
+   * Evaluates an arithmetic or bitwise op on ints of this type like the
+   * compiled program does, with overflow handled as mode says. None if the
+   * compiled program traps, i.e. on division by zero, or of the smallest int
+   * by -1, and on overflow in `OverflowMode::Trap`.
+   */
+  pub fn eval_binop(self, op: Opcode, n1: i64, n2: i64, mode: OverflowMode) -> Option<i64> {
+    // shift amounts are taken modulo the number of bits, negative ones included
+    let amount = (n2 as u32) % self.bits();
+    let res = match op {
+      Opcode::Add | Opcode::Sub | Opcode::Mul if mode != OverflowMode::Wrap => {
+        return self.eval_checked(op, n1, n2, mode)
+      }
+      Opcode::Add => n1.wrapping_add(n2),
+      Opcode::Sub => n1.wrapping_sub(n2),
+      Opcode::Mul => n1.wrapping_mul(n2),
+      Opcode::Div | Opcode::Mod if n2 == 0 => return None,
+      Opcode::Div | Opcode::Mod if self.is_signed() && n1 as i128 == self.min() && n2 == -1 => {
+        return None
+      }
+      Opcode::Div if self.is_signed() => n1 / n2,
+      Opcode::Mod if self.is_signed() => n1 % n2,
+      Opcode::Div => ((n1 as u64) / (n2 as u64)) as i64,
+      Opcode::Mod => ((n1 as u64) % (n2 as u64)) as i64,
+      Opcode::BitAnd => n1 & n2,
+      Opcode::BitOr => n1 | n2,
+      Opcode::BitXor => n1 ^ n2,
+      Opcode::Shl => n1 << amount,
+      // `>>` on unsigned ints is a logical shift, as their bits above the type's are 0
+      Opcode::Shr if self.is_signed() => n1 >> amount,
+      Opcode::Shr => ((n1 as u64) >> amount) as i64,
+      Opcode::LShr => (self.zero_extend(n1) >> amount) as i64,
+      _ => panic!("`{}` is not an arithmetic op", op),
+    };
+
+    Some(self.wrap(res))
+  }
+
+  // negation is `0 - n`, so it overflows on the smallest signed int
+  pub fn eval_unop(self, op: UnOpcode, n: i64, mode: OverflowMode) -> Option<i64> {
+    match op {
+      UnOpcode::Neg => self.eval_binop(Opcode::Sub, 0, n, mode),
+      UnOpcode::BitNot => Some(self.wrap(!n)),
+      UnOpcode::Not => panic!("`{}` is not an arithmetic op", op),
+    }
+  }
+
+  // `+`, `-` or `*` computed exactly, then trapping or saturating as mode says
+  fn eval_checked(self, op: Opcode, n1: i64, n2: i64, mode: OverflowMode) -> Option<i64> {
+    let (n1, n2) = (self.value(n1), self.value(n2));
+    // only the product of two `u64`s can be too large for an i128
+    let exact = match op {
+      Opcode::Add => n1.checked_add(n2),
+      Opcode::Sub => n1.checked_sub(n2),
+      Opcode::Mul => n1.checked_mul(n2),
+      _ => panic!("`{}` cannot overflow", op),
+    };
+
+    match exact {
+      Some(n) if self.min() <= n && n <= self.max() => Some(n as i64),
+      _ if mode == OverflowMode::Trap => None,
+      Some(n) if n < self.min() => Some(self.min() as i64),
+      _ => Some(self.max() as i64),
+    }
+  }
+
+  // evaluates a comparison, including `==` and `!=`, on ints of this type
+  pub fn compare(self, op: Opcode, n1: i64, n2: i64) -> bool {
+    let ord = if self.is_signed() {
+      n1.cmp(&n2)
+    } else {
+      (n1 as u64).cmp(&(n2 as u64))
+    };
+    match op {
+      Opcode::Lt => ord == Ordering::Less,
+      Opcode::Leq => ord != Ordering::Greater,
+      Opcode::Gt => ord == Ordering::Greater,
+      Opcode::Geq => ord != Ordering::Less,
+      Opcode::LogEq => ord == Ordering::Equal,
+      Opcode::LogNeq => ord != Ordering::Equal,
+      _ => panic!("`{}` is not a comparison", op),
+    }
+  }
+}
+
+/*
+ * Parses the text of an int literal: decimal, or hex, octal or binary with
+ * a `0x`, `0o` or `0b` prefix, with any number of `_` between the digits and
+ * an optional type suffix, e.g. `0xff_u8`. The value is only checked to fit
+ * a `u64` here, whether it fits its type is up to the typechecker.
+ */
+pub fn parse_int_literal(s: &str) -> Result<(u64, IntTy), String> {
+  let (radix, rest) = match s.get(..2) {
+    Some("0x") => (16, &s[2..]),
+    Some("0o") => (8, &s[2..]),
+    Some("0b") => (2, &s[2..]),
+    _ => (10, s),
+  };
+  // `i` and `u` are not digits in any radix
+  let split = rest.find(|c| c == 'i' || c == 'u').unwrap_or(rest.len());
+  let int_ty = if split == rest.len() {
+    IntTy::I32
+  } else {
+    rest[split..].parse()?
+  };
+  let digits: String = rest[..split].chars().filter(|c| *c != '_').collect();
+  if digits.is_empty() {
+    return Err("integer literal has no digits".to_string());
+  }
+
+  match u64::from_str_radix(&digits, radix) {
+    Ok(n) => Ok((n, int_ty)),
+    Err(_) => Err("integer literal is too large".to_string()),
+  }
+}
+
+// also accepts `int`, the name `i32` is shown as
+impl FromStr for IntTy {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<IntTy, String> {
+    match s {
+      "i8" => Ok(IntTy::I8),
+      "i16" => Ok(IntTy::I16),
+      "int" | "i32" => Ok(IntTy::I32),
+      "i64" => Ok(IntTy::I64),
+      "u8" => Ok(IntTy::U8),
+      "u16" => Ok(IntTy::U16),
+      "u32" => Ok(IntTy::U32),
+      "u64" => Ok(IntTy::U64),
+      _ => Err(format!("invalid integer type `{}`", s)),
+    }
+  }
+}
+
+impl fmt::Display for IntTy {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      IntTy::I8 => write!(f, "i8"),
+      IntTy::I16 => write!(f, "i16"),
+      IntTy::I32 => write!(f, "int"),
+      IntTy::I64 => write!(f, "i64"),
+      IntTy::U8 => write!(f, "u8"),
+      IntTy::U16 => write!(f, "u16"),
+      IntTy::U32 => write!(f, "u32"),
+      IntTy::U64 => write!(f, "u64"),
+    }
+  }
+}
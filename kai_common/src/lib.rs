@@ -1,2 +1,8 @@
+pub mod diagnostics;
+pub mod int_ty;
 pub mod ops;
+pub mod opt_level;
+pub mod overflow_mode;
 pub mod remove_comments;
+pub mod strings;
+pub mod tests;
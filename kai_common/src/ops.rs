@@ -1,6 +1,6 @@
 use std::fmt;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Opcode {
   Add, // +
   Sub, // -
@@ -8,6 +8,8 @@ pub enum Opcode {
   Div, // /
   Mod, // %
 
+  Concat, // .
+
   Lt,  // <
   Leq, // <=
   Gt,  // >
@@ -17,6 +19,20 @@ pub enum Opcode {
   LogOr,  // ||
   LogEq,  // ==
   LogNeq, // !=
+
+  BitAnd, // &
+  BitOr,  // |
+  BitXor, // ^
+  Shl,    // <<
+  Shr,    // >>, arithmetic
+  LShr,   // >>>, logical
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum UnOpcode {
+  Neg,    // -
+  Not,    // !
+  BitNot, // ~
 }
 
 pub trait IsShortCircuit {
@@ -42,15 +58,34 @@ impl fmt::Display for Opcode {
       Opcode::Div => write!(f, "/"),
       Opcode::Mod => write!(f, "%"),
 
+      Opcode::Concat => write!(f, "."),
+
       Opcode::Lt => write!(f, "<"),
-      Opcode::Leq => write!(f, "<"),
+      Opcode::Leq => write!(f, "<="),
       Opcode::Gt => write!(f, ">"),
-      Opcode::Geq => write!(f, ">"),
+      Opcode::Geq => write!(f, ">="),
 
-      Opcode::LogAnd => write!(f, "&"),
+      Opcode::LogAnd => write!(f, "&&"),
       Opcode::LogOr => write!(f, "||"),
       Opcode::LogEq => write!(f, "=="),
       Opcode::LogNeq => write!(f, "!="),
+
+      Opcode::BitAnd => write!(f, "&"),
+      Opcode::BitOr => write!(f, "|"),
+      Opcode::BitXor => write!(f, "^"),
+      Opcode::Shl => write!(f, "<<"),
+      Opcode::Shr => write!(f, ">>"),
+      Opcode::LShr => write!(f, ">>>"),
+    }
+  }
+}
+
+impl fmt::Display for UnOpcode {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      UnOpcode::Neg => write!(f, "-"),
+      UnOpcode::Not => write!(f, "!"),
+      UnOpcode::BitNot => write!(f, "~"),
     }
   }
 }
@@ -0,0 +1,41 @@
+use std::fmt;
+use std::str::FromStr;
+
+// how hard the compiler tries to optimize, as selected by `-O0` to `-O3`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OptLevel {
+  #[default]
+  O0,
+  O1,
+  O2,
+  O3,
+}
+
+// parses the level without its `-O` prefix, e.g. "2"
+impl FromStr for OptLevel {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<OptLevel, String> {
+    match s {
+      "0" => Ok(OptLevel::O0),
+      "1" => Ok(OptLevel::O1),
+      "2" => Ok(OptLevel::O2),
+      "3" => Ok(OptLevel::O3),
+      _ => Err(format!(
+        "invalid optimization level `{}`, expected 0 to 3",
+        s
+      )),
+    }
+  }
+}
+
+impl fmt::Display for OptLevel {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      OptLevel::O0 => write!(f, "-O0"),
+      OptLevel::O1 => write!(f, "-O1"),
+      OptLevel::O2 => write!(f, "-O2"),
+      OptLevel::O3 => write!(f, "-O3"),
+    }
+  }
+}
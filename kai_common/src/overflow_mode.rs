@@ -0,0 +1,44 @@
+use std::fmt;
+use std::str::FromStr;
+
+/*
+ * What `+`, `-`, `*` and unary `-` do when the result does not fit its
+ * type, as selected by `--overflow` or a function's `@overflow(mode)`.
+ * Division by zero, and of the smallest signed int by -1, always traps.
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum OverflowMode {
+  // two's complement wrap around
+  #[default]
+  Wrap,
+  // abort the program with a runtime error
+  Trap,
+  // clamp to the smallest or largest value of the type
+  Saturate,
+}
+
+impl FromStr for OverflowMode {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<OverflowMode, String> {
+    match s {
+      "wrap" => Ok(OverflowMode::Wrap),
+      "trap" => Ok(OverflowMode::Trap),
+      "saturate" => Ok(OverflowMode::Saturate),
+      _ => Err(format!(
+        "invalid overflow mode `{}`, expected wrap, trap or saturate",
+        s
+      )),
+    }
+  }
+}
+
+impl fmt::Display for OverflowMode {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      OverflowMode::Wrap => write!(f, "wrap"),
+      OverflowMode::Trap => write!(f, "trap"),
+      OverflowMode::Saturate => write!(f, "saturate"),
+    }
+  }
+}
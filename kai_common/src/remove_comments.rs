@@ -1,48 +1,90 @@
-pub fn remove_comments(prog: &String) -> Result<String, String> {
-  let mut elided_prog = "".to_string();
-  let mut unmatched_block_comment = false;
+use crate::diagnostics::{Diagnostic, Span, E_UNMATCHED_COMMENT};
 
-  let mut prog_iter = prog.chars().peekable();
-  while let Some(c0) = prog_iter.next() {
-    let c1 = prog_iter.peek();
-    if c1 == None {
-      elided_prog.push(c0);
-    }
+/*
+ * Blanks out line and block comments.
+ *
+ * Comments are replaced by spaces instead of being dropped, so byte offsets
+ * into the result (and therefore spans in the AST) are also offsets into the
+ * original program, which is what diagnostics are rendered against.
+ */
+pub fn remove_comments(prog: &String) -> Result<String, Diagnostic> {
+  let mut elided_prog = String::with_capacity(prog.len());
+
+  let mut prog_iter = prog.char_indices().peekable();
+  while let Some((i0, c0)) = prog_iter.next() {
+    let c1 = prog_iter.peek().map(|(_, c)| *c);
     match (c0, c1) {
+      ('"', _) => {
+        // copy string literals verbatim, comment markers inside them are not comments
+        elided_prog.push(c0);
+        while let Some((_, chr_in_str)) = prog_iter.next() {
+          elided_prog.push(chr_in_str);
+          match chr_in_str {
+            '\\' => {
+              if let Some((_, escaped)) = prog_iter.next() {
+                elided_prog.push(escaped);
+              }
+            }
+            '"' => break,
+            _ => {}
+          }
+        }
+      }
       ('/', Some('/')) => {
         // skip all characters until the next line
-        let mut chr_in_comment = prog_iter.next();
-        while chr_in_comment != Some('\n') && chr_in_comment != None {
-          chr_in_comment = prog_iter.next();
+        blank(&mut elided_prog, c0);
+        while let Some((_, chr_in_comment)) = prog_iter.peek() {
+          if *chr_in_comment == '\n' {
+            break;
+          }
+          blank(&mut elided_prog, *chr_in_comment);
+          prog_iter.next();
         }
       }
       ('/', Some('*')) => {
         // skip all characters until we find the end brace
-        prog_iter.next();
-        let mut chr0 = prog_iter.next();
+        blank(&mut elided_prog, c0);
         loop {
-          if chr0 == Some('*') && prog_iter.peek() == Some(&'/') {
-            prog_iter.next();
-            break;
+          match prog_iter.next() {
+            Some((_, '*')) if prog_iter.peek().map(|(_, c)| *c) == Some('/') => {
+              prog_iter.next();
+              elided_prog.push_str("  ");
+              break;
+            }
+            Some((_, chr_in_comment)) => blank(&mut elided_prog, chr_in_comment),
+            None => {
+              return Err(Diagnostic::error(
+                E_UNMATCHED_COMMENT,
+                "unterminated block comment".to_string(),
+                Span::new(i0, i0 + 2),
+              ));
+            }
           }
-          if chr0 == None {
-            unmatched_block_comment = true;
-            break;
-          }
-          chr0 = prog_iter.next();
         }
       }
       ('*', Some('/')) => {
         // unmatched end brace
-        return Err("unmatched closed comment brace".to_string());
+        return Err(Diagnostic::error(
+          E_UNMATCHED_COMMENT,
+          "unmatched closing comment brace".to_string(),
+          Span::new(i0, i0 + 2),
+        ));
       }
       _ => elided_prog.push(c0),
     }
   }
 
-  if unmatched_block_comment {
-    return Err("unmatched open comment brace".to_string());
+  Ok(elided_prog)
+}
+
+// replaces a commented out character with as many spaces as it has bytes
+fn blank(prog: &mut String, c: char) {
+  if c == '\n' {
+    prog.push('\n');
+    return;
   }
 
-  Ok(elided_prog)
+  for _ in 0..c.len_utf8() {
+    prog.push(' ');
+  }
 }
@@ -0,0 +1,30 @@
+/*
+ * Resolves escape sequences in the body of a string literal (without the
+ * surrounding quotes).
+ *
+ * Supported escapes are \n, \t, \r, \\ and \". Strings are handed to the
+ * runtime as C strings, so there is deliberately no \0.
+ */
+pub fn unescape(s: &str) -> Result<String, String> {
+  let mut unescaped = String::new();
+  let mut chars = s.chars();
+
+  while let Some(c) = chars.next() {
+    if c != '\\' {
+      unescaped.push(c);
+      continue;
+    }
+
+    match chars.next() {
+      Some('n') => unescaped.push('\n'),
+      Some('t') => unescaped.push('\t'),
+      Some('r') => unescaped.push('\r'),
+      Some('\\') => unescaped.push('\\'),
+      Some('"') => unescaped.push('"'),
+      Some(c) => return Err(format!("unknown escape sequence `\\{}`", c)),
+      None => return Err("string literal ends in a lone `\\`".to_string()),
+    }
+  }
+
+  Ok(unescaped)
+}
@@ -1,7 +1,13 @@
 use crate::remove_comments;
 
+/*
+ * comments are blanked out rather than removed, so compare the remaining
+ * tokens against the expected program, and check that byte offsets and line
+ * numbers are unchanged
+ */
+#[allow(dead_code)]
 fn test_remove_comments(prog: &str, expected_prog: &str) {
-  let res = remove_comments::remove_comments(prog.to_string());
+  let res = remove_comments::remove_comments(&prog.to_string());
   match res {
     Err(s) => panic!(
       "Expected removing comments to succeed
@@ -9,7 +15,11 @@ fn test_remove_comments(prog: &str, expected_prog: &str) {
       s,
     ),
     Ok(actual_prog) => assert!(
-      actual_prog == expected_prog,
+      actual_prog
+        .split_whitespace()
+        .eq(expected_prog.split_whitespace())
+        && actual_prog.len() == prog.len()
+        && actual_prog.lines().count() == prog.lines().count(),
       "program after removing comments does not match expected result
       original: `{:?}`
       expected: `{:?}`
@@ -21,8 +31,9 @@ fn test_remove_comments(prog: &str, expected_prog: &str) {
   }
 }
 
+#[allow(dead_code)]
 fn test_remove_comments_fail(prog: &str, expected_prog: &str) {
-  let res = remove_comments::remove_comments(prog.to_string());
+  let res = remove_comments::remove_comments(&prog.to_string());
   match res {
     Err(_) => (),
     Ok(actual_prog) => panic!(
@@ -104,3 +115,201 @@ function main(): int {
 
   test_remove_comments(prog, elided);
 }
+
+#[test]
+fn test_remove_comments_no_trailing_newline() {
+  // the last char used to be pushed twice when the input ended right after code
+  let prog = "function main(): int { return 0; }";
+  let res = remove_comments::remove_comments(&prog.to_string());
+  assert_eq!(res, Ok(prog.to_string()));
+}
+
+#[test]
+fn test_remove_comments_in_strings() {
+  let prog = "let s = \"// not a comment /* nor this */\"; // but this is";
+  let elided = "let s = \"// not a comment /* nor this */\";";
+
+  test_remove_comments(prog, elided);
+}
+
+#[test]
+fn test_remove_comments_unterminated() {
+  use crate::diagnostics::Span;
+
+  let res = remove_comments::remove_comments(&"let x = 1; /* oops".to_string());
+  assert_eq!(res.unwrap_err().span, Some(Span::new(11, 13)));
+}
+
+#[test]
+fn test_render_diagnostic() {
+  use crate::diagnostics::{Diagnostic, SourceFile, Span};
+
+  let file = SourceFile::new(
+    "main.kai".to_string(),
+    "function main(): int {\n  let x = 1;\n  let x = 2;\n}".to_string(),
+  );
+  let diag = Diagnostic::error(
+    "E0101",
+    "variable `x` is already defined in this scope".to_string(),
+    Span::new(38, 48),
+  )
+  .with_note("`x` was first declared on line 2".to_string());
+
+  let expected = "\
+error[E0101]: variable `x` is already defined in this scope
+ --> main.kai:3:3
+  |
+3 |   let x = 2;
+  |   ^^^^^^^^^^
+  = note: `x` was first declared on line 2";
+  assert_eq!(diag.render(&file), expected);
+}
+
+#[test]
+fn test_render_diagnostic_at_eof() {
+  use crate::diagnostics::{Diagnostic, SourceFile, Span};
+
+  let file = SourceFile::new("main.kai".to_string(), "function main(): int {".to_string());
+  let diag = Diagnostic::error(
+    "E0001",
+    "unexpected end of file".to_string(),
+    Span::new(22, 22),
+  );
+
+  let expected = "\
+error[E0001]: unexpected end of file
+ --> main.kai:1:23
+  |
+1 | function main(): int {
+  |                       ^";
+  assert_eq!(diag.render(&file), expected);
+}
+
+#[test]
+fn test_source_map() {
+  use crate::diagnostics::{Diagnostic, SourceFile, SourceMap, Span};
+
+  let mut source_map = SourceMap::new();
+  let a = source_map.add_file(SourceFile::new(
+    "a.kai".to_string(),
+    "function f(): int {}".to_string(),
+  ));
+  let b = source_map.add_file(SourceFile::new("b.kai".to_string(), "\nlet".to_string()));
+  assert_eq!((a, b), (0, 21));
+
+  // `let` at offset 1 of b.kai
+  let diag = Diagnostic::error(
+    "E0001",
+    "unexpected token".to_string(),
+    Span::new(1, 4).shift(b),
+  );
+  assert_eq!(
+    source_map.lookup(diag.span.unwrap()).unwrap().1.name,
+    "b.kai"
+  );
+
+  let expected = "\
+error[E0001]: unexpected token
+ --> b.kai:2:1
+  |
+2 | let
+  | ^^^";
+  assert_eq!(source_map.render(&diag), expected);
+  assert_eq!(
+    source_map.location(diag.span.unwrap()).unwrap(),
+    "b.kai:2:1"
+  );
+  assert_eq!(SourceMap::new().location(diag.span.unwrap()), None);
+}
+
+#[test]
+fn test_render_diagnostic_without_span() {
+  use crate::diagnostics::{Diagnostic, SourceFile, SourceMap};
+
+  let file = || SourceFile::new("a.kai".to_string(), "let".to_string());
+  let mut source_map = SourceMap::new();
+  source_map.add_file(file());
+
+  // rendered plain, not pointing at the start of the first file
+  let diag = Diagnostic::without_span("E0201", "linking failed".to_string())
+    .with_note("`cc` exited with 1".to_string());
+  let expected = "\
+error[E0201]: linking failed
+  = note: `cc` exited with 1";
+  assert_eq!(source_map.render(&diag), expected);
+  assert_eq!(diag.render(&file()), expected);
+}
+
+#[test]
+fn test_overflow_modes() {
+  use crate::int_ty::IntTy;
+  use crate::ops::{Opcode, UnOpcode};
+  use crate::overflow_mode::OverflowMode::*;
+
+  assert_eq!(IntTy::I8.eval_binop(Opcode::Add, 127, 1, Wrap), Some(-128));
+  assert_eq!(IntTy::I8.eval_binop(Opcode::Add, 127, 1, Trap), None);
+  assert_eq!(
+    IntTy::I8.eval_binop(Opcode::Add, 127, 1, Saturate),
+    Some(127)
+  );
+  assert_eq!(
+    IntTy::I8.eval_binop(Opcode::Mul, -100, 2, Saturate),
+    Some(-128)
+  );
+  assert_eq!(IntTy::U8.eval_binop(Opcode::Sub, 1, 2, Wrap), Some(255));
+  assert_eq!(IntTy::U8.eval_binop(Opcode::Sub, 1, 2, Saturate), Some(0));
+  assert_eq!(IntTy::I32.eval_unop(UnOpcode::Neg, -2147483648, Trap), None);
+  assert_eq!(
+    IntTy::I32.eval_unop(UnOpcode::Neg, -2147483648, Saturate),
+    Some(2147483647)
+  );
+
+  // too large for an i128, and `u64::MAX` kept as -1
+  assert_eq!(IntTy::U64.eval_binop(Opcode::Mul, -1, -1, Trap), None);
+  assert_eq!(
+    IntTy::U64.eval_binop(Opcode::Mul, -1, -1, Saturate),
+    Some(-1)
+  );
+  assert_eq!(IntTy::U64.eval_binop(Opcode::Mul, -1, -1, Wrap), Some(1));
+
+  // the mode does not change what traps on division
+  assert_eq!(IntTy::I32.eval_binop(Opcode::Div, 1, 0, Saturate), None);
+  assert_eq!(
+    IntTy::I32.eval_binop(Opcode::Div, -2147483648, -1, Wrap),
+    None
+  );
+  assert_eq!(
+    IntTy::U32.eval_binop(Opcode::Div, 4294967295, 4294967295, Trap),
+    Some(1)
+  );
+}
+
+#[test]
+fn test_parse_int_literal() {
+  use crate::int_ty::{parse_int_literal, IntTy};
+
+  assert_eq!(parse_int_literal("42"), Ok((42, IntTy::I32)));
+  assert_eq!(parse_int_literal("1_000_000"), Ok((1000000, IntTy::I32)));
+  assert_eq!(parse_int_literal("0xff_u8"), Ok((255, IntTy::U8)));
+  assert_eq!(
+    parse_int_literal("0xDEADbeef"),
+    Ok((0xdeadbeef, IntTy::I32))
+  );
+  assert_eq!(parse_int_literal("0o17i64"), Ok((15, IntTy::I64)));
+  assert_eq!(parse_int_literal("0b1010_1010u16"), Ok((170, IntTy::U16)));
+  assert_eq!(
+    parse_int_literal("18446744073709551615u64"),
+    Ok((u64::MAX, IntTy::U64))
+  );
+
+  // only whether the value fits a `u64` is checked
+  assert_eq!(parse_int_literal("300u8"), Ok((300, IntTy::U8)));
+  assert_eq!(
+    parse_int_literal("18446744073709551616"),
+    Err("integer literal is too large".to_string())
+  );
+  assert_eq!(
+    parse_int_literal("0x_"),
+    Err("integer literal has no digits".to_string())
+  );
+}
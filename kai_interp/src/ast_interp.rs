@@ -0,0 +1,205 @@
+use crate::{call_builtin, eval_binop, eval_cast, eval_unop, Value, MAX_CALL_DEPTH};
+use kai_ast::ast;
+use kai_common::diagnostics::{Diagnostic, Span, E_STACK_OVERFLOW};
+use kai_common::int_ty::IntTy;
+use kai_common::ops::{IsShortCircuit, Opcode};
+use kai_common::overflow_mode::OverflowMode;
+use std::collections::HashMap;
+
+/*
+ * Tree-walking interpreter, evaluating the statements of a typechecked
+ * program one by one.
+ */
+
+struct AstInterpCtx<'a> {
+  functions: HashMap<&'a str, &'a ast::Function>,
+  depth: usize,
+  // that of the function being run
+  overflow: OverflowMode,
+}
+
+// how executing a statement continues
+enum Flow {
+  Next,
+  Break,
+  Continue,
+  Return(Value),
+}
+
+// runs `main`, returning its result, like compiled code it must be `main(): int`
+pub fn run_main(prog: &ast::Program) -> Result<i32, Diagnostic> {
+  let is_entry_point = |func: &ast::Function| {
+    func.ident == "main" && func.args.is_empty() && func.ret_ty == ast::Type::Int(IntTy::I32)
+  };
+  if !prog.functions.iter().any(is_entry_point) {
+    return Err(crate::no_main_error());
+  }
+
+  // `main` returns an `int`
+  Ok(call_function(prog, "main", vec![])?.as_int() as i32)
+}
+
+pub fn call_function(
+  prog: &ast::Program,
+  ident: &str,
+  args: Vec<Value>,
+) -> Result<Value, Diagnostic> {
+  let mut ctx = AstInterpCtx {
+    functions: prog
+      .functions
+      .iter()
+      .map(|func| (func.ident.as_str(), func))
+      .collect(),
+    depth: 0,
+    overflow: OverflowMode::default(),
+  };
+  let span = ctx.functions[ident].span;
+
+  crate::with_interp_stack(|| ctx.call(ident, args, span))
+}
+
+impl<'a> AstInterpCtx<'a> {
+  // span is the call's, for reporting a stack overflow
+  fn call(&mut self, ident: &str, args: Vec<Value>, span: Span) -> Result<Value, Diagnostic> {
+    if let Some(res) = call_builtin(ident, &args) {
+      return Ok(res);
+    }
+
+    let func = self.functions[ident];
+    if self.depth == MAX_CALL_DEPTH {
+      return Err(
+        Diagnostic::error(E_STACK_OVERFLOW, "stack overflow".to_string(), span).with_note(format!(
+          "calls are nested more than {} deep",
+          MAX_CALL_DEPTH
+        )),
+      );
+    }
+
+    let mut vars: HashMap<String, Value> = func
+      .args
+      .iter()
+      .map(|arg| arg.ident.clone())
+      .zip(args)
+      .collect();
+
+    self.depth += 1;
+    let caller_overflow = std::mem::replace(&mut self.overflow, func.overflow.unwrap_or_default());
+    let flow = self.exec_block(&mut vars, &func.body);
+    self.overflow = caller_overflow;
+    self.depth -= 1;
+
+    match flow? {
+      Flow::Return(res) => Ok(res),
+      _ => panic!("interpreter: `{}` ended without returning", ident),
+    }
+  }
+
+  fn exec_block(
+    &mut self,
+    vars: &mut HashMap<String, Value>,
+    stmts: &[ast::Stmt],
+  ) -> Result<Flow, Diagnostic> {
+    for stmt in stmts {
+      match self.exec_stmt(vars, stmt)? {
+        Flow::Next => {}
+        flow => return Ok(flow),
+      }
+    }
+
+    Ok(Flow::Next)
+  }
+
+  fn exec_stmt(
+    &mut self,
+    vars: &mut HashMap<String, Value>,
+    stmt: &ast::Stmt,
+  ) -> Result<Flow, Diagnostic> {
+    match &stmt.kind {
+      ast::StmtKind::VarDecl(ident, e) | ast::StmtKind::VarAsgn(ident, e) => {
+        let v = self.eval_expr(vars, e)?;
+        vars.insert(ident.clone(), v);
+      }
+      ast::StmtKind::If(cond, then_block, else_if) => {
+        if self.eval_expr(vars, cond)?.as_bool() {
+          return self.exec_block(vars, then_block);
+        }
+        return self.exec_else_if(vars, else_if);
+      }
+      ast::StmtKind::While(cond, body) => {
+        while self.eval_expr(vars, cond)?.as_bool() {
+          match self.exec_block(vars, body)? {
+            Flow::Break => break,
+            Flow::Return(v) => return Ok(Flow::Return(v)),
+            Flow::Next | Flow::Continue => {}
+          }
+        }
+      }
+      ast::StmtKind::Break => return Ok(Flow::Break),
+      ast::StmtKind::Continue => return Ok(Flow::Continue),
+      ast::StmtKind::Return(e) => return Ok(Flow::Return(self.eval_expr(vars, e)?)),
+      ast::StmtKind::Comment(_) => {}
+    }
+
+    Ok(Flow::Next)
+  }
+
+  fn exec_else_if(
+    &mut self,
+    vars: &mut HashMap<String, Value>,
+    else_if: &ast::ElseIf,
+  ) -> Result<Flow, Diagnostic> {
+    match else_if {
+      ast::ElseIf::Empty => Ok(Flow::Next),
+      ast::ElseIf::ElseIf(cond, block, rest) => {
+        if self.eval_expr(vars, cond)?.as_bool() {
+          self.exec_block(vars, block)
+        } else {
+          self.exec_else_if(vars, rest)
+        }
+      }
+      ast::ElseIf::Else(block) => self.exec_block(vars, block),
+    }
+  }
+
+  fn eval_expr(
+    &mut self,
+    vars: &HashMap<String, Value>,
+    expr: &ast::Expr,
+  ) -> Result<Value, Diagnostic> {
+    match &expr.kind {
+      // the typechecker checks literals fit their type, `u64`s above `i64::MAX` keep their bits
+      ast::ExprKind::Num(n, int_ty) => Ok(Value::Int(*n as i64, *int_ty)),
+      ast::ExprKind::Bool(b) => Ok(Value::Bool(*b)),
+      ast::ExprKind::Str(s) => Ok(Value::Str(s.clone())),
+      ast::ExprKind::Ident(ident) => Ok(vars[ident].clone()),
+      ast::ExprKind::Binop(op, e1, e2) => {
+        let v1 = self.eval_expr(vars, e1)?;
+        // `&&` and `||` only evaluate their rhs if the lhs does not decide the result
+        if op.is_short_circuit() && v1.as_bool() == (*op == Opcode::LogOr) {
+          return Ok(v1);
+        }
+        let v2 = self.eval_expr(vars, e2)?;
+        eval_binop(*op, &v1, &v2, Some(expr.span), self.overflow)
+      }
+      ast::ExprKind::Unop(op, e) => {
+        // e.g. `-2147483648`, which does not overflow in `OverflowMode::Trap`
+        if let Some((n, int_ty)) = expr.negated_literal() {
+          return Ok(Value::Int(n as i64, int_ty));
+        }
+        let v = self.eval_expr(vars, e)?;
+        eval_unop(*op, &v, Some(expr.span), self.overflow)
+      }
+      ast::ExprKind::Cast(e, ty) => match ty {
+        ast::Type::Int(int_ty) => Ok(eval_cast(&self.eval_expr(vars, e)?, *int_ty)),
+        _ => panic!("interpreter: cannot cast to `{}`", ty),
+      },
+      ast::ExprKind::Call(ident, args) => {
+        let args = args
+          .iter()
+          .map(|arg| self.eval_expr(vars, arg))
+          .collect::<Result<Vec<Value>, Diagnostic>>()?;
+        self.call(ident, args, expr.span)
+      }
+    }
+  }
+}
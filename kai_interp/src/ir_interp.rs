@@ -0,0 +1,238 @@
+use crate::{call_builtin, eval_binop, eval_cast, eval_unop, Value, MAX_CALL_DEPTH};
+use kai_common::diagnostics::{Diagnostic, E_STACK_OVERFLOW};
+use kai_common::int_ty::IntTy;
+use kai_ir::ir::*;
+use std::collections::HashMap;
+
+/*
+ * Interpreter for the IR, before or after SSA construction or out-of-SSA
+ * translation, to check the lowering without going through LLVM.
+ *
+ * Runtime errors point at the op they happened in, as far as the function's
+ * `spans` know it, otherwise at no place in the source, with a note naming
+ * the function they happened in.
+ */
+
+struct IrInterpCtx<'a> {
+  functions: HashMap<&'a str, &'a IrFunction>,
+  // position of each label in its function's body
+  label_positions: &'a HashMap<&'a str, HashMap<IrLabel, usize>>,
+  depth: usize,
+}
+
+// runs `main`, returning its result, like compiled code it must be `main(): int`
+pub fn run_main(ir: &IrProgram) -> Result<i32, Diagnostic> {
+  let is_entry_point = |func: &IrFunction| {
+    func.ident == "main" && func.args.is_empty() && func.ret_ty == IrType::Int(IntTy::I32)
+  };
+  if !ir.functions.iter().any(is_entry_point) {
+    return Err(crate::no_main_error());
+  }
+
+  // `main` returns an `int`
+  Ok(call_function(ir, "main", vec![])?.as_int() as i32)
+}
+
+pub fn call_function(ir: &IrProgram, ident: &str, args: Vec<Value>) -> Result<Value, Diagnostic> {
+  let label_positions = ir
+    .functions
+    .iter()
+    .map(|func| (func.ident.as_str(), label_positions(func)))
+    .collect();
+  let mut ctx = IrInterpCtx {
+    functions: ir
+      .functions
+      .iter()
+      .map(|func| (func.ident.as_str(), func))
+      .collect(),
+    label_positions: &label_positions,
+    depth: 0,
+  };
+
+  crate::with_interp_stack(|| ctx.call(ident, args))
+}
+
+fn label_positions(func: &IrFunction) -> HashMap<IrLabel, usize> {
+  func
+    .body
+    .iter()
+    .enumerate()
+    .filter_map(|(i, cmd)| match cmd {
+      IrCmd::Label(l) => Some((*l, i)),
+      _ => None,
+    })
+    .collect()
+}
+
+impl<'a> IrInterpCtx<'a> {
+  fn call(&mut self, ident: &str, args: Vec<Value>) -> Result<Value, Diagnostic> {
+    if let Some(res) = call_builtin(ident, &args) {
+      return Ok(res);
+    }
+
+    let func = self.functions[ident];
+    if self.depth == MAX_CALL_DEPTH {
+      return Err(
+        Diagnostic::without_span(E_STACK_OVERFLOW, "stack overflow".to_string())
+          .with_note(format!(
+            "calls are nested more than {} deep",
+            MAX_CALL_DEPTH
+          ))
+          .with_note(format!("in function `{}`", ident)),
+      );
+    }
+
+    self.depth += 1;
+    let res = self.exec_function(func, args);
+    self.depth -= 1;
+
+    res.map_err(|diag| {
+      // only the innermost function an error happened in is named
+      if diag
+        .notes
+        .iter()
+        .any(|note| note.starts_with("in function"))
+      {
+        diag
+      } else {
+        diag.with_note(format!("in function `{}`", ident))
+      }
+    })
+  }
+
+  fn exec_function(&mut self, func: &IrFunction, args: Vec<Value>) -> Result<Value, Diagnostic> {
+    // through the shared map rather than self, which calls borrow mutably
+    let label_positions = self.label_positions;
+    let labels = &label_positions[func.ident.as_str()];
+    let mut vars: HashMap<IrVar, Value> = func
+      .args
+      .iter()
+      .map(|arg| arg.ident.clone())
+      .zip(args)
+      .collect();
+
+    // the block being run, and the one run before it, which phis select their value by
+    let mut current: Option<IrLabel> = None;
+    let mut pred: Option<IrLabel> = None;
+    let mut pc = 0;
+    loop {
+      let cmd = func
+        .body
+        .get(pc)
+        .unwrap_or_else(|| panic!("interpreter: `{}` ended without returning", func.ident));
+
+      match cmd {
+        IrCmd::Label(l) => {
+          pred = current;
+          current = Some(*l);
+          pc += 1;
+        }
+        IrCmd::Goto(l) => pc = labels[l],
+        IrCmd::Cond(lit, l1, l2) => {
+          let l = if self.eval_lit(&vars, lit).as_bool() {
+            l1
+          } else {
+            l2
+          };
+          pc = labels[l];
+        }
+        IrCmd::Return(lit) => return Ok(self.eval_lit(&vars, lit)),
+        IrCmd::Asgn(_, IrExpr::Phi(_)) => pc = exec_phis(&func.body, pc, pred, &mut vars),
+        // out-of-SSA translation turns phis into copies, which may copy undefined vars
+        IrCmd::Asgn(var, IrExpr::Literal(IrLiteral::Var(src))) if !vars.contains_key(src) => {
+          vars.remove(var);
+          pc += 1;
+        }
+        IrCmd::Asgn(var, e) => {
+          let v = self.eval_expr(&vars, func, var, e)?;
+          vars.insert(var.clone(), v);
+          pc += 1;
+        }
+      }
+    }
+  }
+
+  // e is the expr assigned to dest in func
+  fn eval_expr(
+    &mut self,
+    vars: &HashMap<IrVar, Value>,
+    func: &IrFunction,
+    dest: &IrVar,
+    e: &IrExpr,
+  ) -> Result<Value, Diagnostic> {
+    let span = match dest {
+      IrVar::Temp(n, _, _) => func.spans.get(n).copied(),
+      IrVar::Ident(_, _) => None,
+    };
+    match e {
+      IrExpr::Literal(lit) => Ok(self.eval_lit(vars, lit)),
+      IrExpr::Binop(op, lit1, lit2) => {
+        let v1 = self.eval_lit(vars, lit1);
+        let v2 = self.eval_lit(vars, lit2);
+        eval_binop(*op, &v1, &v2, span, func.overflow)
+      }
+      IrExpr::Unop(op, lit) => eval_unop(*op, &self.eval_lit(vars, lit), span, func.overflow),
+      IrExpr::Cast(lit, ty) => match ty {
+        IrType::Int(int_ty) => Ok(eval_cast(&self.eval_lit(vars, lit), *int_ty)),
+        _ => panic!("interpreter: cannot cast to {}", ty),
+      },
+      IrExpr::Call(ident, args) => {
+        let args = args.iter().map(|arg| self.eval_lit(vars, arg)).collect();
+        self.call(ident, args)
+      }
+      IrExpr::Phi(_) => panic!("interpreter: phis are run by `exec_phis`"),
+    }
+  }
+
+  fn eval_lit(&self, vars: &HashMap<IrVar, Value>, lit: &IrLiteral) -> Value {
+    match lit {
+      IrLiteral::Num(n, int_ty) => Value::Int(*n, *int_ty),
+      IrLiteral::Bool(b) => Value::Bool(*b),
+      IrLiteral::Str(s) => Value::Str(s.clone()),
+      IrLiteral::Var(var) => vars
+        .get(var)
+        .unwrap_or_else(|| panic!("interpreter: `{}` is used before it is defined", var))
+        .clone(),
+    }
+  }
+}
+
+/*
+ * Runs the phis starting at pc, returning the position after them. Phis read
+ * their values on entry to the block, before any of them is assigned, so all
+ * are evaluated first.
+ *
+ * The incoming value may be undefined (e.g. version 0 of a temp that is not
+ * an arg), when it is never used, so its phi leaves the dest undefined too.
+ */
+fn exec_phis(
+  body: &[IrCmd],
+  pc: usize,
+  pred: Option<IrLabel>,
+  vars: &mut HashMap<IrVar, Value>,
+) -> usize {
+  let mut assignments = vec![];
+  let mut end = pc;
+  while let Some(IrCmd::Asgn(dest, IrExpr::Phi(incoming))) = body.get(end) {
+    let (src, _) = incoming
+      .iter()
+      .find(|(_, l)| Some(*l) == pred)
+      .unwrap_or_else(|| {
+        panic!(
+          "interpreter: phi for `{}` has no value coming from {:?}",
+          dest, pred
+        )
+      });
+    assignments.push((dest, vars.get(src).cloned()));
+    end += 1;
+  }
+
+  for (dest, v) in assignments {
+    match v {
+      Some(v) => vars.insert(dest.clone(), v),
+      None => vars.remove(dest),
+    };
+  }
+
+  end
+}
@@ -0,0 +1,184 @@
+use kai_common::diagnostics::{
+  Diagnostic, Span, E_ARITH_OVERFLOW, E_DIV_BY_ZERO, E_DIV_OVERFLOW, E_NO_MAIN,
+};
+use kai_common::int_ty::IntTy;
+use kai_common::ops::{Opcode, UnOpcode};
+use kai_common::overflow_mode::OverflowMode;
+use std::fmt;
+
+pub mod ast_interp;
+pub mod ir_interp;
+pub mod tests;
+
+/*
+ * Interpreters for Kai programs, to run them without LLVM. They follow what
+ * the compiled program does: ints wrap around, trap or saturate at the size
+ * of their type as the function's `OverflowMode` says, division by zero or
+ * of the smallest signed int by -1 is an error (the native program traps),
+ * and the result of `main` is the program's result.
+ */
+
+// deeper recursion is reported as a stack overflow instead of crashing the interpreter
+pub const MAX_CALL_DEPTH: usize = 1000;
+
+const INTERP_STACK_SIZE: usize = 64 << 20;
+
+/*
+ * Interpreting a call takes far more stack than the compiled call does, so
+ * interpreters run on a thread of their own with a stack big enough for
+ * `MAX_CALL_DEPTH` calls.
+ */
+fn with_interp_stack<T: Send, F: FnOnce() -> T + Send>(f: F) -> T {
+  std::thread::scope(|scope| {
+    std::thread::Builder::new()
+      .stack_size(INTERP_STACK_SIZE)
+      .spawn_scoped(scope, f)
+      .unwrap()
+      .join()
+      .unwrap_or_else(|err| std::panic::resume_unwind(err))
+  })
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+  // kept in an i64 like `IntTy::wrap` describes
+  Int(i64, IntTy),
+  Bool(bool),
+  Str(String),
+}
+
+impl Value {
+  // the typechecker guarantees every operand has the type its operator expects
+  pub fn as_int(&self) -> i64 {
+    match self {
+      Value::Int(n, _) => *n,
+      _ => panic!("interpreter: expected an int, found `{}`", self),
+    }
+  }
+
+  pub fn as_bool(&self) -> bool {
+    match self {
+      Value::Bool(b) => *b,
+      _ => panic!("interpreter: expected a bool, found `{}`", self),
+    }
+  }
+
+  pub fn as_str(&self) -> &str {
+    match self {
+      Value::Str(s) => s,
+      _ => panic!("interpreter: expected a string, found `{}`", self),
+    }
+  }
+}
+
+/*
+ * Evaluates a binop on already evaluated operands, span is the binop's own if
+ * known, and mode the overflow mode of the function it is in. Short-circuiting
+ * ops are evaluated here too, for when both sides are known.
+ */
+pub fn eval_binop(
+  op: Opcode,
+  v1: &Value,
+  v2: &Value,
+  span: Option<Span>,
+  mode: OverflowMode,
+) -> Result<Value, Diagnostic> {
+  let res = match (op, v1, v2) {
+    (Opcode::Concat, _, _) => Value::Str(format!("{}{}", v1.as_str(), v2.as_str())),
+
+    (Opcode::LogAnd, _, _) => Value::Bool(v1.as_bool() && v2.as_bool()),
+    (Opcode::LogOr, _, _) => Value::Bool(v1.as_bool() || v2.as_bool()),
+
+    (_, Value::Int(n1, int_ty), Value::Int(n2, _)) => match op {
+      Opcode::Lt | Opcode::Leq | Opcode::Gt | Opcode::Geq | Opcode::LogEq | Opcode::LogNeq => {
+        Value::Bool(int_ty.compare(op, *n1, *n2))
+      }
+      _ => match int_ty.eval_binop(op, *n1, *n2, mode) {
+        Some(n) => Value::Int(n, *int_ty),
+        None if *n2 == 0 && matches!(op, Opcode::Div | Opcode::Mod) => {
+          return Err(Diagnostic::error(
+            E_DIV_BY_ZERO,
+            format!("attempt to calculate `{} {} 0`", v1, op),
+            span,
+          ))
+        }
+        None => {
+          let code = match op {
+            Opcode::Div | Opcode::Mod => E_DIV_OVERFLOW,
+            _ => E_ARITH_OVERFLOW,
+          };
+          return Err(Diagnostic::error(
+            code,
+            format!(
+              "attempt to calculate `{} {} {}`, which overflows",
+              v1, op, v2
+            ),
+            span,
+          ));
+        }
+      },
+    },
+
+    (Opcode::LogEq, _, _) => Value::Bool(v1 == v2),
+    (Opcode::LogNeq, _, _) => Value::Bool(v1 != v2),
+    _ => panic!("interpreter: cannot calculate `{} {} {}`", v1, op, v2),
+  };
+
+  Ok(res)
+}
+
+// like `eval_binop`, negation only fails on overflow in `OverflowMode::Trap`
+pub fn eval_unop(
+  op: UnOpcode,
+  v: &Value,
+  span: Option<Span>,
+  mode: OverflowMode,
+) -> Result<Value, Diagnostic> {
+  let res = match (op, v) {
+    (UnOpcode::Not, _) => Value::Bool(!v.as_bool()),
+    (_, Value::Int(n, int_ty)) => match int_ty.eval_unop(op, *n, mode) {
+      Some(n) => Value::Int(n, *int_ty),
+      None => {
+        return Err(Diagnostic::error(
+          E_ARITH_OVERFLOW,
+          format!("attempt to negate `{}`, which overflows", v),
+          span,
+        ))
+      }
+    },
+    _ => panic!("interpreter: cannot calculate `{}{}`", op, v),
+  };
+
+  Ok(res)
+}
+
+// converts an int to another integer type, like `as` does
+pub fn eval_cast(v: &Value, int_ty: IntTy) -> Value {
+  Value::Int(int_ty.wrap(v.as_int()), int_ty)
+}
+
+// when there is no `main(): int` to run
+fn no_main_error() -> Diagnostic {
+  Diagnostic::without_span(E_NO_MAIN, "`main` function not found".to_string())
+    .with_note("`main` takes no arguments and returns an `int`".to_string())
+}
+
+// the functions in runtime/kai_runtime.c callable from Kai, see `kai_ast::builtins`
+pub fn call_builtin(ident: &str, args: &[Value]) -> Option<Value> {
+  match ident {
+    // strlen, so the length in bytes
+    "len" => Some(Value::Int(args[0].as_str().len() as i32 as i64, IntTy::I32)),
+    _ => None,
+  }
+}
+
+impl fmt::Display for Value {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Value::Int(n, IntTy::U64) => write!(f, "{}", *n as u64),
+      Value::Int(n, _) => write!(f, "{}", n),
+      Value::Bool(b) => write!(f, "{}", b),
+      Value::Str(s) => write!(f, "{:?}", s),
+    }
+  }
+}
@@ -0,0 +1,259 @@
+#[allow(unused_imports)]
+use crate::Value;
+#[allow(unused_imports)]
+use crate::{ast_interp, ir_interp};
+#[allow(unused_imports)]
+use kai_common::diagnostics::*;
+#[allow(unused_imports)]
+use kai_common::opt_level::OptLevel;
+#[allow(unused_imports)]
+use kai_ir::pass_manager::PassManager;
+use std::fs;
+use std::path::PathBuf;
+
+#[allow(dead_code)]
+fn parse_and_typecheck(prog: &str) -> kai_ast::ast::Program {
+  let prog = kai_common::remove_comments::remove_comments(&prog.to_string()).unwrap();
+  let ast = kai_parse::parse_program(&prog).unwrap();
+  kai_typecheck::typecheck(&ast).unwrap();
+  ast
+}
+
+// the IR of prog, in SSA form if ssa is set
+#[allow(dead_code)]
+fn gen_ir(prog: &str, ssa: bool) -> kai_ir::ir::IrProgram {
+  let ast = parse_and_typecheck(prog);
+  let fn_var_ty_maps = kai_typecheck::typecheck(&ast).unwrap();
+  if ssa {
+    kai_ir::ir_gen(&ast, fn_var_ty_maps)
+  } else {
+    kai_ir::ir_gen_pre_ssa(&ast, fn_var_ty_maps)
+  }
+}
+
+// the `n` of an example's `@expect(n)`, None for `@fail` and `@ignore` examples
+#[allow(dead_code)]
+fn expected_result(prog: &str) -> Option<i32> {
+  let start = prog.find("@expect(")? + "@expect(".len();
+  let len = prog[start..].find(')')?;
+  Some(prog[start..start + len].parse().unwrap())
+}
+
+/*
+ * Calls run on every example with an `@expect(n)`, and checks that it
+ * returns n. These are the same examples tests/integration_test.rs compiles
+ * natively.
+ */
+#[allow(dead_code)]
+fn test_examples(run: fn(&str) -> Result<i32, Diagnostic>) {
+  let mut example_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+  example_dir.pop();
+  example_dir.push("example");
+
+  let mut num_examples = 0;
+  for dir in fs::read_dir(example_dir).unwrap().flatten() {
+    for entry in fs::read_dir(dir.path()).unwrap().flatten() {
+      let path = entry.path();
+      if path.extension().map_or(true, |ext| ext != "kai") {
+        continue;
+      }
+
+      let prog = fs::read_to_string(&path).unwrap();
+      let expected = match expected_result(&prog) {
+        Some(expected) if !prog.contains("@ignore") => expected,
+        _ => continue,
+      };
+      match run(&prog) {
+        Ok(res) => assert_eq!(res, expected, "wrong result for {:?}", path),
+        Err(diag) => panic!("running {:?} failed: {}", path, diag.render_plain()),
+      }
+      num_examples += 1;
+    }
+  }
+
+  assert!(num_examples > 0, "found no examples");
+}
+
+#[test]
+fn test_ast_interp_examples() {
+  test_examples(|prog| ast_interp::run_main(&parse_and_typecheck(prog)));
+}
+
+#[test]
+fn test_ir_interp_examples() {
+  test_examples(|prog| ir_interp::run_main(&gen_ir(prog, false)));
+  test_examples(|prog| ir_interp::run_main(&gen_ir(prog, true)));
+  test_examples(|prog| {
+    let mut ir = gen_ir(prog, true);
+    for ir_func in ir.functions.iter_mut() {
+      kai_ir::out_of_ssa::destruct_ssa(ir_func);
+    }
+    ir_interp::run_main(&ir)
+  });
+  // optimizing must not change what a program computes
+  test_examples(|prog| {
+    let mut ir = gen_ir(prog, true);
+    assert!(PassManager::for_opt_level(OptLevel::O1)
+      .run(&mut ir)
+      .is_empty());
+    ir_interp::run_main(&ir)
+  });
+}
+
+#[test]
+fn test_ast_interp_semantics() {
+  // `&&` and `||` do not evaluate their rhs if the lhs decides the result
+  let prog = "
+function main(): int {
+  if false && 1 / 0 == 0 {
+    return 1;
+  }
+  if true || 1 / 0 == 0 {
+    return 2;
+  }
+  return 3;
+}";
+  assert_eq!(ast_interp::run_main(&parse_and_typecheck(prog)), Ok(2));
+
+  // ints wrap around like they do natively
+  let prog = "function main(): int { return 2147483647 + 1; }";
+  assert_eq!(
+    ast_interp::run_main(&parse_and_typecheck(prog)),
+    Ok(-2147483648)
+  );
+
+  let prog = "function f(s: string): string { return s . \"!\"; }";
+  let res = ast_interp::call_function(
+    &parse_and_typecheck(prog),
+    "f",
+    vec![Value::Str("hi".to_string())],
+  );
+  assert_eq!(res, Ok(Value::Str("hi!".to_string())));
+}
+
+#[test]
+fn test_ast_interp_errors() {
+  let prog = "function main(): int { let x = 0; return 5 % x; }";
+  let diag = ast_interp::run_main(&parse_and_typecheck(prog)).unwrap_err();
+  assert_eq!(diag.code, E_DIV_BY_ZERO);
+  assert_eq!(&prog[diag.span.unwrap().lo..diag.span.unwrap().hi], "5 % x");
+
+  let prog = "function main(): int { return f(1); } function f(n: int): int { return f(n + 1); }";
+  let diag = ast_interp::run_main(&parse_and_typecheck(prog)).unwrap_err();
+  assert_eq!(diag.code, E_STACK_OVERFLOW);
+  assert_eq!(
+    &prog[diag.span.unwrap().lo..diag.span.unwrap().hi],
+    "f(n + 1)"
+  );
+
+  // `main` must be `main(): int`, like for compiled code
+  for prog in &[
+    "function f(): int { return 1; }",
+    "function main(x: int): int { return x; }",
+    "function main(x: int): bool { return true; }",
+  ] {
+    let diag = ast_interp::run_main(&parse_and_typecheck(prog)).unwrap_err();
+    assert_eq!(diag.code, E_NO_MAIN);
+    assert_eq!(diag.span, None);
+  }
+}
+
+#[test]
+fn test_ir_interp_phis() {
+  // the swap only comes out right if both phis read their values before either is assigned
+  let prog = "
+function main(): int {
+  let a = 1;
+  let b = 2;
+  let i = 0;
+  while i < 3 {
+    let t = a;
+    a = b;
+    b = t;
+    i = i + 1;
+  }
+  return a * 10 + b;
+}";
+  assert_eq!(ir_interp::run_main(&gen_ir(prog, true)), Ok(21));
+  assert_eq!(ast_interp::run_main(&parse_and_typecheck(prog)), Ok(21));
+}
+
+#[test]
+fn test_ir_interp_errors() {
+  let prog = "function main(): int { return f(0); } function f(x: int): int { return 1 / x; }";
+  let diag = ir_interp::run_main(&gen_ir(prog, true)).unwrap_err();
+  assert_eq!(diag.code, E_DIV_BY_ZERO);
+  assert_eq!(&prog[diag.span.unwrap().lo..diag.span.unwrap().hi], "1 / x");
+  assert_eq!(diag.notes, vec!["in function `f`".to_string()]);
+
+  let prog = "function main(): int { return main(); }";
+  let diag = ir_interp::run_main(&gen_ir(prog, true)).unwrap_err();
+  assert_eq!(diag.code, E_STACK_OVERFLOW);
+
+  for prog in &[
+    "function main(x: int): int { return x; }",
+    "function main(): bool { return true; }",
+  ] {
+    let diag = ir_interp::run_main(&gen_ir(prog, true)).unwrap_err();
+    assert_eq!(diag.code, E_NO_MAIN);
+  }
+}
+
+#[test]
+fn test_overflow_modes() {
+  // each function overflows as its attribute says, wrapping around without one
+  let prog = "
+@overflow(saturate)
+function sat(x: i8, y: i8): i8 {
+  return x * y - -x;
+}
+
+function wrap(x: i8): i8 {
+  return x + 100i8;
+}
+
+function main(): int {
+  return sat(100i8, 2i8) as int + wrap(100i8) as int;
+}";
+  assert_eq!(ast_interp::run_main(&parse_and_typecheck(prog)), Ok(71));
+  assert_eq!(ir_interp::run_main(&gen_ir(prog, true)), Ok(71));
+
+  let prog = "
+@overflow(trap)
+function f(x: u8, y: u8): u8 {
+  let z = x - y;
+  return z;
+}
+
+function main(): int {
+  return f(1u8, 2u8) as int;
+}";
+  let diag = ast_interp::run_main(&parse_and_typecheck(prog)).unwrap_err();
+  assert_eq!(diag.code, E_ARITH_OVERFLOW);
+  assert_eq!(
+    diag.message,
+    "attempt to calculate `1 - 2`, which overflows"
+  );
+  assert_eq!(&prog[diag.span.unwrap().lo..diag.span.unwrap().hi], "x - y");
+  // assignments to variables are traced back to the op too
+  let diag = ir_interp::run_main(&gen_ir(prog, false)).unwrap_err();
+  assert_eq!(diag.code, E_ARITH_OVERFLOW);
+  assert_eq!(&prog[diag.span.unwrap().lo..diag.span.unwrap().hi], "x - y");
+
+  // negative literals are not negations, so do not overflow
+  let prog = "@overflow(trap) function main(): int { let x = -2147483648; return x + 1; }";
+  assert_eq!(
+    ast_interp::run_main(&parse_and_typecheck(prog)),
+    Ok(-2147483647)
+  );
+  assert_eq!(ir_interp::run_main(&gen_ir(prog, true)), Ok(-2147483647));
+
+  let prog = "@overflow(trap) function main(): int { let x = -2147483647 - 1; return -x; }";
+  let diag = ast_interp::run_main(&parse_and_typecheck(prog)).unwrap_err();
+  assert_eq!(
+    diag.message,
+    "attempt to negate `-2147483648`, which overflows"
+  );
+  let diag = ir_interp::run_main(&gen_ir(prog, true)).unwrap_err();
+  assert_eq!(&prog[diag.span.unwrap().lo..diag.span.unwrap().hi], "-x");
+}
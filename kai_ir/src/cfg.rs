@@ -23,6 +23,11 @@ impl BasicBlock {
     return &self.predecessors;
   }
 
+  // [start, end) of the commands in the function body this block was built from
+  pub fn cmd_range(&self) -> (usize, usize) {
+    return self.cmd_range;
+  }
+
   pub fn cmds(&self) -> &Vec<IrCmd> {
     return &self.cmds;
   }
@@ -174,8 +179,14 @@ impl<'a> ControlFlowGraph<'a> {
     // into self.basic_blocks, and into line_bb_map.
     // we will then go to the line of each next label, build/insert the basic block there,
     // and return those basic blocks to the calling function.
-    for line_ in (line + 1)..cmds.len() {
+    //
+    // a block is registered in line_bb_index_map before its successors are built, so
+    // a back edge (e.g. the end of a loop body jumping to the loop header) finds the
+    // existing block instead of recursing forever.
+    for line_ in line..cmds.len() {
       match cmds[line_] {
+        // the label at the start of this block does not end it
+        IrCmd::Label(_) if line_ == line => continue,
         IrCmd::Label(_) => {
           let bb = self.new_bb(line, line_, cmds);
           let bb_index = self.basic_blocks.len();
@@ -234,7 +245,14 @@ impl<'a> ControlFlowGraph<'a> {
       }
     }
 
-    panic!("should never reach here");
+    // ran off the end of the function without a jump, e.g. after a loop that
+    // never exits, so the block has no successors
+    let bb = self.new_bb(line, cmds.len(), cmds);
+    let bb_index = self.basic_blocks.len();
+    self.basic_blocks.push(bb);
+    line_bb_index_map.insert(line, bb_index);
+
+    bb_index
   }
 
   // fn find_next_branch_index(cmds: Vec<IrCmd>, line: usize) -> i32 {
@@ -0,0 +1,51 @@
+use crate::ir::*;
+use crate::ir_utils::used_vars;
+use kai_common::ops::Opcode;
+use kai_common::overflow_mode::OverflowMode;
+use std::collections::HashMap;
+
+/*
+ * Dead code elimination over a function in SSA form: assignments to temps
+ * that are never read are removed, until none are left, since removing one
+ * may leave the temps it read unused too.
+ *
+ * Assignments whose expression may not finish are kept even when unused:
+ * calls, which may trap or recurse forever, divisions that may trap, and
+ * arithmetic that may overflow in `OverflowMode::Trap`.
+ */
+pub fn remove_dead_code(func: &mut IrFunction) {
+  loop {
+    // how often each var is read, not counting a phi reading its own dest around a loop
+    let mut use_counts: HashMap<IrVar, usize> = HashMap::new();
+    for cmd in &func.body {
+      for var in used_vars(cmd) {
+        if !matches!(cmd, IrCmd::Asgn(dest, _) if dest == var) {
+          *use_counts.entry(var.clone()).or_insert(0) += 1;
+        }
+      }
+    }
+
+    let len = func.body.len();
+    let overflow = func.overflow;
+    func.body.retain(|cmd| match cmd {
+      IrCmd::Asgn(var, expr) => use_counts.contains_key(var) || has_side_effects(expr, overflow),
+      _ => true,
+    });
+    if func.body.len() == len {
+      break;
+    }
+  }
+}
+
+fn has_side_effects(expr: &IrExpr, overflow: OverflowMode) -> bool {
+  match expr {
+    IrExpr::Call(_, _) => true,
+    // only dividing by zero, or the smallest int by -1, traps
+    IrExpr::Binop(Opcode::Div, _, divisor) | IrExpr::Binop(Opcode::Mod, _, divisor) => {
+      !matches!(divisor, IrLiteral::Num(n, _) if *n != 0 && *n != -1)
+    }
+    IrExpr::Binop(op, _, _) => op_may_trap(*op, overflow),
+    IrExpr::Unop(op, _) => unop_may_trap(*op, overflow),
+    IrExpr::Cast(_, _) | IrExpr::Literal(_) | IrExpr::Phi(_) => false,
+  }
+}
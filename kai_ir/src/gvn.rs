@@ -0,0 +1,223 @@
+use crate::ir::*;
+use crate::pass_manager::Analyses;
+use kai_common::ops::{Opcode, UnOpcode};
+use std::collections::{HashMap, HashSet};
+
+/*
+ * Dominator-based global value numbering over a function in SSA form, see
+ * `Value Numbering` by Briggs, Cooper and Simpson.
+ *
+ * Blocks are visited in a preorder walk of the dominator tree, with a table
+ * of the expressions computed in the blocks dominating the current one. An
+ * expression already in the table is redundant: its dest becomes a copy of
+ * the temp that first computed it, and every use of it is replaced by that
+ * temp. Copies are propagated the same way, and phis choosing between the
+ * same values as an earlier phi in their block, or between a single value,
+ * are replaced too.
+ *
+ * Redundant assignments and phis are left in place for DCE.
+ */
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Value {
+  Binop(Opcode, IrLiteral, IrLiteral),
+  Unop(UnOpcode, IrLiteral),
+  Cast(IrLiteral, IrType),
+  // the block index, and its operands ordered by label
+  Phi(usize, Vec<(IrVar, IrLabel)>),
+}
+
+struct GvnCtx {
+  args: HashSet<IrVar>,
+  dom_children: Vec<Vec<usize>>,
+  cmd_ranges: Vec<(usize, usize)>,
+  // values computed in the blocks dominating the current one
+  table: HashMap<Value, IrVar>,
+  // the value to use instead of each redundant var
+  replacements: HashMap<IrVar, IrLiteral>,
+}
+
+pub fn gvn(func: &mut IrFunction, analyses: &mut Analyses) {
+  let idoms = analyses.dominators(func).clone();
+
+  let mut dom_children = vec![vec![]; idoms.len()];
+  for (u, idom) in idoms.iter().enumerate().skip(1) {
+    dom_children[*idom].push(u);
+  }
+  let mut ctx = GvnCtx {
+    args: func.args.iter().map(|arg| arg.ident.clone()).collect(),
+    dom_children,
+    cmd_ranges: analyses
+      .cfg(func)
+      .basic_blocks()
+      .iter()
+      .map(|bb| bb.cmd_range())
+      .collect(),
+    table: HashMap::new(),
+    replacements: HashMap::new(),
+  };
+  ctx.number_block(0, &mut func.body);
+
+  // phi operands coming in along back edges are only known to be redundant now
+  for cmd in func.body.iter_mut() {
+    ctx.replace_uses(cmd);
+  }
+}
+
+impl GvnCtx {
+  fn number_block(&mut self, u: usize, body: &mut Vec<IrCmd>) {
+    let (start, end) = self.cmd_ranges[u];
+    let mut added = vec![];
+    for cmd in body[start..end].iter_mut() {
+      self.replace_uses(cmd);
+      let (dest, expr) = match cmd {
+        IrCmd::Asgn(dest, expr) => (dest.clone(), expr),
+        _ => continue,
+      };
+
+      let value = match expr {
+        IrExpr::Literal(lit @ IrLiteral::Var(_))
+        | IrExpr::Literal(lit @ IrLiteral::Num(_, _))
+        | IrExpr::Literal(lit @ IrLiteral::Bool(_)) => {
+          self.replacements.insert(dest, lit.clone());
+          continue;
+        }
+        IrExpr::Phi(incoming) => match trivial_phi_value(&dest, incoming) {
+          // version 0 of a temp that is not an arg is undefined, and may only be a phi operand
+          Some(var) if self.args.contains(&var) || !matches!(var, IrVar::Temp(_, _, 0)) => {
+            self.replacements.insert(dest, IrLiteral::Var(var));
+            continue;
+          }
+          _ => {
+            let mut incoming = incoming.clone();
+            incoming.sort_by_key(|(_, l)| l.label);
+            Value::Phi(u, incoming)
+          }
+        },
+        // each concatenation makes a new string
+        IrExpr::Binop(Opcode::Concat, _, _) => continue,
+        IrExpr::Binop(op, lit1, lit2) => normalize(*op, lit1.clone(), lit2.clone()),
+        IrExpr::Unop(op, lit) => Value::Unop(*op, lit.clone()),
+        IrExpr::Cast(lit, ty) => Value::Cast(lit.clone(), *ty),
+        // strings are not propagated, see `sccp`, and calls may have side effects
+        IrExpr::Literal(IrLiteral::Str(_)) | IrExpr::Call(_, _) => continue,
+      };
+
+      match self.lookup(&value) {
+        Some(leader) => {
+          // phis have to stay at the start of their block, so are left as they are
+          if !matches!(expr, IrExpr::Phi(_)) {
+            *expr = expr_from_var(leader.clone());
+          }
+          self.replacements.insert(dest, IrLiteral::Var(leader));
+        }
+        None => {
+          self.table.insert(value.clone(), dest);
+          added.push(value);
+        }
+      }
+    }
+
+    for v in self.dom_children[u].clone() {
+      self.number_block(v, body);
+    }
+
+    // values computed here are not available in the blocks it does not dominate
+    for value in added {
+      self.table.remove(&value);
+    }
+  }
+
+  fn lookup(&self, value: &Value) -> Option<IrVar> {
+    if let Some(var) = self.table.get(value) {
+      return Some(var.clone());
+    }
+    match value {
+      Value::Binop(op, lit1, lit2) if is_commutative(*op) => self
+        .table
+        .get(&Value::Binop(*op, lit2.clone(), lit1.clone()))
+        .cloned(),
+      _ => None,
+    }
+  }
+
+  fn replace_uses(&self, cmd: &mut IrCmd) {
+    match cmd {
+      IrCmd::Asgn(_, IrExpr::Literal(lit))
+      | IrCmd::Asgn(_, IrExpr::Unop(_, lit))
+      | IrCmd::Asgn(_, IrExpr::Cast(lit, _))
+      | IrCmd::Cond(lit, _, _)
+      | IrCmd::Return(lit) => self.replace_lit(lit),
+      IrCmd::Asgn(_, IrExpr::Binop(_, lit1, lit2)) => {
+        self.replace_lit(lit1);
+        self.replace_lit(lit2);
+      }
+      IrCmd::Asgn(_, IrExpr::Call(_, args)) => {
+        for arg in args.iter_mut() {
+          self.replace_lit(arg);
+        }
+      }
+      // phi operands have to stay vars
+      IrCmd::Asgn(_, IrExpr::Phi(incoming)) => {
+        for (var, _) in incoming.iter_mut() {
+          if let IrLiteral::Var(replacement) = self.resolve(var) {
+            *var = replacement;
+          }
+        }
+      }
+      IrCmd::Label(_) | IrCmd::Goto(_) => {}
+    }
+  }
+
+  fn replace_lit(&self, lit: &mut IrLiteral) {
+    if let IrLiteral::Var(var) = lit {
+      *lit = self.resolve(var);
+    }
+  }
+
+  // follows replacements until a var that is not replaced, or a constant
+  fn resolve(&self, var: &IrVar) -> IrLiteral {
+    let mut lit = IrLiteral::Var(var.clone());
+    while let IrLiteral::Var(var) = &lit {
+      match self.replacements.get(var) {
+        Some(replacement) => lit = replacement.clone(),
+        None => break,
+      }
+    }
+
+    lit
+  }
+}
+
+// `a > b` is `b < a`, so both are numbered the same
+fn normalize(op: Opcode, lit1: IrLiteral, lit2: IrLiteral) -> Value {
+  match op {
+    Opcode::Gt => Value::Binop(Opcode::Lt, lit2, lit1),
+    Opcode::Geq => Value::Binop(Opcode::Leq, lit2, lit1),
+    _ => Value::Binop(op, lit1, lit2),
+  }
+}
+
+fn is_commutative(op: Opcode) -> bool {
+  match op {
+    Opcode::Add | Opcode::Mul | Opcode::LogAnd | Opcode::LogOr | Opcode::LogEq | Opcode::LogNeq => {
+      true
+    }
+    Opcode::BitAnd | Opcode::BitOr | Opcode::BitXor => true,
+    _ => false,
+  }
+}
+
+// the only value a phi can choose, not counting itself around a loop
+fn trivial_phi_value(dest: &IrVar, incoming: &[(IrVar, IrLabel)]) -> Option<IrVar> {
+  let mut values = incoming
+    .iter()
+    .map(|(var, _)| var)
+    .filter(|var| *var != dest);
+  let first = values.next()?;
+  if values.all(|var| var == first) {
+    return Some(first.clone());
+  }
+
+  None
+}
@@ -1,14 +1,31 @@
 use kai_ast::ast;
-use kai_common::ops::Opcode;
+use kai_common::diagnostics::Span;
+use kai_common::int_ty::IntTy;
+use kai_common::ops::{Opcode, UnOpcode};
+use kai_common::overflow_mode::OverflowMode;
+use std::collections::HashMap;
 use std::fmt;
 use std::fmt::Debug;
 
+#[derive(Debug)]
+pub struct IrProgram {
+  pub functions: Vec<IrFunction>,
+}
+
 #[derive(Debug)]
 pub struct IrFunction {
   pub ident: String,
   pub args: Vec<IrFuncArg>,
   pub body: Vec<IrCmd>,
   pub ret_ty: IrType,
+  pub overflow: OverflowMode,
+  /*
+   * The source spans of the ops that may trap, by the number of the temp
+   * they are assigned to, for runtime errors to point at. Temps keep their
+   * number through SSA construction and every pass, so this stays valid as
+   * long as no temp is renumbered.
+   */
+  pub spans: HashMap<i32, Span>,
 }
 
 #[derive(Debug)]
@@ -31,12 +48,18 @@ pub enum IrExpr {
   Phi(Vec<(IrVar, IrLabel)>),
   Literal(IrLiteral),
   Binop(Opcode, IrLiteral, IrLiteral),
+  Unop(UnOpcode, IrLiteral),
+  // converts an int to another integer type
+  Cast(IrLiteral, IrType),
+  Call(String, Vec<IrLiteral>),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
 pub enum IrLiteral {
-  Num(i32),
+  // kept in an i64 like `IntTy::wrap` describes
+  Num(i64, IntTy),
   Bool(bool),
+  Str(String),
   Var(IrVar),
 }
 
@@ -49,8 +72,9 @@ pub enum IrVar {
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
 pub enum IrType {
-  Int,
+  Int(IntTy),
   Bool,
+  Str,  // pointer to a NUL-terminated string
   Addr, // addresses
 }
 
@@ -75,31 +99,68 @@ pub fn expr_from_lit(lit: IrLiteral) -> IrExpr {
   return IrExpr::Literal(lit);
 }
 
-pub fn gen_op_arg_type(op: Opcode) -> IrType {
+// the operands an op takes, both of which always have the same type
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum OpArgs {
+  Int,
+  Bool,
+  Str,
+  Any,
+}
+
+impl OpArgs {
+  pub fn accepts(self, ty: IrType) -> bool {
+    match self {
+      OpArgs::Int => matches!(ty, IrType::Int(_)),
+      OpArgs::Bool => ty == IrType::Bool,
+      OpArgs::Str => ty == IrType::Str,
+      OpArgs::Any => true,
+    }
+  }
+}
+
+pub fn gen_op_args(op: Opcode) -> OpArgs {
   match op {
-    Opcode::Add => IrType::Int,
-    Opcode::Sub => IrType::Int,
-    Opcode::Mul => IrType::Int,
-    Opcode::Div => IrType::Int,
-    Opcode::Mod => IrType::Int,
-    Opcode::Lt => IrType::Int,
-    Opcode::Leq => IrType::Int,
-    Opcode::Gt => IrType::Int,
-    Opcode::Geq => IrType::Int,
-    Opcode::LogAnd => IrType::Bool,
-    Opcode::LogOr => IrType::Bool,
-    Opcode::LogEq => IrType::Bool,
-    Opcode::LogNeq => IrType::Bool,
+    Opcode::Add => OpArgs::Int,
+    Opcode::Sub => OpArgs::Int,
+    Opcode::Mul => OpArgs::Int,
+    Opcode::Div => OpArgs::Int,
+    Opcode::Mod => OpArgs::Int,
+    Opcode::Concat => OpArgs::Str,
+    Opcode::Lt => OpArgs::Int,
+    Opcode::Leq => OpArgs::Int,
+    Opcode::Gt => OpArgs::Int,
+    Opcode::Geq => OpArgs::Int,
+    Opcode::LogAnd => OpArgs::Bool,
+    Opcode::LogOr => OpArgs::Bool,
+    Opcode::LogEq => OpArgs::Any,
+    Opcode::LogNeq => OpArgs::Any,
+    Opcode::BitAnd => OpArgs::Int,
+    Opcode::BitOr => OpArgs::Int,
+    Opcode::BitXor => OpArgs::Int,
+    Opcode::Shl => OpArgs::Int,
+    Opcode::Shr => OpArgs::Int,
+    Opcode::LShr => OpArgs::Int,
+  }
+}
+
+pub fn gen_unop_args(op: UnOpcode) -> OpArgs {
+  match op {
+    UnOpcode::Neg => OpArgs::Int,
+    UnOpcode::Not => OpArgs::Bool,
+    UnOpcode::BitNot => OpArgs::Int,
   }
 }
 
-pub fn gen_op_result_type(op: Opcode) -> IrType {
+// ops on ints result in an int of the same type as their operands
+pub fn gen_op_result_type(op: Opcode, arg_ty: IrType) -> IrType {
   match op {
-    Opcode::Add => IrType::Int,
-    Opcode::Sub => IrType::Int,
-    Opcode::Mul => IrType::Int,
-    Opcode::Div => IrType::Int,
-    Opcode::Mod => IrType::Int,
+    Opcode::Add => arg_ty,
+    Opcode::Sub => arg_ty,
+    Opcode::Mul => arg_ty,
+    Opcode::Div => arg_ty,
+    Opcode::Mod => arg_ty,
+    Opcode::Concat => IrType::Str,
     Opcode::Lt => IrType::Bool,
     Opcode::Leq => IrType::Bool,
     Opcode::Gt => IrType::Bool,
@@ -108,6 +169,47 @@ pub fn gen_op_result_type(op: Opcode) -> IrType {
     Opcode::LogOr => IrType::Bool,
     Opcode::LogEq => IrType::Bool,
     Opcode::LogNeq => IrType::Bool,
+    Opcode::BitAnd => arg_ty,
+    Opcode::BitOr => arg_ty,
+    Opcode::BitXor => arg_ty,
+    Opcode::Shl => arg_ty,
+    Opcode::Shr => arg_ty,
+    Opcode::LShr => arg_ty,
+  }
+}
+
+// whether an op may abort the program, depending on its operands
+pub fn op_may_trap(op: Opcode, overflow: OverflowMode) -> bool {
+  match op {
+    Opcode::Div | Opcode::Mod => true,
+    Opcode::Add | Opcode::Sub | Opcode::Mul => overflow == OverflowMode::Trap,
+    _ => false,
+  }
+}
+
+pub fn unop_may_trap(op: UnOpcode, overflow: OverflowMode) -> bool {
+  op == UnOpcode::Neg && overflow == OverflowMode::Trap
+}
+
+pub fn var_ty(var: &IrVar) -> IrType {
+  match var {
+    IrVar::Ident(_, ty) | IrVar::Temp(_, ty, _) => *ty,
+  }
+}
+
+pub fn lit_ty(lit: &IrLiteral) -> IrType {
+  match lit {
+    IrLiteral::Num(_, int_ty) => IrType::Int(*int_ty),
+    IrLiteral::Bool(_) => IrType::Bool,
+    IrLiteral::Str(_) => IrType::Str,
+    IrLiteral::Var(var) => var_ty(var),
+  }
+}
+
+impl fmt::Display for IrProgram {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let fn_tokens: Vec<String> = self.functions.iter().map(|func| func.to_string()).collect();
+    return write!(f, "{}", fn_tokens.join("\n\n"));
   }
 }
 
@@ -121,11 +223,15 @@ impl fmt::Display for IrFunction {
     for i in 0..self.body.len() {
       stmt_tokens.push(format!("{}: {}", i, self.body[i].to_string()));
     }
+    // the default is left out, so most functions print the same as they are written
+    if self.overflow != OverflowMode::Wrap {
+      write!(f, "@overflow({}) ", self.overflow)?;
+    }
     return write!(
       f,
       "{}({}): {}\n{}",
       (*self).ident.to_string(),
-      arg_tokens.join("\n"),
+      arg_tokens.join(", "),
       (*self).ret_ty,
       stmt_tokens.join("\n"),
     );
@@ -155,7 +261,22 @@ impl fmt::Display for IrExpr {
     match self {
       IrExpr::Literal(l) => write!(f, "{}", l),
       IrExpr::Binop(o, l, r) => write!(f, "{} {} {}", l, o, r),
-      IrExpr::Phi(phis) => write!(f, "phi({:?})", phis),
+      // a keyword rather than `-`, which would read like a negative literal
+      IrExpr::Unop(UnOpcode::Neg, l) => write!(f, "neg {}", l),
+      IrExpr::Unop(UnOpcode::Not, l) => write!(f, "not {}", l),
+      IrExpr::Unop(UnOpcode::BitNot, l) => write!(f, "bitnot {}", l),
+      IrExpr::Cast(l, ty) => write!(f, "{} as {}", l, ty),
+      IrExpr::Phi(phis) => {
+        let phi_tokens: Vec<String> = phis
+          .iter()
+          .map(|(var, label)| format!("[{}, {}]", var, label))
+          .collect();
+        write!(f, "phi({})", phi_tokens.join(", "))
+      }
+      IrExpr::Call(ident, args) => {
+        let arg_tokens: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        write!(f, "call {}({})", ident, arg_tokens.join(", "))
+      }
     }
   }
 }
@@ -163,8 +284,12 @@ impl fmt::Display for IrExpr {
 impl fmt::Display for IrLiteral {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     match self {
-      IrLiteral::Num(n) => write!(f, "{}", n),
+      // ints other than `int` have their type as a suffix, like `255u8`
+      IrLiteral::Num(n, IntTy::I32) => write!(f, "{}", n),
+      IrLiteral::Num(n, IntTy::U64) => write!(f, "{}{}", *n as u64, IntTy::U64),
+      IrLiteral::Num(n, int_ty) => write!(f, "{}{}", n, int_ty),
       IrLiteral::Bool(b) => write!(f, "{}", b),
+      IrLiteral::Str(s) => write!(f, "{:?}", s),
       IrLiteral::Var(v) => write!(f, "{}", v),
     }
   }
@@ -173,7 +298,8 @@ impl fmt::Display for IrLiteral {
 impl fmt::Display for IrVar {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     match self {
-      IrVar::Ident(ident, ty) => write!(f, "{}({})", ident, ty),
+      // `$` keeps idents apart from temps, e.g. an ident named `t0_1`
+      IrVar::Ident(ident, ty) => write!(f, "${}({})", ident, ty),
       IrVar::Temp(n, ty, v) => write!(f, "t{}_{}({})", n, v, ty),
     }
   }
@@ -182,8 +308,9 @@ impl fmt::Display for IrVar {
 impl fmt::Display for IrType {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     match self {
-      IrType::Int => write!(f, "int"),
+      IrType::Int(int_ty) => write!(f, "{}", int_ty),
       IrType::Bool => write!(f, "bool"),
+      IrType::Str => write!(f, "string"),
       IrType::Addr => write!(f, "addr"),
     }
   }
@@ -194,3 +321,14 @@ impl fmt::Display for IrLabel {
     write!(f, ".L{}", self.label)
   }
 }
+
+impl fmt::Display for OpArgs {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      OpArgs::Int => write!(f, "integer"),
+      OpArgs::Bool => write!(f, "bool"),
+      OpArgs::Str => write!(f, "string"),
+      OpArgs::Any => write!(f, "any"),
+    }
+  }
+}
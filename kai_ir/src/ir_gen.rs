@@ -2,6 +2,7 @@ use super::IrGenContext;
 use crate::ir::*;
 use kai_ast::ast;
 use kai_ast::ast::IsLiteral;
+use kai_common::diagnostics::Span;
 use kai_common::ops::{IsShortCircuit, Opcode};
 
 struct BlkGenResult {
@@ -11,6 +12,9 @@ struct BlkGenResult {
 
 impl IrGenContext {
   pub fn gen_ir_function(&mut self, ast_func: &ast::Function) -> IrFunction {
+    for arg in &ast_func.args {
+      self.var_keys.insert(arg.ident.clone(), arg.ident.clone());
+    }
     IrFunction {
       ident: ast_func.ident.clone(),
       args: self.gen_ir_funcargs(&ast_func.args),
@@ -18,13 +22,16 @@ impl IrGenContext {
         .gen_ir_body(&ast_func.body, self.gen_ir_type(&ast_func.ret_ty))
         .cmds,
       ret_ty: self.gen_ir_type(&ast_func.ret_ty),
+      overflow: self.overflow,
+      spans: std::mem::take(&mut self.spans),
     }
   }
 
   fn gen_ir_type(&self, ty: &ast::Type) -> IrType {
     match *ty {
-      ast::Type::Int => IrType::Int,
+      ast::Type::Int(int_ty) => IrType::Int(int_ty),
       ast::Type::Bool => IrType::Bool,
+      ast::Type::Str => IrType::Str,
       _ => panic!("cannot translate fn ast type to ir type"),
     }
   }
@@ -49,34 +56,42 @@ impl IrGenContext {
     let mut cmds = vec![];
     let mut did_return = false;
     for stmt in stmts {
-      match stmt {
-        ast::Stmt::VarDecl(ident, expr) => {
-          let decl_cmds = self.gen_ir_expr_and_asgn(
-            IrVar::Ident(
-              ident.clone(),
-              self.gen_ir_type(self.var_ty_map.get(ident).unwrap()),
-            ),
-            &expr,
-          );
+      // the rest of the block is unreachable, and would not end in a jump
+      if did_return {
+        break;
+      }
+      match &stmt.kind {
+        ast::StmtKind::VarDecl(ident, expr) => {
+          // expr cannot refer to ident, it is not in scope before the declaration
+          self.declare_var(ident, self.gen_ir_expr_type(expr));
+          let decl_cmds = self.gen_ir_expr_and_asgn(self.gen_ir_var(ident), &expr);
           cmds.extend(decl_cmds);
         }
-        ast::Stmt::VarAsgn(ident, expr) => {
-          let asgn_cmds = self.gen_ir_expr_and_asgn(
-            IrVar::Ident(
-              ident.clone(),
-              self.gen_ir_type(self.var_ty_map.get(ident).unwrap()),
-            ),
-            &expr,
-          );
+        ast::StmtKind::VarAsgn(ident, expr) => {
+          let asgn_cmds = self.gen_ir_expr_and_asgn(self.gen_ir_var(ident), &expr);
           cmds.extend(asgn_cmds);
         }
-        ast::Stmt::If(cond, blk, else_if) => {
+        ast::StmtKind::If(cond, blk, else_if) => {
           let if_result = self.gen_ir_if(cond, blk, else_if, ret_ty);
           cmds.extend(if_result.cmds);
 
           did_return = did_return || if_result.did_return;
         }
-        ast::Stmt::Return(expr) => {
+        ast::StmtKind::While(cond, blk) => {
+          let while_result = self.gen_ir_while(cond, blk, ret_ty);
+          cmds.extend(while_result.cmds);
+        }
+        ast::StmtKind::Break => {
+          let (_, exit_label) = *self.loop_labels.last().unwrap();
+          cmds.push(IrCmd::Goto(exit_label));
+          did_return = true;
+        }
+        ast::StmtKind::Continue => {
+          let (header_label, _) = *self.loop_labels.last().unwrap();
+          cmds.push(IrCmd::Goto(header_label));
+          did_return = true;
+        }
+        ast::StmtKind::Return(expr) => {
           // !("STATEMENTS: {:?}, RETURNSSSSS", stmts);
           if expr.is_literal() {
             cmds.push(IrCmd::Return(self.gen_ir_expr_for_lit(expr)));
@@ -89,7 +104,7 @@ impl IrGenContext {
           cmds.extend(ret_cmds);
           did_return = true;
         }
-        ast::Stmt::Comment(_) => {}
+        ast::StmtKind::Comment(_) => {}
       }
     }
 
@@ -188,81 +203,171 @@ impl IrGenContext {
     BlkGenResult { did_return, cmds }
   }
 
+  /*
+   * translate while loop into list of commands w/ branches
+   *
+   *   goto header
+   * header:
+   *   <cond>
+   *   branch cond: body, exit
+   * body:
+   *   <body>
+   *   goto header
+   * exit:
+   */
+  fn gen_ir_while(
+    &mut self,
+    cond: &ast::Expr,
+    body_stmts: &Vec<ast::Stmt>,
+    ret_ty: IrType,
+  ) -> BlkGenResult {
+    let cond_target = self.get_tmp_and_incr(IrType::Bool);
+    let header_label = self.get_label_and_incr();
+    let body_label = self.get_label_and_incr();
+    let exit_label = self.get_label_and_incr();
+
+    let mut cmds = vec![IrCmd::Goto(header_label), IrCmd::Label(header_label)];
+    cmds.extend(self.gen_ir_expr_and_asgn(cond_target.clone(), cond));
+    cmds.push(IrCmd::Cond(
+      lit_from_var(cond_target.clone()),
+      body_label,
+      exit_label,
+    ));
+
+    cmds.push(IrCmd::Label(body_label));
+    self.loop_labels.push((header_label, exit_label));
+    let body_res = self.gen_ir_body(body_stmts, ret_ty);
+    self.loop_labels.pop();
+    cmds.extend(body_res.cmds);
+    if !body_res.did_return {
+      cmds.push(IrCmd::Goto(header_label));
+    }
+    cmds.push(IrCmd::Label(exit_label));
+
+    // the body may never run, so the loop as a whole never returns
+    BlkGenResult {
+      did_return: false,
+      cmds,
+    }
+  }
+
   // translate expression into list of commands, and assign it to target
   fn gen_ir_expr_and_asgn(&mut self, target: IrVar, expr: &ast::Expr) -> Vec<IrCmd> {
-    match expr {
-      ast::Expr::Num(n) => vec![IrCmd::Asgn(target, IrExpr::Literal(IrLiteral::Num(*n)))],
-      ast::Expr::Bool(b) => vec![IrCmd::Asgn(target, IrExpr::Literal(IrLiteral::Bool(*b)))],
-      ast::Expr::Ident(ident) => vec![IrCmd::Asgn(
+    match &expr.kind {
+      ast::ExprKind::Num(_, _) => vec![IrCmd::Asgn(
+        target,
+        IrExpr::Literal(self.gen_ir_expr_for_lit(expr)),
+      )],
+      ast::ExprKind::Unop(_, _) if expr.is_literal() => vec![IrCmd::Asgn(
         target,
-        IrExpr::Literal(IrLiteral::Var(IrVar::Ident(
-          ident.clone(),
-          self.gen_ir_type(self.var_ty_map.get(ident).unwrap()),
-        ))),
+        IrExpr::Literal(self.gen_ir_expr_for_lit(expr)),
       )],
-      ast::Expr::Binop(op, e1_box, e2_box) => {
+      ast::ExprKind::Bool(b) => vec![IrCmd::Asgn(target, IrExpr::Literal(IrLiteral::Bool(*b)))],
+      ast::ExprKind::Str(s) => vec![IrCmd::Asgn(
+        target,
+        IrExpr::Literal(IrLiteral::Str(s.clone())),
+      )],
+      ast::ExprKind::Ident(ident) => vec![IrCmd::Asgn(
+        target,
+        IrExpr::Literal(IrLiteral::Var(self.gen_ir_var(ident))),
+      )],
+      ast::ExprKind::Binop(op, e1_box, e2_box) => {
         if op.is_short_circuit() {
           return self.gen_ir_expr_for_short_circuit(target, *op, e1_box, e2_box);
         }
-        match (&**e1_box, &**e2_box) {
-          (ast::Expr::Binop(op1, _, _), ast::Expr::Binop(op2, _, _)) => {
-            // need to generate tmps for both
-            let target1 = self.get_tmp_and_incr(gen_op_arg_type(*op1));
-            let target2 = self.get_tmp_and_incr(gen_op_arg_type(*op2));
-            let mut cmds = self.gen_ir_expr_and_asgn(target1.clone(), e1_box);
-            cmds.extend(self.gen_ir_expr_and_asgn(target2.clone(), e2_box));
-            cmds.push(IrCmd::Asgn(
-              target,
-              IrExpr::Binop(
-                *op,
-                lit_from_var(target1.clone()),
-                lit_from_var(target2.clone()),
-              ),
-            ));
-            cmds
-          }
-          (ast::Expr::Binop(op1, _, _), e2) => {
-            // only needs temps for left side
-            let target1 = self.get_tmp_and_incr(gen_op_arg_type(*op1));
-            let mut cmds = self.gen_ir_expr_and_asgn(target1.clone(), &*e1_box);
-            cmds.push(IrCmd::Asgn(
-              target,
-              IrExpr::Binop(
-                *op,
-                IrLiteral::Var(target1.clone()),
-                self.gen_ir_expr_for_lit(&e2),
-              ),
-            ));
-            cmds
-          }
-          (e1, ast::Expr::Binop(op2, _, _)) => {
-            // only needs temps for left side
-            let target1 = self.get_tmp_and_incr(gen_op_arg_type(*op2));
-            let mut cmds = self.gen_ir_expr_and_asgn(target1.clone(), &*e2_box);
-            cmds.push(IrCmd::Asgn(
-              target,
-              IrExpr::Binop(
-                *op,
-                IrLiteral::Var(target1.clone()),
-                self.gen_ir_expr_for_lit(&e1),
-              ),
-            ));
-            cmds
-          }
-          // should both be literal values
-          (e1, e2) => vec![IrCmd::Asgn(
-            target,
-            IrExpr::Binop(
-              *op,
-              self.gen_ir_expr_for_lit(&e1),
-              self.gen_ir_expr_for_lit(&e2),
-            ),
-          )],
+        let mut cmds = vec![];
+        let lit1 = self.gen_ir_operand(e1_box, &mut cmds);
+        let lit2 = self.gen_ir_operand(e2_box, &mut cmds);
+        let binop = IrExpr::Binop(*op, lit1, lit2);
+        if op_may_trap(*op, self.overflow) {
+          self.gen_ir_trapping_asgn(target, binop, expr.span, &mut cmds);
+        } else {
+          cmds.push(IrCmd::Asgn(target, binop));
         }
+        cmds
+      }
+      ast::ExprKind::Unop(op, e_box) => {
+        let mut cmds = vec![];
+        let lit = self.gen_ir_operand(e_box, &mut cmds);
+        let unop = IrExpr::Unop(*op, lit);
+        if unop_may_trap(*op, self.overflow) {
+          self.gen_ir_trapping_asgn(target, unop, expr.span, &mut cmds);
+        } else {
+          cmds.push(IrCmd::Asgn(target, unop));
+        }
+        cmds
+      }
+      ast::ExprKind::Cast(e_box, ty) => {
+        let mut cmds = vec![];
+        let lit = self.gen_ir_operand(e_box, &mut cmds);
+        cmds.push(IrCmd::Asgn(target, IrExpr::Cast(lit, self.gen_ir_type(ty))));
+        cmds
+      }
+      ast::ExprKind::Call(ident, args) => {
+        let mut cmds = vec![];
+        let mut arg_lits = vec![];
+        for arg in args {
+          arg_lits.push(self.gen_ir_operand(arg, &mut cmds));
+        }
+        cmds.push(IrCmd::Asgn(target, IrExpr::Call(ident.clone(), arg_lits)));
+        cmds
+      }
+    }
+  }
+
+  /*
+   * Assigns an expr that may trap to target, through a temp of its own if
+   * target is a var, so that its span can be found by the temp's number.
+   */
+  fn gen_ir_trapping_asgn(
+    &mut self,
+    target: IrVar,
+    expr: IrExpr,
+    span: Span,
+    cmds: &mut Vec<IrCmd>,
+  ) {
+    match target {
+      IrVar::Temp(n, _, _) => {
+        self.spans.insert(n, span);
+        cmds.push(IrCmd::Asgn(target, expr));
+      }
+      IrVar::Ident(_, ty) => {
+        let tmp = self.get_tmp_and_incr(ty);
+        self.gen_ir_trapping_asgn(tmp.clone(), expr, span, cmds);
+        cmds.push(IrCmd::Asgn(target, expr_from_var(tmp)));
       }
     }
   }
 
+  /*
+   * translate an operand of a compound expression into a literal.
+   * literals are used as-is, everything else is evaluated into a fresh temp,
+   * with the commands to do so appended to cmds
+   */
+  fn gen_ir_operand(&mut self, expr: &ast::Expr, cmds: &mut Vec<IrCmd>) -> IrLiteral {
+    if expr.is_literal() {
+      return self.gen_ir_expr_for_lit(expr);
+    }
+
+    let target = self.get_tmp_and_incr(self.gen_ir_expr_type(expr));
+    cmds.extend(self.gen_ir_expr_and_asgn(target.clone(), expr));
+    lit_from_var(target)
+  }
+
+  fn gen_ir_expr_type(&self, expr: &ast::Expr) -> IrType {
+    match &expr.kind {
+      ast::ExprKind::Num(_, int_ty) => IrType::Int(*int_ty),
+      ast::ExprKind::Bool(_) => IrType::Bool,
+      ast::ExprKind::Str(_) => IrType::Str,
+      ast::ExprKind::Ident(ident) => var_ty(&self.gen_ir_var(ident)),
+      ast::ExprKind::Binop(op, e1, _) => gen_op_result_type(*op, self.gen_ir_expr_type(e1)),
+      // every unop results in the type of its operand
+      ast::ExprKind::Unop(_, e) => self.gen_ir_expr_type(e),
+      ast::ExprKind::Cast(_, ty) => self.gen_ir_type(ty),
+      ast::ExprKind::Call(ident, _) => self.gen_ir_type(self.fn_ret_ty_map.get(ident).unwrap()),
+    }
+  }
+
   fn gen_ir_expr_for_short_circuit(
     &mut self,
     target: IrVar,
@@ -328,17 +433,36 @@ impl IrGenContext {
       *expr,
     );
 
-    match expr {
-      ast::Expr::Num(n) => IrLiteral::Num(*n),
-      ast::Expr::Bool(b) => IrLiteral::Bool(*b),
-      ast::Expr::Ident(ident) => IrLiteral::Var(IrVar::Ident(
-        ident.clone(),
-        self.gen_ir_type(self.var_ty_map.get(ident).unwrap()),
-      )),
+    match &expr.kind {
+      // the typechecker checks literals fit their type, `u64`s above `i64::MAX` keep their bits
+      ast::ExprKind::Num(n, int_ty) => IrLiteral::Num(*n as i64, *int_ty),
+      ast::ExprKind::Unop(_, _) => {
+        let (n, int_ty) = expr.negated_literal().unwrap();
+        IrLiteral::Num(n as i64, int_ty)
+      }
+      ast::ExprKind::Bool(b) => IrLiteral::Bool(*b),
+      ast::ExprKind::Str(s) => IrLiteral::Str(s.clone()),
+      ast::ExprKind::Ident(ident) => IrLiteral::Var(self.gen_ir_var(ident)),
       _ => panic!("impossible case ```gen_ir_expr_for_lit```, should have caught in assert"),
     }
   }
 
+  // points ident at the declaration of its name with type ty, see `ast::decl_key`
+  fn declare_var(&mut self, ident: &str, ty: IrType) {
+    let mut n = 0;
+    while self.gen_ir_type(&self.var_ty_map[&ast::decl_key(ident, n)]) != ty {
+      n += 1;
+    }
+    let key = ast::decl_key(ident, n);
+    self.var_keys.insert(ident.to_string(), key);
+  }
+
+  // the var ident refers to in the current scope
+  fn gen_ir_var(&self, ident: &str) -> IrVar {
+    let key = &self.var_keys[ident];
+    IrVar::Ident(key.clone(), self.gen_ir_type(&self.var_ty_map[key]))
+  }
+
   fn get_tmp_and_incr(&mut self, ty: IrType) -> IrVar {
     let tmp = IrVar::Temp(self.tmp_count, ty, 0);
     self.tmp_count += 1;
@@ -0,0 +1,504 @@
+use crate::ir::*;
+use kai_common::diagnostics::{Diagnostic, Span, E_SYNTAX};
+use kai_common::int_ty::IntTy;
+use kai_common::ops::{Opcode, UnOpcode};
+use kai_common::overflow_mode::OverflowMode;
+use std::collections::HashMap;
+
+/*
+ * Parser for the textual IR, as printed by the `Display` impls in ir.rs.
+ * Printing a parsed program gives back the text it was parsed from.
+ *
+ * program  := function { blank-line { blank-line } function }
+ * function := header { '\n' cmd-line }
+ * header   := [ '@overflow' '(' mode ')' ] IDENT '(' [ arg { ',' arg } ] ')' ':' type
+ * arg      := var ':' type
+ * cmd-line := [ NUM ':' ] cmd
+ * cmd      := label | 'goto' label | 'branch' lit ':' label ',' label
+ *           | 'ret' lit | var '=' expr
+ * expr     := 'phi' '(' [ incoming { ',' incoming } ] ')'
+ *           | 'call' IDENT '(' [ lit { ',' lit } ] ')'
+ *           | unop lit | lit 'as' type | lit [ op lit ]
+ * unop     := 'neg' | 'not' | 'bitnot'
+ * incoming := '[' var ',' label ']'
+ * lit      := [ '-' ] NUM [ int-type ] | 'true' | 'false' | STRING | var
+ * var      := 't' NUM '_' NUM '(' type ')'    (temp number, SSA version)
+ *           | '$' IDENT '(' type ')'
+ * label    := '.L' NUM
+ * type     := int-type | 'bool' | 'string' | 'addr'
+ * int-type := 'int' | 'i8' | 'i16' | 'i32' | 'i64' | 'u8' | 'u16' | 'u32' | 'u64'
+ * mode     := 'wrap' | 'trap' | 'saturate'
+ *
+ * The numbers in front of cmds are only there to be read by people, and are
+ * not checked. Strings are quoted and escaped like Rust's `{:?}` does.
+ */
+
+pub fn parse_ir_program(src: &str) -> Result<IrProgram, Diagnostic> {
+  let mut functions = vec![];
+  for lines in split_functions(src) {
+    functions.push(parse_function_lines(src, &lines)?);
+  }
+
+  Ok(IrProgram { functions })
+}
+
+// src has to hold exactly one function
+pub fn parse_ir_function(src: &str) -> Result<IrFunction, Diagnostic> {
+  let mut program = parse_ir_program(src)?;
+  match program.functions.len() {
+    1 => Ok(program.functions.remove(0)),
+    n => Err(Diagnostic::error(
+      E_SYNTAX,
+      format!("expected exactly one function, found {}", n),
+      Span::new(0, src.len()),
+    )),
+  }
+}
+
+// byte ranges of the lines of each function, which are separated by blank lines
+fn split_functions(src: &str) -> Vec<Vec<Span>> {
+  let mut functions = vec![];
+  let mut lines = vec![];
+  let mut start = 0;
+  for line in src.split('\n') {
+    let span = Span::new(start, start + line.len());
+    start = span.hi + 1;
+
+    if line.trim().is_empty() {
+      if !lines.is_empty() {
+        functions.push(std::mem::take(&mut lines));
+      }
+    } else {
+      lines.push(span);
+    }
+  }
+  if !lines.is_empty() {
+    functions.push(lines);
+  }
+
+  functions
+}
+
+fn parse_function_lines(src: &str, lines: &[Span]) -> Result<IrFunction, Diagnostic> {
+  let mut header = Cursor::new(src, lines[0]);
+  let mut overflow = OverflowMode::Wrap;
+  if header.eat("@overflow") {
+    header.expect("(")?;
+    overflow = header.overflow_mode()?;
+    header.expect(")")?;
+  }
+  let ident = header.ident()?.to_string();
+  header.expect("(")?;
+  let mut args = vec![];
+  if !header.eat(")") {
+    loop {
+      let var = header.var()?;
+      header.expect(":")?;
+      let ty = header.ty()?;
+      args.push(IrFuncArg { ty, ident: var });
+      if header.eat(")") {
+        break;
+      }
+      header.expect(",")?;
+    }
+  }
+  header.expect(":")?;
+  let ret_ty = header.ty()?;
+  header.end()?;
+
+  let mut body = vec![];
+  for line in &lines[1..] {
+    let mut cursor = Cursor::new(src, *line);
+    if cursor.peek().map_or(false, |c| c.is_ascii_digit()) {
+      cursor.digits()?;
+      cursor.expect(":")?;
+    }
+    body.push(cursor.cmd()?);
+    cursor.end()?;
+  }
+
+  // the IR has no source to point into
+  Ok(IrFunction {
+    ident,
+    args,
+    body,
+    ret_ty,
+    overflow,
+    spans: HashMap::new(),
+  })
+}
+
+// longer ops first, so that e.g. `<=` is not read as `<`
+const OPS: [(&str, Opcode); 20] = [
+  (">>>", Opcode::LShr),
+  ("<<", Opcode::Shl),
+  (">>", Opcode::Shr),
+  ("<=", Opcode::Leq),
+  (">=", Opcode::Geq),
+  ("&&", Opcode::LogAnd),
+  ("||", Opcode::LogOr),
+  ("==", Opcode::LogEq),
+  ("!=", Opcode::LogNeq),
+  ("+", Opcode::Add),
+  ("-", Opcode::Sub),
+  ("*", Opcode::Mul),
+  ("/", Opcode::Div),
+  ("%", Opcode::Mod),
+  (".", Opcode::Concat),
+  ("<", Opcode::Lt),
+  (">", Opcode::Gt),
+  ("&", Opcode::BitAnd),
+  ("|", Opcode::BitOr),
+  ("^", Opcode::BitXor),
+];
+
+// reads a single line, `pos` and `end` are offsets into the whole source
+struct Cursor<'a> {
+  src: &'a str,
+  pos: usize,
+  end: usize,
+}
+
+impl<'a> Cursor<'a> {
+  fn new(src: &'a str, line: Span) -> Cursor<'a> {
+    Cursor {
+      src,
+      pos: line.lo,
+      end: line.hi,
+    }
+  }
+
+  fn rest(&self) -> &'a str {
+    &self.src[self.pos..self.end]
+  }
+
+  fn skip_whitespace(&mut self) {
+    let rest = self.rest();
+    self.pos += rest.len() - rest.trim_start().len();
+  }
+
+  fn peek(&mut self) -> Option<char> {
+    self.skip_whitespace();
+    self.rest().chars().next()
+  }
+
+  fn eat(&mut self, token: &str) -> bool {
+    self.skip_whitespace();
+    if self.rest().starts_with(token) {
+      self.pos += token.len();
+      true
+    } else {
+      false
+    }
+  }
+
+  // like eat, but only if the keyword is not the start of a longer word
+  fn eat_keyword(&mut self, keyword: &str) -> bool {
+    self.skip_whitespace();
+    match self.rest().strip_prefix(keyword) {
+      Some(after) if !after.starts_with(is_ident_char) => self.eat(keyword),
+      _ => false,
+    }
+  }
+
+  fn expect(&mut self, token: &str) -> Result<(), Diagnostic> {
+    if self.eat(token) {
+      Ok(())
+    } else {
+      Err(self.error(&format!("`{}`", token)))
+    }
+  }
+
+  fn end(&mut self) -> Result<(), Diagnostic> {
+    match self.peek() {
+      None => Ok(()),
+      Some(_) => Err(self.error("end of line")),
+    }
+  }
+
+  // reports that `expected` is missing at the current position
+  fn error(&mut self, expected: &str) -> Diagnostic {
+    self.skip_whitespace();
+    let token_len = self
+      .rest()
+      .find(char::is_whitespace)
+      .unwrap_or(self.rest().len());
+    let span = Span::new(self.pos, self.pos + token_len);
+    let found = if token_len == 0 {
+      "end of line".to_string()
+    } else {
+      format!("`{}`", &self.src[span.lo..span.hi])
+    };
+
+    Diagnostic::error(
+      E_SYNTAX,
+      format!("expected {}, found {}", expected, found),
+      span,
+    )
+  }
+
+  fn ident(&mut self) -> Result<&'a str, Diagnostic> {
+    self.skip_whitespace();
+    let rest = self.rest();
+    let len = rest.find(|c| !is_ident_char(c)).unwrap_or(rest.len());
+    if len == 0 || rest.starts_with(|c: char| c.is_ascii_digit()) {
+      return Err(self.error("an identifier"));
+    }
+    self.pos += len;
+
+    Ok(&rest[..len])
+  }
+
+  fn digits(&mut self) -> Result<&'a str, Diagnostic> {
+    self.skip_whitespace();
+    let rest = self.rest();
+    let len = rest
+      .find(|c: char| !c.is_ascii_digit())
+      .unwrap_or(rest.len());
+    if len == 0 {
+      return Err(self.error("a number"));
+    }
+    self.pos += len;
+
+    Ok(&rest[..len])
+  }
+
+  // the digits of a label or temp, which are always small enough
+  fn index<T: std::str::FromStr>(&mut self) -> Result<T, Diagnostic> {
+    let start = self.pos;
+    let digits = self.digits()?;
+    digits.parse().map_err(|_| {
+      self.pos = start;
+      self.error("a smaller number")
+    })
+  }
+
+  fn ty(&mut self) -> Result<IrType, Diagnostic> {
+    let start = self.pos;
+    match self.ident() {
+      Ok(ident) if ident.parse::<IntTy>().is_ok() => Ok(IrType::Int(ident.parse().unwrap())),
+      Ok("bool") => Ok(IrType::Bool),
+      Ok("string") => Ok(IrType::Str),
+      Ok("addr") => Ok(IrType::Addr),
+      _ => {
+        self.pos = start;
+        Err(self.error("a type"))
+      }
+    }
+  }
+
+  fn overflow_mode(&mut self) -> Result<OverflowMode, Diagnostic> {
+    let start = self.pos;
+    match self.ident().map(str::parse) {
+      Ok(Ok(mode)) => Ok(mode),
+      _ => {
+        self.pos = start;
+        Err(self.error("an overflow mode"))
+      }
+    }
+  }
+
+  fn label(&mut self) -> Result<IrLabel, Diagnostic> {
+    self.expect(".L")?;
+    Ok(IrLabel {
+      label: self.index()?,
+    })
+  }
+
+  fn var(&mut self) -> Result<IrVar, Diagnostic> {
+    let var = if self.eat("$") {
+      let ident = self.ident()?.to_string();
+      self.expect("(")?;
+      IrVar::Ident(ident, self.ty()?)
+    } else if self.peek() == Some('t') {
+      self.pos += 1;
+      let n = self.index()?;
+      self.expect("_")?;
+      let version = self.index()?;
+      self.expect("(")?;
+      IrVar::Temp(n, self.ty()?, version)
+    } else {
+      return Err(self.error("a variable"));
+    };
+    self.expect(")")?;
+
+    Ok(var)
+  }
+
+  fn lit(&mut self) -> Result<IrLiteral, Diagnostic> {
+    match self.peek() {
+      Some('"') => self.string().map(IrLiteral::Str),
+      Some(c) if c == '-' || c.is_ascii_digit() => {
+        let start = self.pos;
+        let negative = self.eat("-");
+        let digits = self.digits()?;
+        // a suffix right after the digits gives the type, see `IrLiteral`'s `Display`
+        let int_ty = if self.rest().starts_with(is_ident_char) {
+          self
+            .ident()?
+            .parse()
+            .map_err(|_| self.error("an integer type"))?
+        } else {
+          IntTy::I32
+        };
+        let n = format!("{}{}", if negative { "-" } else { "" }, digits).parse::<i128>();
+        match n {
+          // u64s above i64::MAX are kept as their bits
+          Ok(n) if int_ty.min() <= n && n <= int_ty.max() => Ok(IrLiteral::Num(n as i64, int_ty)),
+          _ => {
+            self.pos = start;
+            Err(self.error(&format!("an int that fits in {}", int_ty)))
+          }
+        }
+      }
+      _ if self.eat_keyword("true") => Ok(IrLiteral::Bool(true)),
+      _ if self.eat_keyword("false") => Ok(IrLiteral::Bool(false)),
+      _ => self.var().map(IrLiteral::Var),
+    }
+  }
+
+  // the inverse of `{:?}` on a String
+  fn string(&mut self) -> Result<String, Diagnostic> {
+    let start = self.pos;
+    self.expect("\"")?;
+    let mut s = String::new();
+    let mut chars = self.rest().char_indices();
+    while let Some((i, c)) = chars.next() {
+      let escaped = match c {
+        '"' => {
+          self.pos += i + 1;
+          return Ok(s);
+        }
+        '\\' => chars.next().map(|(_, c)| c),
+        c => {
+          s.push(c);
+          continue;
+        }
+      };
+      let unescaped = match escaped {
+        Some('n') => Some('\n'),
+        Some('t') => Some('\t'),
+        Some('r') => Some('\r'),
+        Some('0') => Some('\0'),
+        Some(c @ '\\') | Some(c @ '"') | Some(c @ '\'') => Some(c),
+        Some('u') => {
+          let hex: String = chars
+            .by_ref()
+            .map(|(_, c)| c)
+            .take_while(|c| *c != '}')
+            .collect();
+          hex
+            .strip_prefix('{')
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .and_then(std::char::from_u32)
+        }
+        _ => None,
+      };
+      match unescaped {
+        Some(c) => s.push(c),
+        None => {
+          return Err(Diagnostic::error(
+            E_SYNTAX,
+            "invalid escape in string".to_string(),
+            Span::new(self.pos + i, self.pos + i + 2),
+          ))
+        }
+      }
+    }
+
+    self.pos = start;
+    Err(Diagnostic::error(
+      E_SYNTAX,
+      "unterminated string".to_string(),
+      Span::new(start, self.end),
+    ))
+  }
+
+  fn cmd(&mut self) -> Result<IrCmd, Diagnostic> {
+    if self.peek() == Some('.') {
+      return self.label().map(IrCmd::Label);
+    }
+    if self.eat_keyword("goto") {
+      return self.label().map(IrCmd::Goto);
+    }
+    if self.eat_keyword("branch") {
+      let cond = self.lit()?;
+      self.expect(":")?;
+      let l1 = self.label()?;
+      self.expect(",")?;
+      let l2 = self.label()?;
+      return Ok(IrCmd::Cond(cond, l1, l2));
+    }
+    if self.eat_keyword("ret") {
+      return self.lit().map(IrCmd::Return);
+    }
+
+    let var = self.var()?;
+    self.expect("=")?;
+    Ok(IrCmd::Asgn(var, self.expr()?))
+  }
+
+  fn expr(&mut self) -> Result<IrExpr, Diagnostic> {
+    if self.eat_keyword("phi") {
+      self.expect("(")?;
+      let mut incoming = vec![];
+      if !self.eat(")") {
+        loop {
+          self.expect("[")?;
+          let var = self.var()?;
+          self.expect(",")?;
+          incoming.push((var, self.label()?));
+          self.expect("]")?;
+          if self.eat(")") {
+            break;
+          }
+          self.expect(",")?;
+        }
+      }
+      return Ok(IrExpr::Phi(incoming));
+    }
+
+    if self.eat_keyword("call") {
+      let ident = self.ident()?.to_string();
+      self.expect("(")?;
+      let mut args = vec![];
+      if !self.eat(")") {
+        loop {
+          args.push(self.lit()?);
+          if self.eat(")") {
+            break;
+          }
+          self.expect(",")?;
+        }
+      }
+      return Ok(IrExpr::Call(ident, args));
+    }
+
+    if self.eat_keyword("neg") {
+      return Ok(IrExpr::Unop(UnOpcode::Neg, self.lit()?));
+    }
+    if self.eat_keyword("not") {
+      return Ok(IrExpr::Unop(UnOpcode::Not, self.lit()?));
+    }
+    if self.eat_keyword("bitnot") {
+      return Ok(IrExpr::Unop(UnOpcode::BitNot, self.lit()?));
+    }
+
+    let lhs = self.lit()?;
+    if self.peek().is_none() {
+      return Ok(IrExpr::Literal(lhs));
+    }
+    if self.eat_keyword("as") {
+      return Ok(IrExpr::Cast(lhs, self.ty()?));
+    }
+    let op = match OPS.iter().find(|(token, _)| self.eat(token)) {
+      Some((_, op)) => *op,
+      None => return Err(self.error("an operator")),
+    };
+    Ok(IrExpr::Binop(op, lhs, self.lit()?))
+  }
+}
+
+fn is_ident_char(c: char) -> bool {
+  c.is_ascii_alphanumeric() || c == '_'
+}
@@ -25,6 +25,8 @@ impl IrTempConversionContext {
       args: self.convert_vars_to_temps_args(ir.args),
       body: self.convert_vars_to_temps_body(ir.body),
       ret_ty: ir.ret_ty,
+      overflow: ir.overflow,
+      spans: ir.spans,
     }
   }
 
@@ -91,7 +93,16 @@ impl IrTempConversionContext {
         self.convert_vars_to_temps_lit(lit1),
         self.convert_vars_to_temps_lit(lit2),
       ),
+      IrExpr::Unop(op, lit) => IrExpr::Unop(op, self.convert_vars_to_temps_lit(lit)),
+      IrExpr::Cast(lit, ty) => IrExpr::Cast(self.convert_vars_to_temps_lit(lit), ty),
       IrExpr::Phi(phis) => IrExpr::Phi(phis),
+      IrExpr::Call(ident, args) => IrExpr::Call(
+        ident,
+        args
+          .into_iter()
+          .map(|arg| self.convert_vars_to_temps_lit(arg))
+          .collect(),
+      ),
     }
   }
 
@@ -109,6 +120,13 @@ pub fn get_tmp_to_type_map(ir: &IrFunction) -> HashMap<i32, IrType> {
   let mut tmp_to_ty_map = HashMap::new();
   let cmds = &ir.body;
 
+  // args are never assigned in the body, but still need phis if they are reassigned
+  for arg in &ir.args {
+    if let IrVar::Temp(var, ty, _) = arg.ident {
+      tmp_to_ty_map.insert(var, ty);
+    }
+  }
+
   for cmd in cmds {
     match cmd {
       IrCmd::Asgn(IrVar::Temp(var, ty, _), _) => {
@@ -123,3 +141,26 @@ pub fn get_tmp_to_type_map(ir: &IrFunction) -> HashMap<i32, IrType> {
 
   tmp_to_ty_map
 }
+
+// every var cmd reads, phi operands included
+pub fn used_vars(cmd: &IrCmd) -> Vec<&IrVar> {
+  let lits: Vec<&IrLiteral> = match cmd {
+    IrCmd::Asgn(_, IrExpr::Literal(lit))
+    | IrCmd::Asgn(_, IrExpr::Unop(_, lit))
+    | IrCmd::Asgn(_, IrExpr::Cast(lit, _))
+    | IrCmd::Cond(lit, _, _)
+    | IrCmd::Return(lit) => vec![lit],
+    IrCmd::Asgn(_, IrExpr::Binop(_, lit1, lit2)) => vec![lit1, lit2],
+    IrCmd::Asgn(_, IrExpr::Call(_, args)) => args.iter().collect(),
+    IrCmd::Asgn(_, IrExpr::Phi(incoming)) => return incoming.iter().map(|(var, _)| var).collect(),
+    IrCmd::Label(_) | IrCmd::Goto(_) => vec![],
+  };
+
+  lits
+    .into_iter()
+    .filter_map(|lit| match lit {
+      IrLiteral::Var(var) => Some(var),
+      _ => None,
+    })
+    .collect()
+}
@@ -1,16 +1,36 @@
 use kai_ast::ast;
+use kai_common::diagnostics::Span;
+use kai_common::overflow_mode::OverflowMode;
 use std::collections::HashMap;
 
 pub mod cfg;
+pub mod dce;
+pub mod gvn;
 pub mod ir;
 pub mod ir_gen;
+pub mod ir_parse;
 pub mod ir_utils;
+pub mod liveness;
+pub mod out_of_ssa;
+pub mod pass_manager;
+pub mod sccp;
+pub mod simplify_cfg;
 pub mod ssa;
+pub mod tests;
+pub mod verify;
 
 struct IrGenContext {
   tmp_count: i32,
   label_count: i32,
   var_ty_map: HashMap<String, ast::Type>,
+  // the `ast::decl_key` of the declaration each var in scope refers to
+  var_keys: HashMap<String, String>,
+  fn_ret_ty_map: HashMap<String, ast::Type>,
+  // (header, exit) labels of the loops enclosing the statement being lowered
+  loop_labels: Vec<(ir::IrLabel, ir::IrLabel)>,
+  overflow: OverflowMode,
+  // see `IrFunction::spans`
+  spans: HashMap<i32, Span>,
 }
 
 struct IrTempConversionContext {
@@ -18,28 +38,94 @@ struct IrTempConversionContext {
   min_available_tmp: i32,
 }
 
-pub fn ir_gen(ast_func: &ast::Function, var_ty_map: HashMap<String, ast::Type>) -> ir::IrFunction {
-  let mut ir_context = IrGenContext {
-    tmp_count: 0,
-    label_count: 0,
-    var_ty_map,
-  };
-  let ir = ir_context.gen_ir_function(ast_func);
-  // println!("{}", ir);
+pub fn ir_gen(
+  ast_prog: &ast::Program,
+  fn_var_ty_maps: HashMap<String, HashMap<String, ast::Type>>,
+) -> ir::IrProgram {
+  ir_gen_program(ast_prog, fn_var_ty_maps, true)
+}
+
+// the IR as it is right before SSA construction, with every var already a temp
+pub fn ir_gen_pre_ssa(
+  ast_prog: &ast::Program,
+  fn_var_ty_maps: HashMap<String, HashMap<String, ast::Type>>,
+) -> ir::IrProgram {
+  ir_gen_program(ast_prog, fn_var_ty_maps, false)
+}
+
+fn ir_gen_program(
+  ast_prog: &ast::Program,
+  fn_var_ty_maps: HashMap<String, HashMap<String, ast::Type>>,
+  ssa: bool,
+) -> ir::IrProgram {
+  let mut fn_var_ty_maps = fn_var_ty_maps;
+  let mut fn_ret_ty_map = HashMap::new();
+  for (ident, fn_ty) in kai_ast::builtins::builtin_fn_types() {
+    if let ast::Type::FnType(_, ret_ty) = fn_ty {
+      fn_ret_ty_map.insert(ident, *ret_ty);
+    }
+  }
+  for ast_func in &ast_prog.functions {
+    fn_ret_ty_map.insert(ast_func.ident.clone(), ast_func.ret_ty.clone());
+  }
+
+  let mut functions = vec![];
+  for ast_func in &ast_prog.functions {
+    let var_ty_map = fn_var_ty_maps.remove(&ast_func.ident).unwrap();
+    let ir_func = if ssa {
+      ir_gen_function(ast_func, var_ty_map, &fn_ret_ty_map)
+    } else {
+      ir_gen_function_pre_ssa(ast_func, var_ty_map, &fn_ret_ty_map).0
+    };
+    functions.push(ir_func);
+  }
+
+  let ir = ir::IrProgram { functions };
+  // the functions have been checked one by one, this also checks the calls between them
+  verify::debug_verify_program(&ir, ssa, "ir_gen");
+  ir
+}
+
+pub fn ir_gen_function(
+  ast_func: &ast::Function,
+  var_ty_map: HashMap<String, ast::Type>,
+  fn_ret_ty_map: &HashMap<String, ast::Type>,
+) -> ir::IrFunction {
+  let (mut ir_temps, tmp_count) = ir_gen_function_pre_ssa(ast_func, var_ty_map, fn_ret_ty_map);
   let mut cfg = cfg::ControlFlowGraph {
     basic_blocks: &mut vec![],
   };
-  let (mut ir_temps, tmp_count) = convert_vars_to_temps(ir);
-  // cfg.build_cfg(&ir_temps);
-  // println!("{}", cfg);
-  // println!("ir_temps: {}", ir_temps);
+  cfg.build_cfg(&ir_temps);
 
-  // let tmp_ty_map = ir_utils::get_tmp_to_type_map(&ir_temps);
-  // ssa::gen_ssa(&mut ir_temps, &mut cfg, &tmp_ty_map, tmp_count as usize);
+  let tmp_ty_map = ir_utils::get_tmp_to_type_map(&ir_temps);
+  ssa::gen_ssa(&mut ir_temps, &mut cfg, &tmp_ty_map, tmp_count as usize);
+  verify::debug_verify_function(&ir_temps, true, "ssa::gen_ssa");
 
   ir_temps
 }
 
+// also returns the number of temps used
+fn ir_gen_function_pre_ssa(
+  ast_func: &ast::Function,
+  var_ty_map: HashMap<String, ast::Type>,
+  fn_ret_ty_map: &HashMap<String, ast::Type>,
+) -> (ir::IrFunction, i32) {
+  let mut ir_context = IrGenContext {
+    tmp_count: 0,
+    label_count: 0,
+    var_ty_map,
+    var_keys: HashMap::new(),
+    fn_ret_ty_map: fn_ret_ty_map.clone(),
+    loop_labels: vec![],
+    overflow: ast_func.overflow.unwrap_or_default(),
+    spans: HashMap::new(),
+  };
+  let ir = ir_context.gen_ir_function(ast_func);
+  let (ir, tmp_count) = convert_vars_to_temps(ir);
+  verify::debug_verify_function(&ir, false, "ir_gen");
+  (ir, tmp_count)
+}
+
 pub fn convert_vars_to_temps(ir: ir::IrFunction) -> (ir::IrFunction, i32) {
   let mut tmp_conversion_ctx = IrTempConversionContext {
     ident_tmp_map: HashMap::new(),
@@ -0,0 +1,81 @@
+use crate::cfg::ControlFlowGraph;
+use crate::ir::*;
+use crate::ir_utils::used_vars;
+use std::collections::{HashMap, HashSet};
+
+/*
+ * The vars live on entry to and exit from each basic block of a function in
+ * SSA form, indexed like the blocks of its CFG.
+ *
+ * A phi operand is only live out of the predecessor it comes from, not into
+ * the phi's block, and phi dests are defined on entry to their block.
+ */
+pub struct Liveness {
+  pub live_in: Vec<HashSet<IrVar>>,
+  pub live_out: Vec<HashSet<IrVar>>,
+}
+
+pub fn liveness(cfg: &ControlFlowGraph) -> Liveness {
+  let n = cfg.basic_blocks().len();
+  let label_to_bb: HashMap<IrLabel, usize> = (0..n)
+    .filter_map(|u| match cfg.at(u).cmds().first() {
+      Some(IrCmd::Label(l)) => Some((*l, u)),
+      _ => None,
+    })
+    .collect();
+
+  // vars each block reads before defining them, and the vars it defines
+  let mut uses = vec![HashSet::new(); n];
+  let mut defs = vec![HashSet::new(); n];
+  // operands of the phis in each block's successors that come from it
+  let mut phi_uses = vec![HashSet::new(); n];
+  for u in 0..n {
+    for cmd in cfg.at(u).cmds() {
+      match cmd {
+        IrCmd::Asgn(_, IrExpr::Phi(incoming)) => {
+          for (var, l) in incoming {
+            if let Some(pred) = label_to_bb.get(l) {
+              phi_uses[*pred].insert(var.clone());
+            }
+          }
+        }
+        _ => {
+          for var in used_vars(cmd) {
+            if !defs[u].contains(var) {
+              uses[u].insert(var.clone());
+            }
+          }
+        }
+      }
+      if let IrCmd::Asgn(var, _) = cmd {
+        defs[u].insert(var.clone());
+      }
+    }
+  }
+
+  let mut live_in: Vec<HashSet<IrVar>> = uses.clone();
+  let mut live_out: Vec<HashSet<IrVar>> = phi_uses;
+  // blocks are visited successors first, so most of them are only updated once outside loops
+  let postorder = cfg.postorder();
+  let mut changed = true;
+  while changed {
+    changed = false;
+    for u in &postorder {
+      let u = *u;
+      let mut out = live_out[u].clone();
+      for v in cfg.at(u).ancestors() {
+        out.extend(live_in[*v].iter().cloned());
+      }
+      let mut in_ = uses[u].clone();
+      in_.extend(out.difference(&defs[u]).cloned());
+
+      if out.len() != live_out[u].len() || in_.len() != live_in[u].len() {
+        live_out[u] = out;
+        live_in[u] = in_;
+        changed = true;
+      }
+    }
+  }
+
+  Liveness { live_in, live_out }
+}
@@ -0,0 +1,279 @@
+use crate::ir::*;
+use std::collections::{HashMap, HashSet};
+
+/*
+ * Translation out of SSA form, for backends without phi nodes.
+ *
+ * Each phi becomes a copy at the end of every predecessor of its block. Two
+ * things make that less simple than it sounds:
+ * - lost copy: if the predecessor has other successors, the copy would also
+ *   run on the paths that do not go to the phi's block. Such critical edges
+ *   are split first, so the copies get a block of their own.
+ * - swap: the phis of a block all read their arguments before any of them is
+ *   written, e.g. `a = phi(b), b = phi(a)`, so the copies of one edge are a
+ *   parallel copy, which is sequentialized using a fresh temp to break cycles.
+ */
+
+struct Block {
+  label: Option<IrLabel>,
+  // (dest, incoming values)
+  phis: Vec<(IrVar, Vec<(IrVar, IrLabel)>)>,
+  // everything else, ending in the block's jump or return
+  cmds: Vec<IrCmd>,
+}
+
+impl Block {
+  fn successors(&self) -> Vec<IrLabel> {
+    match self.cmds.last() {
+      Some(IrCmd::Goto(l)) => vec![*l],
+      Some(IrCmd::Cond(_, l1, l2)) => vec![*l1, *l2],
+      _ => vec![],
+    }
+  }
+
+  fn retarget(&mut self, from: IrLabel, to: IrLabel) {
+    let swap = |l: &mut IrLabel| {
+      if *l == from {
+        *l = to;
+      }
+    };
+    match self.cmds.last_mut() {
+      Some(IrCmd::Goto(l)) => swap(l),
+      Some(IrCmd::Cond(_, l1, l2)) => {
+        swap(l1);
+        swap(l2);
+      }
+      _ => {}
+    }
+  }
+
+  // copies go right before the block's jump
+  fn insert_before_jump(&mut self, copies: Vec<IrCmd>) {
+    let at = self.cmds.len() - 1;
+    self.cmds.splice(at..at, copies);
+  }
+}
+
+// removes every phi from an SSA function, see above
+pub fn destruct_ssa(ir: &mut IrFunction) {
+  let mut blocks = split_blocks(&ir.body);
+  let mut next_label = max_label(&ir.body) + 1;
+  let mut next_tmp = max_tmp(ir) + 1;
+  let args: HashSet<IrVar> = ir.args.iter().map(|arg| arg.ident.clone()).collect();
+
+  split_critical_edges(&mut blocks, &mut next_label);
+
+  let label_to_block: HashMap<IrLabel, usize> = blocks
+    .iter()
+    .enumerate()
+    .filter_map(|(i, block)| block.label.map(|l| (l, i)))
+    .collect();
+
+  for u in 0..blocks.len() {
+    let phis = std::mem::take(&mut blocks[u].phis);
+
+    // group the phis by the edge their arguments flow along
+    let mut edge_copies: HashMap<IrLabel, Vec<(IrVar, IrVar)>> = HashMap::new();
+    for (dest, incoming) in &phis {
+      for (src, pred) in incoming {
+        // version 0 of anything but an arg is never assigned, there is nothing to copy
+        if is_undefined(src, &args) {
+          continue;
+        }
+        edge_copies
+          .entry(*pred)
+          .or_default()
+          .push((dest.clone(), src.clone()));
+      }
+    }
+
+    let mut preds: Vec<IrLabel> = edge_copies.keys().cloned().collect();
+    preds.sort_by_key(|l| l.label);
+    for pred in preds {
+      let copies = sequentialize(edge_copies.remove(&pred).unwrap(), &mut next_tmp);
+      let pred_index = *label_to_block
+        .get(&pred)
+        .expect("out_of_ssa: phi refers to a label that does not exist");
+      blocks[pred_index].insert_before_jump(copies);
+    }
+  }
+
+  ir.body = join_blocks(blocks);
+  crate::verify::debug_verify_function(ir, false, "out_of_ssa::destruct_ssa");
+}
+
+fn is_undefined(var: &IrVar, args: &HashSet<IrVar>) -> bool {
+  match var {
+    IrVar::Temp(_, _, 0) => !args.contains(var),
+    _ => false,
+  }
+}
+
+fn split_blocks(body: &Vec<IrCmd>) -> Vec<Block> {
+  let mut blocks: Vec<Block> = vec![];
+  for cmd in body {
+    match cmd {
+      IrCmd::Label(l) => {
+        // make falling through into a label explicit
+        if let Some(prev) = blocks.last_mut() {
+          if prev.successors().is_empty() && !matches!(prev.cmds.last(), Some(IrCmd::Return(_))) {
+            prev.cmds.push(IrCmd::Goto(*l));
+          }
+        }
+        blocks.push(Block {
+          label: Some(*l),
+          phis: vec![],
+          cmds: vec![],
+        });
+      }
+      _ => {
+        if blocks.is_empty() {
+          blocks.push(Block {
+            label: None,
+            phis: vec![],
+            cmds: vec![],
+          });
+        }
+        let block = blocks.last_mut().unwrap();
+        match cmd {
+          IrCmd::Asgn(dest, IrExpr::Phi(incoming)) => {
+            block.phis.push((dest.clone(), incoming.clone()));
+          }
+          _ => block.cmds.push(cmd.clone()),
+        }
+      }
+    }
+  }
+
+  blocks
+}
+
+/*
+ * An edge is critical if it leaves a block with several successors and enters
+ * a block with several predecessors. Only those entering a block with phis
+ * need splitting, since nothing else is going to be inserted on an edge.
+ */
+fn split_critical_edges(blocks: &mut Vec<Block>, next_label: &mut i32) {
+  let mut pred_counts: HashMap<IrLabel, usize> = HashMap::new();
+  for block in blocks.iter() {
+    for succ in block.successors() {
+      *pred_counts.entry(succ).or_insert(0) += 1;
+    }
+  }
+  let has_phis: HashSet<IrLabel> = blocks
+    .iter()
+    .filter(|block| !block.phis.is_empty())
+    .filter_map(|block| block.label)
+    .collect();
+
+  let mut new_blocks = vec![];
+  for block in blocks.iter_mut() {
+    let succs = block.successors();
+    if succs.len() < 2 {
+      continue;
+    }
+    for succ in succs {
+      if pred_counts[&succ] < 2 || !has_phis.contains(&succ) {
+        continue;
+      }
+
+      let split_label = IrLabel { label: *next_label };
+      *next_label += 1;
+      block.retarget(succ, split_label);
+      new_blocks.push((block.label.unwrap(), succ, split_label));
+    }
+  }
+
+  for (pred, succ, split_label) in new_blocks {
+    // the phis now receive their value along the new edge
+    for block in blocks.iter_mut().filter(|block| block.label == Some(succ)) {
+      for (_, incoming) in block.phis.iter_mut() {
+        for (_, l) in incoming.iter_mut().filter(|(_, l)| *l == pred) {
+          *l = split_label;
+        }
+      }
+    }
+    blocks.push(Block {
+      label: Some(split_label),
+      phis: vec![],
+      cmds: vec![IrCmd::Goto(succ)],
+    });
+  }
+}
+
+/*
+ * Orders a parallel copy so that no dest is written before every copy reading
+ * it has been done. When only cycles are left, the value of one dest is saved
+ * into a fresh temp first, and the copies reading it read the temp instead.
+ */
+fn sequentialize(copies: Vec<(IrVar, IrVar)>, next_tmp: &mut i32) -> Vec<IrCmd> {
+  let mut pending: Vec<(IrVar, IrVar)> = copies.into_iter().filter(|(d, s)| d != s).collect();
+  let mut cmds = vec![];
+
+  while !pending.is_empty() {
+    let ready = pending
+      .iter()
+      .position(|(dest, _)| !pending.iter().any(|(_, src)| src == dest));
+
+    match ready {
+      Some(i) => {
+        let (dest, src) = pending.remove(i);
+        cmds.push(IrCmd::Asgn(dest, expr_from_var(src)));
+      }
+      None => {
+        let dest = pending[0].0.clone();
+        let saved = match &dest {
+          IrVar::Temp(_, ty, _) => IrVar::Temp(*next_tmp, *ty, 1),
+          IrVar::Ident(_, _) => panic!("out_of_ssa: ident vars should be temps by now"),
+        };
+        *next_tmp += 1;
+        cmds.push(IrCmd::Asgn(saved.clone(), expr_from_var(dest.clone())));
+        for (_, src) in pending.iter_mut().filter(|(_, src)| *src == dest) {
+          *src = saved.clone();
+        }
+      }
+    }
+  }
+
+  cmds
+}
+
+fn join_blocks(blocks: Vec<Block>) -> Vec<IrCmd> {
+  let mut body = vec![];
+  for block in blocks {
+    if let Some(l) = block.label {
+      body.push(IrCmd::Label(l));
+    }
+    body.extend(block.cmds);
+  }
+
+  body
+}
+
+fn max_label(body: &[IrCmd]) -> i32 {
+  body
+    .iter()
+    .filter_map(|cmd| match cmd {
+      IrCmd::Label(l) => Some(l.label),
+      _ => None,
+    })
+    .max()
+    .unwrap_or(-1)
+}
+
+fn max_tmp(ir: &IrFunction) -> i32 {
+  let arg_tmps = ir.args.iter().map(|arg| &arg.ident);
+  let def_tmps = ir.body.iter().filter_map(|cmd| match cmd {
+    IrCmd::Asgn(var, _) => Some(var),
+    _ => None,
+  });
+
+  arg_tmps
+    .chain(def_tmps)
+    .filter_map(|var| match var {
+      IrVar::Temp(n, _, _) => Some(*n),
+      _ => None,
+    })
+    .max()
+    .unwrap_or(-1)
+}
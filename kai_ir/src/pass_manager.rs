@@ -0,0 +1,314 @@
+use crate::cfg::{BasicBlock, ControlFlowGraph};
+use crate::ir::*;
+use crate::liveness::{liveness, Liveness};
+use crate::ssa::{dominance_frontiers, dominators};
+use crate::verify;
+use kai_common::diagnostics::Diagnostic;
+use kai_common::opt_level::OptLevel;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+// the analyses a pass can ask for, computed on demand and cached until a pass changes what they depend on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Analysis {
+  Cfg,
+  Dominators,
+  Frontiers,
+  Liveness,
+}
+
+/*
+ * A transform over one function in SSA form.
+ *
+ * `run` returns any errors found in the program, e.g. a division that always
+ * traps. `preserves` lists the analyses still valid after the pass has run,
+ * none by default. Note `Analysis::Cfg` holds a copy of each block's cmds,
+ * so a pass preserving it must not change any cmd, while dominators and
+ * frontiers only depend on the edges between blocks.
+ */
+pub trait Pass {
+  fn name(&self) -> &'static str;
+
+  fn run(&self, func: &mut IrFunction, analyses: &mut Analyses) -> Vec<Diagnostic>;
+
+  fn preserves(&self) -> &'static [Analysis] {
+    &[]
+  }
+}
+
+// the cached analyses of one function
+#[derive(Default)]
+pub struct Analyses {
+  basic_blocks: Option<Vec<BasicBlock>>,
+  dominators: Option<Vec<usize>>,
+  frontiers: Option<Vec<HashSet<usize>>>,
+  liveness: Option<Liveness>,
+}
+
+impl Analyses {
+  pub fn new() -> Analyses {
+    Analyses::default()
+  }
+
+  pub fn cfg(&mut self, func: &IrFunction) -> ControlFlowGraph<'_> {
+    let basic_blocks = self.basic_blocks.get_or_insert_with(|| {
+      let mut basic_blocks = vec![];
+      ControlFlowGraph {
+        basic_blocks: &mut basic_blocks,
+      }
+      .build_cfg(func);
+      basic_blocks
+    });
+
+    ControlFlowGraph { basic_blocks }
+  }
+
+  // the immediate dominator of each block, indexed like the blocks of the CFG
+  pub fn dominators(&mut self, func: &IrFunction) -> &Vec<usize> {
+    if self.dominators.is_none() {
+      self.dominators = Some(dominators(&self.cfg(func)));
+    }
+    self.dominators.as_ref().unwrap()
+  }
+
+  pub fn frontiers(&mut self, func: &IrFunction) -> &Vec<HashSet<usize>> {
+    if self.frontiers.is_none() {
+      let dominators = self.dominators(func).clone();
+      self.frontiers = Some(dominance_frontiers(&self.cfg(func), &dominators));
+    }
+    self.frontiers.as_ref().unwrap()
+  }
+
+  pub fn liveness(&mut self, func: &IrFunction) -> &Liveness {
+    if self.liveness.is_none() {
+      self.liveness = Some(liveness(&self.cfg(func)));
+    }
+    self.liveness.as_ref().unwrap()
+  }
+
+  // drops every analysis not in preserved
+  pub fn invalidate(&mut self, preserved: &[Analysis]) {
+    if !preserved.contains(&Analysis::Cfg) {
+      self.basic_blocks = None;
+    }
+    if !preserved.contains(&Analysis::Dominators) {
+      self.dominators = None;
+    }
+    if !preserved.contains(&Analysis::Frontiers) {
+      self.frontiers = None;
+    }
+    if !preserved.contains(&Analysis::Liveness) {
+      self.liveness = None;
+    }
+  }
+}
+
+struct ConstTraps;
+struct Sccp;
+struct RemoveUnreachableBlocks;
+struct MergeBlocks;
+struct Gvn;
+struct Dce;
+
+impl Pass for ConstTraps {
+  fn name(&self) -> &'static str {
+    "const-traps"
+  }
+
+  fn run(&self, func: &mut IrFunction, _: &mut Analyses) -> Vec<Diagnostic> {
+    crate::sccp::const_traps(func)
+  }
+
+  // an analysis only, func is left as is
+  fn preserves(&self) -> &'static [Analysis] {
+    &[
+      Analysis::Cfg,
+      Analysis::Dominators,
+      Analysis::Frontiers,
+      Analysis::Liveness,
+    ]
+  }
+}
+
+impl Pass for Sccp {
+  fn name(&self) -> &'static str {
+    "sccp"
+  }
+
+  fn run(&self, func: &mut IrFunction, _: &mut Analyses) -> Vec<Diagnostic> {
+    crate::sccp::sccp(func);
+    vec![]
+  }
+}
+
+impl Pass for RemoveUnreachableBlocks {
+  fn name(&self) -> &'static str {
+    "remove-unreachable-blocks"
+  }
+
+  fn run(&self, func: &mut IrFunction, analyses: &mut Analyses) -> Vec<Diagnostic> {
+    crate::simplify_cfg::remove_unreachable_blocks(func, analyses);
+    vec![]
+  }
+}
+
+impl Pass for MergeBlocks {
+  fn name(&self) -> &'static str {
+    "merge-blocks"
+  }
+
+  fn run(&self, func: &mut IrFunction, _: &mut Analyses) -> Vec<Diagnostic> {
+    crate::simplify_cfg::merge_blocks(func);
+    vec![]
+  }
+}
+
+impl Pass for Gvn {
+  fn name(&self) -> &'static str {
+    "gvn"
+  }
+
+  fn run(&self, func: &mut IrFunction, analyses: &mut Analyses) -> Vec<Diagnostic> {
+    crate::gvn::gvn(func, analyses);
+    vec![]
+  }
+
+  // only the cmds in blocks are rewritten, never removed
+  fn preserves(&self) -> &'static [Analysis] {
+    &[Analysis::Dominators, Analysis::Frontiers]
+  }
+}
+
+impl Pass for Dce {
+  fn name(&self) -> &'static str {
+    "dce"
+  }
+
+  fn run(&self, func: &mut IrFunction, _: &mut Analyses) -> Vec<Diagnostic> {
+    crate::dce::remove_dead_code(func);
+    vec![]
+  }
+}
+
+/*
+ * Runs a pipeline of function passes in the order they were added, each
+ * over every function before the next one starts. If a pass reports errors,
+ * the passes after it are not run. In debug builds, each pass's output is
+ * checked by the verifier, so passes need not check it themselves.
+ *
+ * With `print_after` set to the name of a pass, every function is printed
+ * to stderr after that pass has run on it, and with `time_passes` set, the
+ * time spent in each pass is reported once the pipeline is done.
+ */
+#[derive(Default)]
+pub struct PassManager {
+  passes: Vec<Box<dyn Pass>>,
+  pub print_after: Option<String>,
+  pub time_passes: bool,
+}
+
+impl PassManager {
+  pub fn new() -> PassManager {
+    PassManager::default()
+  }
+
+  /*
+   * The pipeline run at each `-O` level. Every level, `-O0` included, starts
+   * by checking for ops on constants that always trap, so whether a program
+   * compiles does not depend on the level. Constant propagation goes first,
+   * leaving unreachable blocks and straight-line chains to clean up, which
+   * gives value numbering larger blocks to work with. DCE goes last, since
+   * every other pass leaves dead assignments behind.
+   *
+   * `-O2` and up run the pipeline twice, as the copies value numbering
+   * propagates can make more values constant.
+   */
+  pub fn for_opt_level(opt_level: OptLevel) -> PassManager {
+    let mut pm = PassManager::new();
+    pm.add(Box::new(ConstTraps));
+    let rounds = match opt_level {
+      OptLevel::O0 => 0,
+      OptLevel::O1 => 1,
+      OptLevel::O2 | OptLevel::O3 => 2,
+    };
+    for _ in 0..rounds {
+      pm.add(Box::new(Sccp));
+      pm.add(Box::new(RemoveUnreachableBlocks));
+      pm.add(Box::new(MergeBlocks));
+      pm.add(Box::new(Gvn));
+      pm.add(Box::new(Dce));
+    }
+
+    pm
+  }
+
+  // the names `--print-after` accepts, in pipeline order
+  pub fn all_pass_names() -> Vec<&'static str> {
+    PassManager::for_opt_level(OptLevel::O1).pass_names()
+  }
+
+  pub fn add(&mut self, pass: Box<dyn Pass>) {
+    self.passes.push(pass);
+  }
+
+  pub fn pass_names(&self) -> Vec<&'static str> {
+    self.passes.iter().map(|pass| pass.name()).collect()
+  }
+
+  pub fn run(&self, ir: &mut IrProgram) -> Vec<Diagnostic> {
+    let mut analyses: Vec<Analyses> = ir.functions.iter().map(|_| Analyses::new()).collect();
+    // the time spent in each pass, summed over the times it appears in the pipeline
+    let mut timings: Vec<(&'static str, Duration)> = vec![];
+
+    let mut diags = vec![];
+    for pass in &self.passes {
+      let start = Instant::now();
+      for (func, analyses) in ir.functions.iter_mut().zip(analyses.iter_mut()) {
+        diags.extend(pass.run(func, analyses));
+        analyses.invalidate(pass.preserves());
+        if self.print_after.as_deref() == Some(pass.name()) {
+          eprintln!("*** IR after {} ***\n{}\n", pass.name(), func);
+        }
+      }
+      let elapsed = start.elapsed();
+      match timings.iter_mut().find(|(name, _)| *name == pass.name()) {
+        Some((_, total)) => *total += elapsed,
+        None => timings.push((pass.name(), elapsed)),
+      }
+      // outside the timing, as it only runs in debug builds
+      for func in &ir.functions {
+        verify::debug_verify_function(func, true, pass.name());
+      }
+
+      if !diags.is_empty() {
+        break;
+      }
+    }
+
+    if self.time_passes {
+      eprintln!("{}", timing_report(&timings));
+    }
+    diags
+  }
+}
+
+fn timing_report(timings: &[(&'static str, Duration)]) -> String {
+  let total: Duration = timings.iter().map(|(_, time)| *time).sum();
+  let mut report = String::from("pass timings:\n");
+  for (name, time) in timings {
+    let percent = if total.as_nanos() == 0 {
+      0.0
+    } else {
+      100.0 * time.as_secs_f64() / total.as_secs_f64()
+    };
+    report += &format!(
+      "  {:<28}{:>10.3}ms{:>7.1}%\n",
+      name,
+      time.as_secs_f64() * 1000.0,
+      percent
+    );
+  }
+  report += &format!("  {:<28}{:>10.3}ms", "total", total.as_secs_f64() * 1000.0);
+
+  report
+}
@@ -0,0 +1,487 @@
+use crate::ir::*;
+use crate::ir_utils::used_vars;
+use kai_common::diagnostics::{Diagnostic, Span, E_ARITH_OVERFLOW, E_DIV_BY_ZERO, E_DIV_OVERFLOW};
+use kai_common::ops::{Opcode, UnOpcode};
+use kai_common::overflow_mode::OverflowMode;
+use std::collections::{HashMap, HashSet};
+
+/*
+ * Sparse conditional constant propagation over a function in SSA form, from
+ * "Constant Propagation with Conditional Branches" (Wegman and Zadeck).
+ *
+ * Every temp version starts out as `Undefined`, and only ever moves down to
+ * `Const` and then `Overdefined`. Blocks are only visited once an edge into
+ * them is found to be executable, and a branch on a constant only makes one
+ * of its edges executable, so a constant flowing around a loop or into a
+ * branch that is never taken is still found to be constant.
+ *
+ * Afterwards constant temps are assigned their value, uses of them are
+ * replaced by it, branches on constants become gotos, and blocks that can
+ * never run are removed, along with the phi operands coming from them.
+ * The now unused assignments are left for dead code elimination.
+ *
+ * Only ints and bools are folded. Strings, calls and args are overdefined.
+ */
+
+#[derive(Clone, Debug, PartialEq)]
+enum Lattice {
+  // no value seen yet, or a var that is never assigned
+  Undefined,
+  Const(IrLiteral),
+  Overdefined,
+}
+
+struct Block {
+  label: Option<IrLabel>,
+  // [start, end) of the block in the body, label included
+  range: (usize, usize),
+}
+
+struct SccpCtx<'a> {
+  func: &'a IrFunction,
+  blocks: Vec<Block>,
+  label_to_block: HashMap<IrLabel, usize>,
+  // the block each line of the body is in
+  line_block: Vec<usize>,
+  // the lines each var is used on, phis included
+  uses: HashMap<IrVar, Vec<usize>>,
+  values: HashMap<IrVar, Lattice>,
+  executable_blocks: Vec<bool>,
+  executable_edges: HashSet<(usize, usize)>,
+  // edges found executable, None coming into the entry block
+  flow_worklist: Vec<(Option<usize>, usize)>,
+  // lines to revisit, since a var they use changed
+  ssa_worklist: Vec<usize>,
+}
+
+// runs SCCP over func, which must be in SSA form, see above
+pub fn sccp(func: &mut IrFunction) {
+  let mut ctx = SccpCtx::new(func);
+  ctx.run();
+  let body = ctx.rewrite();
+
+  func.body = body;
+}
+
+/*
+ * Returns an error for every division by a constant zero, or of the smallest
+ * int by -1, and every overflow of constants in `OverflowMode::Trap`, in a
+ * block that may run, since the compiled program would trap there.
+ *
+ * func must be in SSA form, and is only analysed, not rewritten. This runs
+ * at every `-O` level, so the errors do not depend on which passes run.
+ */
+pub fn const_traps(func: &IrFunction) -> Vec<Diagnostic> {
+  let mut ctx = SccpCtx::new(func);
+  ctx.run();
+  ctx.trap_errors()
+}
+
+// the value of a literal that is not a var
+fn const_value(lit: &IrLiteral) -> Lattice {
+  match lit {
+    IrLiteral::Num(_, _) | IrLiteral::Bool(_) => Lattice::Const(lit.clone()),
+    IrLiteral::Str(_) => Lattice::Overdefined,
+    IrLiteral::Var(_) => panic!("sccp: vars have no value of their own"),
+  }
+}
+
+fn meet(v1: &Lattice, v2: &Lattice) -> Lattice {
+  match (v1, v2) {
+    (Lattice::Undefined, v) | (v, Lattice::Undefined) => v.clone(),
+    (Lattice::Const(c1), Lattice::Const(c2)) if c1 == c2 => v1.clone(),
+    _ => Lattice::Overdefined,
+  }
+}
+
+/*
+ * Evaluates op on constant operands like the compiled program does, with
+ * overflow handled as mode says. None if the compiled program would trap.
+ */
+fn fold_binop(
+  op: Opcode,
+  lit1: &IrLiteral,
+  lit2: &IrLiteral,
+  mode: OverflowMode,
+) -> Option<IrLiteral> {
+  let res = match (op, lit1, lit2) {
+    (Opcode::LogAnd, IrLiteral::Bool(b1), IrLiteral::Bool(b2)) => IrLiteral::Bool(*b1 && *b2),
+    (Opcode::LogOr, IrLiteral::Bool(b1), IrLiteral::Bool(b2)) => IrLiteral::Bool(*b1 || *b2),
+    (_, IrLiteral::Num(n1, int_ty), IrLiteral::Num(n2, _)) => {
+      match gen_op_result_type(op, IrType::Int(*int_ty)) {
+        IrType::Bool => IrLiteral::Bool(int_ty.compare(op, *n1, *n2)),
+        _ => IrLiteral::Num(int_ty.eval_binop(op, *n1, *n2, mode)?, *int_ty),
+      }
+    }
+    (Opcode::LogEq, _, _) => IrLiteral::Bool(lit1 == lit2),
+    (Opcode::LogNeq, _, _) => IrLiteral::Bool(lit1 != lit2),
+    _ => panic!("sccp: cannot fold `{} {} {}`", lit1, op, lit2),
+  };
+
+  Some(res)
+}
+
+fn fold_unop(op: UnOpcode, lit: &IrLiteral, mode: OverflowMode) -> Option<IrLiteral> {
+  let res = match (op, lit) {
+    (UnOpcode::Not, IrLiteral::Bool(b)) => IrLiteral::Bool(!b),
+    (_, IrLiteral::Num(n, int_ty)) => IrLiteral::Num(int_ty.eval_unop(op, *n, mode)?, *int_ty),
+    _ => panic!("sccp: cannot fold `{}{}`", op, lit),
+  };
+
+  Some(res)
+}
+
+// an int converted to another integer type, like `as` does
+fn fold_cast(lit: &IrLiteral, ty: IrType) -> IrLiteral {
+  match (lit, ty) {
+    (IrLiteral::Num(n, _), IrType::Int(int_ty)) => IrLiteral::Num(int_ty.wrap(*n), int_ty),
+    _ => panic!("sccp: cannot fold `{} as {}`", lit, ty),
+  }
+}
+
+impl<'a> SccpCtx<'a> {
+  fn new(func: &'a IrFunction) -> SccpCtx<'a> {
+    let mut blocks: Vec<Block> = vec![];
+    let mut line_block = vec![];
+    let mut uses: HashMap<IrVar, Vec<usize>> = HashMap::new();
+    for (line, cmd) in func.body.iter().enumerate() {
+      match cmd {
+        IrCmd::Label(l) => blocks.push(Block {
+          label: Some(*l),
+          range: (line, line),
+        }),
+        _ if blocks.is_empty() => blocks.push(Block {
+          label: None,
+          range: (line, line),
+        }),
+        _ => {}
+      }
+      blocks.last_mut().unwrap().range.1 = line + 1;
+      line_block.push(blocks.len() - 1);
+
+      for var in used_vars(cmd) {
+        uses.entry(var.clone()).or_default().push(line);
+      }
+    }
+
+    let label_to_block = blocks
+      .iter()
+      .enumerate()
+      .filter_map(|(u, block)| block.label.map(|l| (l, u)))
+      .collect();
+    // args could be anything, everything else starts out undefined
+    let values = func
+      .args
+      .iter()
+      .map(|arg| (arg.ident.clone(), Lattice::Overdefined))
+      .collect();
+
+    SccpCtx {
+      func,
+      executable_blocks: vec![false; blocks.len()],
+      blocks,
+      label_to_block,
+      line_block,
+      uses,
+      values,
+      executable_edges: HashSet::new(),
+      flow_worklist: vec![(None, 0)],
+      ssa_worklist: vec![],
+    }
+  }
+
+  fn run(&mut self) {
+    if self.func.body.is_empty() {
+      return;
+    }
+
+    loop {
+      if let Some((from, to)) = self.flow_worklist.pop() {
+        if let Some(from) = from {
+          if !self.executable_edges.insert((from, to)) {
+            continue;
+          }
+        }
+
+        // phis depend on which edges are executable, so see the new one
+        let (start, end) = self.blocks[to].range;
+        for line in start..end {
+          if let IrCmd::Asgn(_, IrExpr::Phi(_)) = self.func.body[line] {
+            self.visit(line);
+          }
+        }
+        // everything else only needs to be visited once, then when its operands change
+        if !self.executable_blocks[to] {
+          self.executable_blocks[to] = true;
+          for line in start..end {
+            if !matches!(self.func.body[line], IrCmd::Asgn(_, IrExpr::Phi(_))) {
+              self.visit(line);
+            }
+          }
+        }
+      } else if let Some(line) = self.ssa_worklist.pop() {
+        if self.executable_blocks[self.line_block[line]] {
+          self.visit(line);
+        }
+      } else {
+        break;
+      }
+    }
+  }
+
+  fn visit(&mut self, line: usize) {
+    let u = self.line_block[line];
+    match &self.func.body[line] {
+      IrCmd::Asgn(var, expr) => {
+        let value = self.eval_expr(u, expr);
+        let old = self.value(var);
+        let new = meet(&old, &value);
+        if new != old {
+          self.values.insert(var.clone(), new);
+          if let Some(lines) = self.uses.get(var) {
+            self.ssa_worklist.extend(lines);
+          }
+        }
+      }
+      IrCmd::Goto(l) => self.flow_worklist.push((Some(u), self.label_to_block[l])),
+      IrCmd::Cond(lit, l1, l2) => {
+        let (l1, l2) = (self.label_to_block[l1], self.label_to_block[l2]);
+        match self.lit_value(lit) {
+          Lattice::Const(IrLiteral::Bool(true)) => self.flow_worklist.push((Some(u), l1)),
+          Lattice::Const(IrLiteral::Bool(false)) => self.flow_worklist.push((Some(u), l2)),
+          // an undefined condition cannot come out of well-formed SSA, so is not worth optimizing
+          _ => {
+            self.flow_worklist.push((Some(u), l1));
+            self.flow_worklist.push((Some(u), l2));
+          }
+        }
+      }
+      IrCmd::Label(_) | IrCmd::Return(_) => {}
+    }
+  }
+
+  fn eval_expr(&self, u: usize, expr: &IrExpr) -> Lattice {
+    match expr {
+      IrExpr::Literal(lit) => self.lit_value(lit),
+      IrExpr::Binop(op, lit1, lit2) => match (self.lit_value(lit1), self.lit_value(lit2)) {
+        (Lattice::Const(c1), Lattice::Const(c2)) => {
+          match fold_binop(*op, &c1, &c2, self.func.overflow) {
+            Some(res) => Lattice::Const(res),
+            // the compiled program traps, which `trap_errors` reports
+            None => Lattice::Overdefined,
+          }
+        }
+        (Lattice::Overdefined, _) | (_, Lattice::Overdefined) => Lattice::Overdefined,
+        _ => Lattice::Undefined,
+      },
+      IrExpr::Unop(op, lit) => match self.lit_value(lit) {
+        Lattice::Const(c) => match fold_unop(*op, &c, self.func.overflow) {
+          Some(res) => Lattice::Const(res),
+          None => Lattice::Overdefined,
+        },
+        value => value,
+      },
+      IrExpr::Cast(lit, ty) => match self.lit_value(lit) {
+        Lattice::Const(c) => Lattice::Const(fold_cast(&c, *ty)),
+        value => value,
+      },
+      IrExpr::Call(_, _) => Lattice::Overdefined,
+      // only values flowing along executable edges count
+      IrExpr::Phi(incoming) => incoming
+        .iter()
+        .filter(|(_, l)| self.executable_edges.contains(&(self.label_to_block[l], u)))
+        .fold(Lattice::Undefined, |acc, (var, _)| {
+          meet(&acc, &self.value(var))
+        }),
+    }
+  }
+
+  fn lit_value(&self, lit: &IrLiteral) -> Lattice {
+    match lit {
+      IrLiteral::Var(var) => self.value(var),
+      _ => const_value(lit),
+    }
+  }
+
+  fn value(&self, var: &IrVar) -> Lattice {
+    self.values.get(var).cloned().unwrap_or(Lattice::Undefined)
+  }
+
+  fn const_lit(&self, lit: &IrLiteral) -> IrLiteral {
+    match self.lit_value(lit) {
+      Lattice::Const(c) if matches!(lit, IrLiteral::Var(_)) => c,
+      _ => lit.clone(),
+    }
+  }
+
+  /*
+   * Looks for ops that always trap once the values are final, since a var
+   * may look like zero until an edge that gives it another value is found.
+   */
+  fn trap_errors(&self) -> Vec<Diagnostic> {
+    let mut diags = vec![];
+    for (line, cmd) in self.func.body.iter().enumerate() {
+      if !self.executable_blocks[self.line_block[line]] {
+        continue;
+      }
+      let diag = match cmd {
+        IrCmd::Asgn(dest, IrExpr::Binop(op, lit1, lit2)) => {
+          self.binop_error(*op, lit1, lit2, self.span(dest))
+        }
+        IrCmd::Asgn(dest, IrExpr::Unop(op, lit)) => self.unop_error(*op, lit, self.span(dest)),
+        _ => None,
+      };
+
+      if let Some(diag) = diag {
+        diags.push(
+          diag
+            .with_note("the compiled program would trap here".to_string())
+            .with_note(format!("in function `{}`", self.func.ident)),
+        );
+      }
+    }
+
+    diags
+  }
+
+  fn binop_error(
+    &self,
+    op: Opcode,
+    lit1: &IrLiteral,
+    lit2: &IrLiteral,
+    span: Option<Span>,
+  ) -> Option<Diagnostic> {
+    if !op_may_trap(op, self.func.overflow) {
+      return None;
+    }
+
+    let diag = match (self.lit_value(lit1), self.lit_value(lit2)) {
+      (_, Lattice::Const(IrLiteral::Num(0, _))) if matches!(op, Opcode::Div | Opcode::Mod) => {
+        match self.lit_value(lit1) {
+          Lattice::Const(IrLiteral::Num(n1, _)) => Diagnostic::error(
+            E_DIV_BY_ZERO,
+            format!("attempt to calculate `{} {} 0`", n1, op),
+            span,
+          ),
+          _ => Diagnostic::error(
+            E_DIV_BY_ZERO,
+            match op {
+              Opcode::Div => "attempt to divide by zero".to_string(),
+              _ => "attempt to calculate the remainder with a divisor of zero".to_string(),
+            },
+            span,
+          ),
+        }
+      }
+      (Lattice::Const(IrLiteral::Num(n1, int_ty)), Lattice::Const(IrLiteral::Num(-1, _)))
+        if matches!(op, Opcode::Div | Opcode::Mod)
+          && int_ty.is_signed()
+          && n1 as i128 == int_ty.min() =>
+      {
+        Diagnostic::error(
+          E_DIV_OVERFLOW,
+          format!("attempt to calculate `{} {} -1`, which overflows", n1, op),
+          span,
+        )
+      }
+      (Lattice::Const(c1), Lattice::Const(c2))
+        if fold_binop(op, &c1, &c2, self.func.overflow).is_none() =>
+      {
+        Diagnostic::error(
+          E_ARITH_OVERFLOW,
+          format!(
+            "attempt to calculate `{} {} {}`, which overflows",
+            c1, op, c2
+          ),
+          span,
+        )
+      }
+      _ => return None,
+    };
+
+    Some(diag)
+  }
+
+  fn unop_error(&self, op: UnOpcode, lit: &IrLiteral, span: Option<Span>) -> Option<Diagnostic> {
+    match self.lit_value(lit) {
+      Lattice::Const(c) if fold_unop(op, &c, self.func.overflow).is_none() => {
+        Some(Diagnostic::error(
+          E_ARITH_OVERFLOW,
+          format!("attempt to negate `{}`, which overflows", c),
+          span,
+        ))
+      }
+      _ => None,
+    }
+  }
+
+  // where the op assigned to dest is in the source, if it may trap
+  fn span(&self, dest: &IrVar) -> Option<Span> {
+    match dest {
+      IrVar::Temp(n, _, _) => self.func.spans.get(n).copied(),
+      IrVar::Ident(_, _) => None,
+    }
+  }
+
+  // the body with the constants found substituted in, see the top of this file
+  fn rewrite(&self) -> Vec<IrCmd> {
+    let mut body = vec![];
+    for (u, block) in self.blocks.iter().enumerate() {
+      if !self.executable_blocks[u] {
+        continue;
+      }
+
+      // phis that turned out constant become assignments, which must come after every phi
+      let mut folded_phis = vec![];
+      let (start, end) = block.range;
+      for line in start..end {
+        let cmd = match &self.func.body[line] {
+          IrCmd::Asgn(var, expr) => match (self.value(var), expr) {
+            (Lattice::Const(c), IrExpr::Phi(_)) => {
+              folded_phis.push(IrCmd::Asgn(var.clone(), IrExpr::Literal(c)));
+              continue;
+            }
+            (Lattice::Const(c), _) => IrCmd::Asgn(var.clone(), IrExpr::Literal(c)),
+            (_, expr) => IrCmd::Asgn(var.clone(), self.rewrite_expr(u, expr)),
+          },
+          IrCmd::Cond(lit, l1, l2) => match self.lit_value(lit) {
+            Lattice::Const(IrLiteral::Bool(b)) => IrCmd::Goto(if b { *l1 } else { *l2 }),
+            _ => IrCmd::Cond(lit.clone(), *l1, *l2),
+          },
+          IrCmd::Return(lit) => IrCmd::Return(self.const_lit(lit)),
+          cmd => cmd.clone(),
+        };
+
+        if !folded_phis.is_empty() && !matches!(cmd, IrCmd::Asgn(_, IrExpr::Phi(_))) {
+          body.append(&mut folded_phis);
+        }
+        body.push(cmd);
+      }
+    }
+
+    body
+  }
+
+  fn rewrite_expr(&self, u: usize, expr: &IrExpr) -> IrExpr {
+    match expr {
+      IrExpr::Literal(lit) => IrExpr::Literal(self.const_lit(lit)),
+      IrExpr::Binop(op, lit1, lit2) => {
+        IrExpr::Binop(*op, self.const_lit(lit1), self.const_lit(lit2))
+      }
+      IrExpr::Unop(op, lit) => IrExpr::Unop(*op, self.const_lit(lit)),
+      IrExpr::Cast(lit, ty) => IrExpr::Cast(self.const_lit(lit), *ty),
+      IrExpr::Call(ident, args) => IrExpr::Call(
+        ident.clone(),
+        args.iter().map(|arg| self.const_lit(arg)).collect(),
+      ),
+      // operands have to stay vars, only those coming along removed edges go
+      IrExpr::Phi(incoming) => IrExpr::Phi(
+        incoming
+          .iter()
+          .filter(|(_, l)| self.executable_edges.contains(&(self.label_to_block[l], u)))
+          .cloned()
+          .collect(),
+      ),
+    }
+  }
+}
@@ -0,0 +1,192 @@
+use crate::ir::*;
+use crate::pass_manager::Analyses;
+use std::collections::{HashMap, HashSet};
+
+/*
+ * Passes simplifying the control flow of a function in SSA form, left
+ * behind by `ir_gen` lowering every `if` and `while` the same way, and by
+ * constant propagation resolving branches.
+ */
+
+// removes every block that cannot be reached from the entry, and the phi operands coming from them
+pub fn remove_unreachable_blocks(func: &mut IrFunction, analyses: &mut Analyses) {
+  // the CFG is built by following jumps from the entry, so only has the reachable blocks
+  let mut ranges: Vec<(usize, usize)> = analyses
+    .cfg(func)
+    .basic_blocks()
+    .iter()
+    .map(|bb| bb.cmd_range())
+    .collect();
+  ranges.sort();
+  let mut body: Vec<IrCmd> = ranges
+    .iter()
+    .flat_map(|(start, end)| func.body[*start..*end].iter().cloned())
+    .collect();
+
+  let labels: HashSet<IrLabel> = body
+    .iter()
+    .filter_map(|cmd| match cmd {
+      IrCmd::Label(l) => Some(*l),
+      _ => None,
+    })
+    .collect();
+  for cmd in body.iter_mut() {
+    if let IrCmd::Asgn(_, IrExpr::Phi(incoming)) = cmd {
+      incoming.retain(|(_, l)| labels.contains(l));
+    }
+  }
+
+  func.body = body;
+}
+
+struct Block {
+  label: Option<IrLabel>,
+  // everything after the label
+  cmds: Vec<IrCmd>,
+}
+
+/*
+ * Merges each block ending in a goto into its successor, if it is the only
+ * way into it. The successor's phis then have a single operand, and become
+ * copies. Blocks whose phis have an undefined operand are left alone, since
+ * a copy of an undefined var is not valid SSA.
+ */
+pub fn merge_blocks(func: &mut IrFunction) {
+  let mut blocks = split_blocks(&func.body);
+  let defined = defined_vars(func);
+
+  let mut pred_counts: HashMap<IrLabel, usize> = HashMap::new();
+  for block in &blocks {
+    for succ in successors(block) {
+      *pred_counts.entry(succ).or_insert(0) += 1;
+    }
+  }
+  // the entry block is also entered from outside the function
+  if let Some(l) = blocks.first().and_then(|block| block.label) {
+    *pred_counts.entry(l).or_insert(0) += 1;
+  }
+
+  let mut u = 0;
+  while u < blocks.len() {
+    let succ = match blocks[u].cmds.last() {
+      Some(IrCmd::Goto(l)) if pred_counts[l] == 1 => *l,
+      _ => {
+        u += 1;
+        continue;
+      }
+    };
+    let v = blocks
+      .iter()
+      .position(|block| block.label == Some(succ))
+      .unwrap();
+    let phis_defined = blocks[v].cmds.iter().all(|cmd| match cmd {
+      IrCmd::Asgn(_, IrExpr::Phi(incoming)) => {
+        incoming.iter().all(|(var, _)| defined.contains(var))
+      }
+      _ => true,
+    });
+    // phis further on have to name the merged block as where their values come from
+    if v == u || !phis_defined || (blocks[u].label.is_none() && is_phi_operand_label(&blocks, succ))
+    {
+      u += 1;
+      continue;
+    }
+
+    let merged = blocks.remove(v);
+    if v < u {
+      u -= 1;
+    }
+    let block = &mut blocks[u];
+    block.cmds.pop();
+    for cmd in merged.cmds {
+      block.cmds.push(match cmd {
+        IrCmd::Asgn(var, IrExpr::Phi(incoming)) => {
+          IrCmd::Asgn(var, expr_from_var(incoming[0].0.clone()))
+        }
+        cmd => cmd,
+      });
+    }
+
+    let label = blocks[u].label;
+    if let Some(label) = label {
+      relabel_phi_operands(&mut blocks, succ, label);
+    }
+    // u now ends in what succ ended in, which may be another goto to merge
+  }
+
+  func.body = join_blocks(blocks);
+}
+
+fn split_blocks(body: &Vec<IrCmd>) -> Vec<Block> {
+  let mut blocks: Vec<Block> = vec![];
+  for cmd in body {
+    match cmd {
+      IrCmd::Label(l) => blocks.push(Block {
+        label: Some(*l),
+        cmds: vec![],
+      }),
+      _ => {
+        if blocks.is_empty() {
+          blocks.push(Block {
+            label: None,
+            cmds: vec![],
+          });
+        }
+        blocks.last_mut().unwrap().cmds.push(cmd.clone());
+      }
+    }
+  }
+
+  blocks
+}
+
+fn join_blocks(blocks: Vec<Block>) -> Vec<IrCmd> {
+  let mut body = vec![];
+  for block in blocks {
+    if let Some(l) = block.label {
+      body.push(IrCmd::Label(l));
+    }
+    body.extend(block.cmds);
+  }
+
+  body
+}
+
+fn successors(block: &Block) -> Vec<IrLabel> {
+  match block.cmds.last() {
+    Some(IrCmd::Goto(l)) => vec![*l],
+    Some(IrCmd::Cond(_, l1, l2)) => vec![*l1, *l2],
+    _ => vec![],
+  }
+}
+
+// the args and every assigned var
+fn defined_vars(func: &IrFunction) -> HashSet<IrVar> {
+  let args = func.args.iter().map(|arg| arg.ident.clone());
+  let defs = func.body.iter().filter_map(|cmd| match cmd {
+    IrCmd::Asgn(var, _) => Some(var.clone()),
+    _ => None,
+  });
+
+  args.chain(defs).collect()
+}
+
+fn is_phi_operand_label(blocks: &[Block], label: IrLabel) -> bool {
+  blocks
+    .iter()
+    .flat_map(|block| &block.cmds)
+    .any(|cmd| match cmd {
+      IrCmd::Asgn(_, IrExpr::Phi(incoming)) => incoming.iter().any(|(_, l)| *l == label),
+      _ => false,
+    })
+}
+
+fn relabel_phi_operands(blocks: &mut [Block], from: IrLabel, to: IrLabel) {
+  for cmd in blocks.iter_mut().flat_map(|block| block.cmds.iter_mut()) {
+    if let IrCmd::Asgn(_, IrExpr::Phi(incoming)) = cmd {
+      for (_, l) in incoming.iter_mut().filter(|(_, l)| *l == from) {
+        *l = to;
+      }
+    }
+  }
+}
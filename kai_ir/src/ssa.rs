@@ -1,33 +1,5 @@
 use crate::{cfg::*, ir::*};
-use std::{
-  collections::HashSet,
-  collections::{HashMap, VecDeque},
-  convert::TryInto,
-};
-
-// dfs
-// fn _postorder(
-//   cfg: &ControlFlowGraph,
-//   u: usize,
-//   traversal: &mut Vec<usize>,
-//   visited: &mut HashSet<usize>,
-// ) {
-//   if visited.contains(&u) {
-//     return;
-//   }
-//   visited.insert(u);
-//   for v in cfg.basic_blocks[u].ancestors() {
-//     _postorder(cfg, *v, traversal, visited);
-//   }
-//   traversal.push(u);
-// }
-
-// fn postorder(cfg: &ControlFlowGraph) -> Vec<usize> {
-//   let mut ordering = vec![];
-//   _postorder(cfg, 0, &mut ordering, &mut HashSet::new());
-
-//   ordering
-// }
+use std::collections::{HashMap, HashSet, VecDeque};
 
 fn first_processed_pred(
   node: usize,
@@ -80,7 +52,7 @@ fn intersect(
 // find dominators
 // I know this is confusing as hell, I am sorry
 // consult ```A Simple, Fast, Dominance Algorithm```
-fn dominators(cfg: &ControlFlowGraph) -> Vec<usize> {
+pub fn dominators(cfg: &ControlFlowGraph) -> Vec<usize> {
   let postorder = cfg.postorder();
   let mut rev_postorder = postorder.clone();
   rev_postorder.reverse();
@@ -89,9 +61,6 @@ fn dominators(cfg: &ControlFlowGraph) -> Vec<usize> {
   for i in 0..postorder.len() {
     node_to_postorder[postorder[i]] = i;
   }
-  println!("postorder: {:?}", postorder);
-  println!("rev_postorder: {:?}", rev_postorder);
-  println!("node_to_postorder: {:?}", node_to_postorder);
   let mut dominators = vec![None; cfg.basic_blocks.len()];
 
   dominators[0] = Some(0);
@@ -135,11 +104,12 @@ fn dominators(cfg: &ControlFlowGraph) -> Vec<usize> {
   dominators.into_iter().map(|dom| dom.unwrap()).collect()
 }
 
-fn dominance_frontiers(cfg: &ControlFlowGraph, dominators: &Vec<usize>) -> Vec<HashSet<usize>> {
+pub fn dominance_frontiers(cfg: &ControlFlowGraph, dominators: &Vec<usize>) -> Vec<HashSet<usize>> {
   let mut frontiers = vec![HashSet::new(); dominators.len()];
   for u in 0..cfg.basic_blocks.len() {
+    // only join points can be in a dominance frontier
     let bb = cfg.at(u);
-    if bb.ancestors().len() > 1 {
+    if bb.predecessors().len() < 2 {
       continue;
     }
 
@@ -155,99 +125,123 @@ fn dominance_frontiers(cfg: &ControlFlowGraph, dominators: &Vec<usize>) -> Vec<H
   return frontiers;
 }
 
-fn rename_lit(lit: &IrLiteral, var_stack: &Vec<usize>) -> IrLiteral {
-  match lit {
-    IrLiteral::Num(n) => IrLiteral::Num(*n),
-    IrLiteral::Bool(b) => IrLiteral::Bool(*b),
-    IrLiteral::Var(IrVar::Temp(var, ty, _)) => {
-      IrLiteral::Var(IrVar::Temp(*var, *ty, var_stack[*var as usize]))
+/*
+ * Renaming state, per temp:
+ * - counters: the next unused version. version 0 is the value a temp has on
+ *   entry to the function, i.e. the argument for fn args, undefined otherwise
+ * - stacks: versions of the temp reaching the block being renamed, innermost
+ *   definition on top
+ */
+struct RenameCtx {
+  counters: Vec<usize>,
+  stacks: Vec<Vec<usize>>,
+}
+
+impl RenameCtx {
+  fn new(tmp_count: usize) -> RenameCtx {
+    RenameCtx {
+      counters: vec![1; tmp_count],
+      stacks: vec![vec![0]; tmp_count],
+    }
+  }
+
+  fn current(&self, var: i32) -> usize {
+    *self.stacks[var as usize].last().unwrap()
+  }
+
+  fn new_version(&mut self, var: i32) -> usize {
+    let version = self.counters[var as usize];
+    self.counters[var as usize] += 1;
+    self.stacks[var as usize].push(version);
+    version
+  }
+
+  fn pop(&mut self, var: i32) {
+    self.stacks[var as usize].pop();
+  }
+
+  fn rename_lit(&self, lit: &IrLiteral) -> IrLiteral {
+    match lit {
+      IrLiteral::Var(IrVar::Temp(var, ty, _)) => {
+        IrLiteral::Var(IrVar::Temp(*var, *ty, self.current(*var)))
+      }
+      IrLiteral::Var(IrVar::Ident(_, _)) => {
+        panic!("Error in ssa::rename_lit, found ident temp")
+      }
+      _ => lit.clone(),
+    }
+  }
+
+  fn rename_expr(&self, expr: &IrExpr) -> IrExpr {
+    match expr {
+      // phi arguments are filled in from the predecessors, see rename_cfg
+      IrExpr::Phi(phis) => IrExpr::Phi(phis.clone()),
+      IrExpr::Literal(lit) => IrExpr::Literal(self.rename_lit(lit)),
+      IrExpr::Binop(op, lit1, lit2) => {
+        IrExpr::Binop(*op, self.rename_lit(lit1), self.rename_lit(lit2))
+      }
+      IrExpr::Unop(op, lit) => IrExpr::Unop(*op, self.rename_lit(lit)),
+      IrExpr::Cast(lit, ty) => IrExpr::Cast(self.rename_lit(lit), *ty),
+      IrExpr::Call(ident, args) => IrExpr::Call(
+        ident.clone(),
+        args.iter().map(|arg| self.rename_lit(arg)).collect(),
+      ),
     }
-    _ => panic!("Error in ssa::rename_lit, found ident temp"),
   }
 }
 
-fn rename_expr(expr: &IrExpr, var_stack: &Vec<usize>) -> IrExpr {
-  match expr {
-    // should not see phis at this point
-    IrExpr::Phi(phis) => IrExpr::Phi(phis.clone()),
-    IrExpr::Literal(lit) => IrExpr::Literal(rename_lit(lit, var_stack)),
-    IrExpr::Binop(op, lit1, lit2) => IrExpr::Binop(
-      *op,
-      rename_lit(lit1, var_stack),
-      rename_lit(lit2, var_stack),
-    ),
+// the label a basic block gets in the rebuilt IR
+fn bb_label(bb_index: usize) -> IrLabel {
+  IrLabel {
+    label: bb_index as i32,
   }
 }
 
-// dfs over cfg, rename each basic block
+// dfs over the dominator tree, rename each basic block
 fn rename_cfg(
   cfg: &mut ControlFlowGraph,
   dom_tree: &Vec<Vec<usize>>,
-  visited: &mut Vec<bool>,
   bb_index: usize,
-  var_stack: &mut Vec<usize>,
-) -> () {
-  println!("var_stack: {:?}", var_stack);
-  if visited[bb_index] {
-    return;
-  }
-  visited[bb_index] = true;
-  let bb = cfg.at_mut(bb_index);
-  let bb_phis = bb.phis_mut();
+  ctx: &mut RenameCtx,
+) {
+  // every temp defined in this block, so they can be popped on the way out
+  let mut defs = vec![];
 
   // rename defs in PHIs
-  for i in 0..bb_phis.len() {
-    let bb_phi = match &bb_phis[i] {
-      IrCmd::Asgn(IrVar::Temp(var, ty, _), phi) => {
-        let new_cmd = IrCmd::Asgn(
-          IrVar::Temp(*var, *ty, var_stack[*var as usize] + 1),
-          phi.clone(),
-        );
-        var_stack[*var as usize] += 1;
-        new_cmd
-      }
-      _ => panic!("Error in ssa::rename_cfg, encountered non-PHI when scanning PHIs"),
-    };
-
-    bb_phis[i] = bb_phi;
+  for phi in cfg.at_mut(bb_index).phis_mut() {
+    if let IrCmd::Asgn(IrVar::Temp(var, _, version), IrExpr::Phi(_)) = phi {
+      *version = ctx.new_version(*var);
+      defs.push(*var);
+    } else {
+      panic!("Error in ssa::rename_cfg, encountered non-PHI when scanning PHIs");
+    }
   }
 
-  let bb_cmds = bb.cmds_mut();
-
-  // then rename the rest of the block
-  for i in 0..bb_cmds.len() {
-    let cmd = match &bb_cmds[i] {
+  // then rename the rest of the block, uses before defs
+  for cmd in cfg.at_mut(bb_index).cmds_mut() {
+    *cmd = match &*cmd {
       IrCmd::Asgn(IrVar::Temp(var, ty, _), expr) => {
-        let new_cmd = IrCmd::Asgn(
-          IrVar::Temp(*var, *ty, var_stack[*var as usize] + 1),
-          rename_expr(expr, var_stack),
-        );
-        var_stack[*var as usize] += 1;
-        new_cmd
+        let expr = ctx.rename_expr(expr);
+        defs.push(*var);
+        IrCmd::Asgn(IrVar::Temp(*var, *ty, ctx.new_version(*var)), expr)
       }
       IrCmd::Asgn(_, _) => panic!("Error in ssa::rename_cfg, should not have string temps now"),
-      IrCmd::Return(e) => IrCmd::Return(rename_lit(e, var_stack)),
+      IrCmd::Return(e) => IrCmd::Return(ctx.rename_lit(e)),
       IrCmd::Label(l) => IrCmd::Label(*l),
       IrCmd::Goto(l) => IrCmd::Goto(*l),
-      IrCmd::Cond(c, e1, e2) => IrCmd::Cond(rename_lit(c, var_stack), *e1, *e2),
+      IrCmd::Cond(c, e1, e2) => IrCmd::Cond(ctx.rename_lit(c), *e1, *e2),
     };
-    // println!("old cmd: {}, new cmd: {}", bb_cmds[i], cmd);
-    bb_cmds[i] = cmd;
   }
 
+  // the versions live at the end of this block flow into the phis of each successor
   let ancestors = cfg.at(bb_index).ancestors().clone();
-  // add new version of each var to each ancestor in CFG
   for ancestor_index in ancestors {
-    let bb = cfg.at_mut(ancestor_index);
-    let bb_phis = bb.phis_mut();
-    for i in 0..bb_phis.len() {
-      match &mut bb_phis[i] {
+    for phi in cfg.at_mut(ancestor_index).phis_mut() {
+      match phi {
         IrCmd::Asgn(IrVar::Temp(var, ty, _), IrExpr::Phi(phis)) => {
           phis.push((
-            IrVar::Temp(*var, *ty, var_stack[*var as usize]),
-            IrLabel {
-              label: bb_index as i32,
-            },
+            IrVar::Temp(*var, *ty, ctx.current(*var)),
+            bb_label(bb_index),
           ));
         }
         _ => panic!("Error in ssa::rename_cfg, encountered non-PHI when scanning PHIs"),
@@ -257,30 +251,22 @@ fn rename_cfg(
 
   // for child in dominator tree, rename all variables
   for bb_child in &dom_tree[bb_index] {
-    rename_cfg(cfg, dom_tree, visited, *bb_child, var_stack);
+    rename_cfg(cfg, dom_tree, *bb_child, ctx);
   }
 
-  // pop all defs in current block from var_stack
-  // do this by iterating through commands, and when we see a def of v, subtract
-  // from var_stack
-  let bb_cmds_immut = cfg.at(bb_index).cmds();
-  for i in 0..bb_cmds_immut.len() {
-    match &bb_cmds_immut[i] {
-      IrCmd::Asgn(IrVar::Temp(var, ty, _), _) => {
-        var_stack[*var as usize] -= 1;
-      }
-      _ => {}
-    };
+  for var in defs {
+    ctx.pop(var);
   }
 }
 
 /*
  * top level renaming variable subroutine
  * for each basic block:
- * - replace non-phi uses of v with most recent version of v
- * - for each def of v, increment counter for v
+ * - give each def of v (phis included) a fresh version, and push it on v's stack
+ * - replace non-phi uses of v with the version on top of v's stack
  * - for each ancestor of node, add v to PHI node with most recent version
- * - recuse on each child of dominator tree
+ * - recurse on each child of dominator tree
+ * - pop the versions defined in the block
  */
 fn rename_vars_and_insert_phis(
   cfg: &mut ControlFlowGraph,
@@ -290,118 +276,97 @@ fn rename_vars_and_insert_phis(
   tmp_ty_map: &HashMap<i32, IrType>,
   tmp_count: usize,
 ) -> () {
-  // mapping from temp number to current version
-  let mut var_stack = vec![0; tmp_count];
-  // mapping from block to actual phi definitions at the beginning
   for u in 0..phi.len() {
-    let bbs = &phi[u];
+    // sort so the order of phis in a block is deterministic
+    let mut bbs: Vec<&usize> = phi[u].iter().collect();
+    bbs.sort();
     for bb in bbs {
       cfg
         .at_mut(*bb)
         .add_new_phi(u as i32, *tmp_ty_map.get(&(u as i32)).unwrap());
-      // phis_per_block[*bb].insert(u, vec![]);
     }
   }
 
-  // will rename all cmds in place, and populate phis_per_block
-  rename_cfg(
-    cfg,
-    dom_tree,
-    &mut vec![false; cfg.basic_blocks().len()],
-    0,
-    &mut var_stack,
-  );
-
-  println!("cfg: {}", cfg);
+  // will rename all cmds in place, and fill in the phis
+  rename_cfg(cfg, dom_tree, 0, &mut RenameCtx::new(tmp_count));
 }
 
-// fn rebuild_ir_body(body: &mut Vec<IrCmd>, cfg: &ControlFlowGraph) {
-//   // bfs
-//   let mut queue = VecDeque::new();
-//   let mut visited = vec![false; cfg.basic_blocks().len()];
-//   visited[0] = true;
-//   queue.push_back(0);
-
-//   while queue.len() > 0 {
-//     println!("queue: {:?}", queue);
-//     let u = queue.pop_front().unwrap();
-//     let bb = cfg.at(u);
-//     body.push(IrCmd::Label(IrLabel { label: u as i32 }));
-//     for phi in bb.phis() {
-//       body.push(phi.clone());
-//     }
-
-//     let mut seen_jmp = false;
-//     for cmd in bb.cmds() {
-//       match cmd {
-//         // we will be generating new labels
-//         IrCmd::Label(_) => {
-//           continue;
-//         }
-//         IrCmd::Cond(c, _l1, _l2) => {
-//           assert!(
-//             !seen_jmp,
-//             "ssa::rebuild_ir_body: two branches within a single basic block",
-//           );
-//           assert!(
-//             bb.ancestors().len() == 2,
-//             "ssa::rebuild_ir_body: saw conditional, but number of branches != 2"
-//           );
-
-//           seen_jmp = true;
-//           body.push(IrCmd::Cond(
-//             c.clone(),
-//             IrLabel {
-//               label: bb.ancestors()[0] as i32,
-//             },
-//             IrLabel {
-//               label: bb.ancestors()[1] as i32,
-//             },
-//           ));
-//         }
-//         IrCmd::Goto(l) => {
-//           assert!(
-//             !seen_jmp,
-//             "ssa::rebuild_ir_body: two jmps within a single basic block",
-//           );
-//           assert!(
-//             bb.ancestors().len() == 1,
-//             "ssa::rebuild_ir_body: saw goto, but number of branches != 1"
-//           );
-
-//           seen_jmp = true;
-//           body.push(IrCmd::Goto(IrLabel {
-//             label: bb.ancestors()[0] as i32,
-//           }));
-//         }
-//         _ => {
-//           body.push(cmd.clone());
-//         }
-//       }
-//     }
-
-//     // if we still haven't seen a jump, we need to add one
-//     if seen_jmp == false && bb.ancestors().len() > 0 {
-//       println!("{}, {:?}", u, bb.ancestors());
-//       assert!(
-//         bb.ancestors().len() == 1,
-//         "if we are missing a goto, ancestors should be length 1"
-//       );
-//       body.push(IrCmd::Goto(IrLabel {
-//         label: bb.ancestors()[0] as i32,
-//       }));
-//     }
-
-//     for ancestor in bb.ancestors() {
-//       if visited[*ancestor] {
-//         continue;
-//       }
-
-//       visited[*ancestor] = true;
-//       queue.push_back(*ancestor);
-//     }
-//   }
-// }
+/*
+ * Lays the basic blocks back out as a list of commands, breadth first from
+ * the entry block. Every block starts with a label named after its index
+ * (which is what the phis refer to), followed by its phis, and ends in an
+ * explicit jump or return. Unreachable code is dropped.
+ */
+fn rebuild_ir_body(cfg: &ControlFlowGraph) -> Vec<IrCmd> {
+  let mut body = vec![];
+  let mut queue = VecDeque::new();
+  let mut visited = vec![false; cfg.basic_blocks().len()];
+  visited[0] = true;
+  queue.push_back(0);
+
+  while let Some(u) = queue.pop_front() {
+    let bb = cfg.at(u);
+    body.push(IrCmd::Label(bb_label(u)));
+    body.extend(bb.phis().iter().cloned());
+
+    let mut seen_jmp = false;
+    for cmd in bb.cmds() {
+      assert!(
+        !seen_jmp,
+        "ssa::rebuild_ir_body: command after a jump in a basic block"
+      );
+      match cmd {
+        // we will be generating new labels
+        IrCmd::Label(_) => continue,
+        IrCmd::Cond(c, _l1, _l2) => {
+          assert!(
+            bb.ancestors().len() == 2,
+            "ssa::rebuild_ir_body: saw conditional, but number of branches != 2"
+          );
+          seen_jmp = true;
+          body.push(IrCmd::Cond(
+            c.clone(),
+            bb_label(bb.ancestors()[0]),
+            bb_label(bb.ancestors()[1]),
+          ));
+        }
+        IrCmd::Goto(_) => {
+          assert!(
+            bb.ancestors().len() == 1,
+            "ssa::rebuild_ir_body: saw goto, but number of branches != 1"
+          );
+          seen_jmp = true;
+          body.push(IrCmd::Goto(bb_label(bb.ancestors()[0])));
+        }
+        IrCmd::Return(_) => {
+          seen_jmp = true;
+          body.push(cmd.clone());
+        }
+        IrCmd::Asgn(_, _) => body.push(cmd.clone()),
+      }
+    }
+
+    // blocks that fell through into a label need an explicit jump
+    if !seen_jmp && bb.ancestors().len() > 0 {
+      assert!(
+        bb.ancestors().len() == 1,
+        "if we are missing a goto, ancestors should be length 1"
+      );
+      body.push(IrCmd::Goto(bb_label(bb.ancestors()[0])));
+    }
+
+    for ancestor in bb.ancestors() {
+      if visited[*ancestor] {
+        continue;
+      }
+
+      visited[*ancestor] = true;
+      queue.push_back(*ancestor);
+    }
+  }
+
+  body
+}
 
 fn ssa_ir(
   ir: &mut IrFunction,
@@ -424,7 +389,7 @@ fn ssa_ir(
     for cmd in cmds {
       match cmd {
         IrCmd::Asgn(IrVar::Temp(var, _, _), _) => {
-          orig[bb_index].insert(var);
+          orig[bb_index].insert(*var);
           defsites[*var as usize].push(bb_index);
         }
         _ => {
@@ -458,13 +423,16 @@ fn ssa_ir(
   rename_vars_and_insert_phis(cfg, &dom_tree, &phi, tmp_ty_map, tmp_count);
 
   // rebuild IR by traversing the CFG
-  // let mut new_body = vec![];
-  // rebuild_ir_body(&mut new_body, &cfg);
-  // ir.body = new_body;
-
-  println!("{}", ir);
+  ir.body = rebuild_ir_body(&cfg);
 }
 
+/*
+ * Converts a function whose variables have been converted to temps into SSA
+ * form, using `cfg`, which must have been built from the same function.
+ *
+ * Afterwards every temp version is assigned exactly once, and ir.body has
+ * been rebuilt from the CFG (see rebuild_ir_body).
+ */
 pub fn gen_ssa(
   ir: &mut IrFunction,
   cfg: &mut ControlFlowGraph,
@@ -474,13 +442,11 @@ pub fn gen_ssa(
   let dominators = dominators(cfg);
   let dominance_frontiers = dominance_frontiers(cfg, &dominators);
   let mut dom_tree = vec![vec![]; dominators.len()];
-  for i in 0..dominators.len() {
+  for i in 1..dominators.len() {
+    // the entry block is its own dominator, but not its own child
     dom_tree[dominators[i]].push(i);
   }
 
-  println!("dominators: {:?}", dominators);
-  println!("frontiers: {:?}", dominance_frontiers);
-
   ssa_ir(
     ir,
     cfg,
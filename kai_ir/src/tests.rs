@@ -0,0 +1,1362 @@
+#[allow(unused_imports)]
+use crate::cfg::ControlFlowGraph;
+#[allow(unused_imports)]
+use crate::ir::*;
+#[allow(unused_imports)]
+use crate::pass_manager::Analyses;
+#[allow(unused_imports)]
+use kai_common::int_ty::IntTy;
+
+#[allow(dead_code)]
+fn tmp(n: i32) -> IrVar {
+  IrVar::Temp(n, IrType::Int(IntTy::I32), 0)
+}
+
+#[allow(dead_code)]
+fn label(n: i32) -> IrLabel {
+  IrLabel { label: n }
+}
+
+#[allow(dead_code)]
+fn ir_function(body: Vec<IrCmd>) -> IrFunction {
+  IrFunction {
+    ident: "main".to_string(),
+    args: vec![],
+    body,
+    ret_ty: IrType::Int(IntTy::I32),
+    overflow: kai_common::overflow_mode::OverflowMode::Wrap,
+    spans: std::collections::HashMap::new(),
+  }
+}
+
+#[test]
+fn test_cfg_single_block() {
+  let ir = ir_function(vec![IrCmd::Return(IrLiteral::Num(0, IntTy::I32))]);
+  let mut cfg = ControlFlowGraph {
+    basic_blocks: &mut vec![],
+  };
+  cfg.build_cfg(&ir);
+
+  assert_eq!(cfg.basic_blocks().len(), 1);
+  assert!(cfg.at(0).ancestors().is_empty());
+  assert!(cfg.at(0).predecessors().is_empty());
+}
+
+#[test]
+fn test_cfg_loop_back_edge() {
+  let cond = IrVar::Temp(1, IrType::Bool, 0);
+  // t0 = 0; while t0 < 10 { t0 = t0 + 1; } return t0;
+  let ir = ir_function(vec![
+    IrCmd::Asgn(tmp(0), expr_from_lit(IrLiteral::Num(0, IntTy::I32))),
+    IrCmd::Goto(label(0)),
+    IrCmd::Label(label(0)),
+    IrCmd::Asgn(
+      cond.clone(),
+      IrExpr::Binop(
+        kai_common::ops::Opcode::Lt,
+        lit_from_var(tmp(0)),
+        IrLiteral::Num(10, IntTy::I32),
+      ),
+    ),
+    IrCmd::Cond(lit_from_var(cond), label(1), label(2)),
+    IrCmd::Label(label(1)),
+    IrCmd::Asgn(
+      tmp(0),
+      IrExpr::Binop(
+        kai_common::ops::Opcode::Add,
+        lit_from_var(tmp(0)),
+        IrLiteral::Num(1, IntTy::I32),
+      ),
+    ),
+    IrCmd::Goto(label(0)),
+    IrCmd::Label(label(2)),
+    IrCmd::Return(lit_from_var(tmp(0))),
+  ]);
+  let mut cfg = ControlFlowGraph {
+    basic_blocks: &mut vec![],
+  };
+  cfg.build_cfg(&ir);
+
+  // entry, header, body, exit
+  assert_eq!(cfg.basic_blocks().len(), 4);
+  let (entry, header, body, exit) = (0, 1, 2, 3);
+  assert_eq!(*cfg.at(entry).ancestors(), vec![header]);
+  assert_eq!(*cfg.at(header).ancestors(), vec![body, exit]);
+  assert_eq!(*cfg.at(body).ancestors(), vec![header]);
+  assert!(cfg.at(exit).ancestors().is_empty());
+
+  // the header is reached both from the entry and from the back edge
+  let mut header_preds = cfg.at(header).predecessors().clone();
+  header_preds.sort();
+  assert_eq!(header_preds, vec![entry, body]);
+
+  // every block is visited exactly once, with the entry last
+  let mut postorder = cfg.postorder();
+  assert_eq!(postorder.last(), Some(&entry));
+  postorder.sort();
+  assert_eq!(postorder, vec![entry, header, body, exit]);
+}
+
+#[allow(dead_code)]
+fn ssa_function(body: Vec<IrCmd>) -> IrFunction {
+  let mut ir = ir_function(body);
+  let mut cfg = ControlFlowGraph {
+    basic_blocks: &mut vec![],
+  };
+  cfg.build_cfg(&ir);
+  let tmp_ty_map = crate::ir_utils::get_tmp_to_type_map(&ir);
+  let tmp_count = tmp_ty_map.keys().max().unwrap() + 1;
+  crate::ssa::gen_ssa(&mut ir, &mut cfg, &tmp_ty_map, tmp_count as usize);
+
+  ir
+}
+
+#[test]
+fn test_ssa_single_definitions() {
+  let cond = IrVar::Temp(1, IrType::Bool, 0);
+  // t0 = 0; if t0 < 1 { t0 = 1; } else { t0 = 2; } return t0;
+  let ir = ssa_function(vec![
+    IrCmd::Asgn(tmp(0), expr_from_lit(IrLiteral::Num(0, IntTy::I32))),
+    IrCmd::Asgn(
+      cond.clone(),
+      IrExpr::Binop(
+        kai_common::ops::Opcode::Lt,
+        lit_from_var(tmp(0)),
+        IrLiteral::Num(1, IntTy::I32),
+      ),
+    ),
+    IrCmd::Cond(lit_from_var(cond), label(0), label(1)),
+    IrCmd::Label(label(0)),
+    IrCmd::Asgn(tmp(0), expr_from_lit(IrLiteral::Num(1, IntTy::I32))),
+    IrCmd::Goto(label(2)),
+    IrCmd::Label(label(1)),
+    IrCmd::Asgn(tmp(0), expr_from_lit(IrLiteral::Num(2, IntTy::I32))),
+    IrCmd::Goto(label(2)),
+    IrCmd::Label(label(2)),
+    IrCmd::Return(lit_from_var(tmp(0))),
+  ]);
+
+  let mut defs = std::collections::HashSet::new();
+  for cmd in &ir.body {
+    if let IrCmd::Asgn(var, _) = cmd {
+      assert!(
+        defs.insert(var.clone()),
+        "{} is defined twice in\n{}",
+        var,
+        ir
+      );
+    }
+  }
+
+  // the join block merges both branches, and returns the merged value
+  let phi = ir
+    .body
+    .iter()
+    .find_map(|cmd| match cmd {
+      IrCmd::Asgn(IrVar::Temp(0, _, version), IrExpr::Phi(incoming)) => Some((*version, incoming)),
+      _ => None,
+    })
+    .expect("expected a phi for t0");
+  let mut incoming_versions: Vec<usize> = phi
+    .1
+    .iter()
+    .map(|(var, _)| match var {
+      IrVar::Temp(_, _, version) => *version,
+      _ => panic!("phi over an ident"),
+    })
+    .collect();
+  incoming_versions.sort();
+  // the versions assigned in each branch
+  let mut branch_versions: Vec<usize> = ir
+    .body
+    .iter()
+    .filter_map(|cmd| match cmd {
+      IrCmd::Asgn(IrVar::Temp(0, _, version), IrExpr::Literal(IrLiteral::Num(n, _))) if *n > 0 => {
+        Some(*version)
+      }
+      _ => None,
+    })
+    .collect();
+  branch_versions.sort();
+  assert_eq!(incoming_versions, branch_versions);
+  match ir.body.last() {
+    Some(IrCmd::Return(IrLiteral::Var(IrVar::Temp(0, _, version)))) => assert_eq!(*version, phi.0),
+    cmd => panic!(
+      "expected the function to end by returning t0, got {:?}",
+      cmd
+    ),
+  }
+}
+
+#[test]
+fn test_ssa_loop_phis() {
+  let cond = IrVar::Temp(1, IrType::Bool, 0);
+  // t0 = 0; while t0 < 10 { t0 = t0 + 1; } return t0;
+  let ir = ssa_function(vec![
+    IrCmd::Asgn(tmp(0), expr_from_lit(IrLiteral::Num(0, IntTy::I32))),
+    IrCmd::Goto(label(0)),
+    IrCmd::Label(label(0)),
+    IrCmd::Asgn(
+      cond.clone(),
+      IrExpr::Binop(
+        kai_common::ops::Opcode::Lt,
+        lit_from_var(tmp(0)),
+        IrLiteral::Num(10, IntTy::I32),
+      ),
+    ),
+    IrCmd::Cond(lit_from_var(cond), label(1), label(2)),
+    IrCmd::Label(label(1)),
+    IrCmd::Asgn(
+      tmp(0),
+      IrExpr::Binop(
+        kai_common::ops::Opcode::Add,
+        lit_from_var(tmp(0)),
+        IrLiteral::Num(1, IntTy::I32),
+      ),
+    ),
+    IrCmd::Goto(label(0)),
+    IrCmd::Label(label(2)),
+    IrCmd::Return(lit_from_var(tmp(0))),
+  ]);
+
+  // blocks are laid out breadth first: entry, header, body, exit
+  let expected = "\
+main(): int
+0: .L0
+1: t0_1(int) = 0
+2: goto .L1
+3: .L1
+4: t0_2(int) = phi([t0_1(int), .L0], [t0_3(int), .L2])
+5: t1_1(bool) = phi([t1_0(bool), .L0], [t1_2(bool), .L2])
+6: t1_2(bool) = t0_2(int) < 10
+7: branch t1_2(bool): .L2, .L3
+8: .L2
+9: t0_3(int) = t0_2(int) + 1
+10: goto .L1
+11: .L3
+12: ret t0_2(int)";
+  assert_eq!(ir.to_string(), expected);
+}
+
+#[allow(dead_code)]
+fn ssa_tmp(n: i32, version: usize) -> IrVar {
+  IrVar::Temp(n, IrType::Int(IntTy::I32), version)
+}
+
+#[allow(dead_code)]
+fn phi(dest: IrVar, incoming: Vec<(IrVar, i32)>) -> IrCmd {
+  IrCmd::Asgn(
+    dest,
+    IrExpr::Phi(incoming.into_iter().map(|(v, l)| (v, label(l))).collect()),
+  )
+}
+
+// the commands between a label and the next label
+#[allow(dead_code)]
+fn block_of(ir: &IrFunction, l: IrLabel) -> Vec<IrCmd> {
+  ir.body
+    .iter()
+    .skip_while(|cmd| !matches!(cmd, IrCmd::Label(l_) if *l_ == l))
+    .skip(1)
+    .take_while(|cmd| !matches!(cmd, IrCmd::Label(_)))
+    .cloned()
+    .collect()
+}
+
+#[test]
+fn test_out_of_ssa_swap() {
+  let cond = IrVar::Temp(3, IrType::Bool, 1);
+  let (a1, a2, b1, b2) = (ssa_tmp(0, 1), ssa_tmp(0, 2), ssa_tmp(1, 1), ssa_tmp(1, 2));
+  let (i1, i2, i3) = (ssa_tmp(2, 1), ssa_tmp(2, 2), ssa_tmp(2, 3));
+  // a, b = 1, 2; for i in 0..3 { a, b = b, a; } return a;
+  let mut ir = ir_function(vec![
+    IrCmd::Label(label(0)),
+    IrCmd::Asgn(a1.clone(), expr_from_lit(IrLiteral::Num(1, IntTy::I32))),
+    IrCmd::Asgn(b1.clone(), expr_from_lit(IrLiteral::Num(2, IntTy::I32))),
+    IrCmd::Asgn(i1.clone(), expr_from_lit(IrLiteral::Num(0, IntTy::I32))),
+    IrCmd::Goto(label(1)),
+    IrCmd::Label(label(1)),
+    phi(a2.clone(), vec![(a1, 0), (b2.clone(), 2)]),
+    phi(b2.clone(), vec![(b1, 0), (a2.clone(), 2)]),
+    phi(i2.clone(), vec![(i1, 0), (i3.clone(), 2)]),
+    IrCmd::Asgn(
+      cond.clone(),
+      IrExpr::Binop(
+        kai_common::ops::Opcode::Lt,
+        lit_from_var(i2.clone()),
+        IrLiteral::Num(3, IntTy::I32),
+      ),
+    ),
+    IrCmd::Cond(lit_from_var(cond), label(2), label(3)),
+    IrCmd::Label(label(2)),
+    IrCmd::Asgn(
+      i3.clone(),
+      IrExpr::Binop(
+        kai_common::ops::Opcode::Add,
+        lit_from_var(i2.clone()),
+        IrLiteral::Num(1, IntTy::I32),
+      ),
+    ),
+    IrCmd::Goto(label(1)),
+    IrCmd::Label(label(3)),
+    IrCmd::Return(lit_from_var(a2.clone())),
+  ]);
+  crate::out_of_ssa::destruct_ssa(&mut ir);
+
+  assert!(
+    !ir
+      .body
+      .iter()
+      .any(|cmd| matches!(cmd, IrCmd::Asgn(_, IrExpr::Phi(_)))),
+    "phis left in\n{}",
+    ir
+  );
+
+  // run the copies on the back edge symbolically, a and b must be swapped
+  let mut values: std::collections::HashMap<IrVar, String> = std::collections::HashMap::new();
+  values.insert(a2.clone(), "a".to_string());
+  values.insert(b2.clone(), "b".to_string());
+  for cmd in block_of(&ir, label(2)) {
+    if let IrCmd::Asgn(dest, IrExpr::Literal(IrLiteral::Var(src))) = cmd {
+      let value = values.get(&src).cloned().unwrap_or_default();
+      values.insert(dest, value);
+    }
+  }
+  assert_eq!(values[&a2], "b", "in\n{}", ir);
+  assert_eq!(values[&b2], "a", "in\n{}", ir);
+}
+
+#[test]
+fn test_out_of_ssa_lost_copy() {
+  let cond = IrVar::Temp(1, IrType::Bool, 1);
+  let (x1, x2, x3) = (ssa_tmp(0, 1), ssa_tmp(0, 2), ssa_tmp(0, 3));
+  // the loop is a single block, so its back edge is critical
+  let mut ir = ir_function(vec![
+    IrCmd::Label(label(0)),
+    IrCmd::Asgn(x1.clone(), expr_from_lit(IrLiteral::Num(1, IntTy::I32))),
+    IrCmd::Goto(label(1)),
+    IrCmd::Label(label(1)),
+    phi(x2.clone(), vec![(x1, 0), (x3.clone(), 1)]),
+    IrCmd::Asgn(
+      x3.clone(),
+      IrExpr::Binop(
+        kai_common::ops::Opcode::Add,
+        lit_from_var(x2.clone()),
+        IrLiteral::Num(1, IntTy::I32),
+      ),
+    ),
+    IrCmd::Asgn(
+      cond.clone(),
+      IrExpr::Binop(
+        kai_common::ops::Opcode::Lt,
+        lit_from_var(x3.clone()),
+        IrLiteral::Num(10, IntTy::I32),
+      ),
+    ),
+    IrCmd::Cond(lit_from_var(cond), label(1), label(2)),
+    IrCmd::Label(label(2)),
+    // x2, not x3: the copy into x2 must not run on the way out of the loop
+    IrCmd::Return(lit_from_var(x2.clone())),
+  ]);
+  crate::out_of_ssa::destruct_ssa(&mut ir);
+
+  let loop_block = block_of(&ir, label(1));
+  assert!(
+    !loop_block
+      .iter()
+      .any(|cmd| matches!(cmd, IrCmd::Asgn(dest, _) if *dest == x2)),
+    "x2 is assigned in the loop block itself in\n{}",
+    ir
+  );
+  let split = match loop_block.last() {
+    Some(IrCmd::Cond(_, split, exit)) => {
+      assert_eq!(*exit, label(2));
+      *split
+    }
+    cmd => panic!("expected the loop to end in a branch, got {:?}", cmd),
+  };
+  let split_block = block_of(&ir, split);
+  assert!(matches!(
+    &split_block[..],
+    [IrCmd::Asgn(dest, IrExpr::Literal(IrLiteral::Var(src))), IrCmd::Goto(l)]
+      if *dest == x2 && *src == x3 && *l == label(1)
+  ));
+}
+
+#[allow(dead_code)]
+fn assert_round_trips(src: &str) {
+  let ir =
+    crate::ir_parse::parse_ir_program(src).unwrap_or_else(|diag| panic!("{}\n{}", diag, src));
+  assert_eq!(ir.to_string(), src);
+}
+
+#[test]
+fn test_ir_parse_round_trip() {
+  assert_round_trips(
+    "\
+f(t0_0(int): int, t1_0(string): string): string
+0: t2_0(int) = t0_0(int) - -5
+1: t3_1(bool) = t2_0(int) <= 2147483647
+2: branch t3_1(bool): .L0, .L1
+3: .L0
+4: t4_0(string) = t1_0(string) . \"a \\\"quoted\\\"\\n\\t\\\\ string\"
+5: ret t4_0(string)
+6: .L1
+7: t5_2(int) = call len(t1_0(string))
+8: goto .L2
+9: .L2
+10: t6_1(bool) = phi([t3_1(bool), .L1], [t3_0(bool), .L0])
+11: t7_0(bool) = t6_1(bool) && false
+12: t8_0(string) = \"\\u{1b}[0m\"
+13: t9_0(int) = neg -1
+14: t10_0(bool) = not t7_0(bool)
+15: t11_0(int) = bitnot t9_0(int)
+16: ret \"\"
+
+main(): int
+0: $x(int) = 1
+1: $t0_1(bool) = $x(int) != 2
+2: t0_0(int) = call f()
+3: ret -2147483648",
+  );
+
+  // every operator prints differently
+  let ops = [
+    "+", "-", "*", "/", "%", ".", "<", "<=", ">", ">=", "&&", "||", "==", "!=", "&", "|", "^",
+    "<<", ">>", ">>>",
+  ];
+  for op in &ops {
+    assert_round_trips(&format!("main(): int\n0: t0_0(int) = 1 {} 2\n1: ret 0", op));
+  }
+  assert_round_trips("empty(): int\n");
+
+  // literals of every type but `int` carry their type as a suffix
+  assert_round_trips(
+    "\
+g(t0_0(u8): u8, $y(i64): i16): u64
+0: t1_0(u8) = t0_0(u8) + 255u8
+1: t2_0(i16) = -32768i16
+2: t3_0(bool) = $y(i64) < -1i64
+3: t4_0(u64) = t0_0(u8) as u64
+4: t5_0(u64) = t4_0(u64) / 18446744073709551615u64
+5: ret t5_0(u64)",
+  );
+
+  // functions that do not wrap around on overflow say so in their header
+  assert_round_trips(
+    "\
+@overflow(trap) h(t0_0(int): int): int
+0: t1_0(int) = neg t0_0(int)
+1: ret t1_0(int)
+
+@overflow(saturate) main(): int
+0: ret 0",
+  );
+}
+
+#[test]
+fn test_ir_parse_handwritten() {
+  // line numbers, indentation and extra blank lines are optional
+  let ir = crate::ir_parse::parse_ir_function(
+    "
+main(): int
+  t0_0(int) = 0
+  goto .L0
+  .L0
+  t1_0(bool) = t0_0(int) < 10
+  branch t1_0(bool): .L1, .L2
+  .L1
+  t0_0(int) = t0_0(int) + 1
+  goto .L0
+  .L2
+  ret t0_0(int)
+
+",
+  )
+  .unwrap();
+  let mut cfg = ControlFlowGraph {
+    basic_blocks: &mut vec![],
+  };
+  cfg.build_cfg(&ir);
+
+  // entry, header, body, exit, like test_cfg_loop_back_edge
+  assert_eq!(cfg.basic_blocks().len(), 4);
+  assert_eq!(ir.body.len(), 10);
+}
+
+#[test]
+fn test_ir_parse_errors() {
+  use crate::ir_parse::{parse_ir_function, parse_ir_program};
+  use kai_common::diagnostics::{Span, E_SYNTAX};
+
+  let src = "main(): int\n0: t0_0(int) = 1 +\n1: ret 0";
+  let diag = parse_ir_program(src).unwrap_err();
+  assert_eq!(diag.code, E_SYNTAX);
+  assert_eq!(diag.message, "expected a variable, found end of line");
+  assert_eq!(diag.span, Some(Span::new(30, 30)));
+
+  let src = "main(): int\nret t0_0(float)";
+  let diag = parse_ir_program(src).unwrap_err();
+  assert_eq!(diag.message, "expected a type, found `float)`");
+  assert_eq!(&src[diag.span.unwrap().lo..diag.span.unwrap().hi], "float)");
+
+  let diag = parse_ir_program("main(): int\ngoto .L0 .L1").unwrap_err();
+  assert_eq!(diag.message, "expected end of line, found `.L1`");
+
+  let diag = parse_ir_program("main(): int\nret \"abc").unwrap_err();
+  assert_eq!(diag.message, "unterminated string");
+
+  let src = "main(): int\nret 256u8";
+  let diag = parse_ir_program(src).unwrap_err();
+  assert_eq!(
+    diag.message,
+    "expected an int that fits in u8, found `256u8`"
+  );
+  assert_eq!(&src[diag.span.unwrap().lo..diag.span.unwrap().hi], "256u8");
+
+  let diag = parse_ir_function("f(): int\n\ng(): int").unwrap_err();
+  assert_eq!(diag.message, "expected exactly one function, found 2");
+
+  // a keyword's length may not end on a char boundary of what follows
+  let diag = parse_ir_program("f(): int\n0: ab€").unwrap_err();
+  assert_eq!(diag.code, E_SYNTAX);
+}
+
+// the message of the first error the verifier finds in src
+#[allow(dead_code)]
+fn verify_error(src: &str, ssa: bool) -> String {
+  let ir = crate::ir_parse::parse_ir_program(src).unwrap();
+  crate::verify::verify_program(&ir, ssa)
+    .expect_err("expected the IR to be malformed")
+    .to_string()
+}
+
+#[test]
+fn test_verify_well_formed() {
+  let ir = crate::ir_parse::parse_ir_program(
+    "\
+f(t0_0(int): int, t1_0(string): string): int
+0: .L0
+1: t2_1(int) = call len(t1_0(string))
+2: t3_1(bool) = t2_1(int) == t0_0(int)
+3: branch t3_1(bool): .L1, .L2
+4: .L1
+5: t4_1(int) = t2_1(int) + 1
+6: goto .L2
+7: .L2
+8: t4_2(int) = phi([t4_0(int), .L0], [t4_1(int), .L1])
+9: t5_1(int) = call f(t4_2(int), \"a\")
+10: ret t5_1(int)",
+  )
+  .unwrap();
+  assert_eq!(crate::verify::verify_program(&ir, true), Ok(()));
+  // phis are only allowed in SSA form
+  assert_eq!(
+    crate::verify::verify_program(&ir, false)
+      .unwrap_err()
+      .message,
+    "phi outside of SSA form"
+  );
+}
+
+#[test]
+fn test_verify_errors() {
+  let cases = [
+    (
+      "main(): int\n0: goto .L0\n1: .L0\n2: goto .L1",
+      "in `main`, line 2: jump to undefined label `.L1`",
+    ),
+    (
+      "main(): int\n0: goto .L0\n1: .L0\n2: goto .L0\n3: .L0\n4: ret 0",
+      "in `main`, line 3: label `.L0` is defined more than once",
+    ),
+    (
+      "main(): int\n0: t0_0(int) = 1\n1: .L0\n2: ret 0",
+      "in `main`, line 1: block falls through into `.L0` without a jump",
+    ),
+    (
+      "main(): int\n0: ret 0\n1: t0_0(int) = 1",
+      "in `main`, line 1: `t0_0(int) = 1` comes after the end of its block",
+    ),
+    (
+      "main(): int\n0: t0_0(int) = 1",
+      "in `main`: function ends without a jump or return",
+    ),
+    (
+      "main(): int\n0: t0_0(int) = 1 + true\n1: ret 0",
+      "in `main`, line 0: `+` takes integer operands, but `true` is bool",
+    ),
+    (
+      "main(): int\n0: t0_0(bool) = 1 == \"1\"\n1: ret 0",
+      "in `main`, line 0: `==` on operands of different types, int and string",
+    ),
+    (
+      "main(): int\n0: t0_0(int) = 1 < 2\n1: ret 0",
+      "in `main`, line 0: `t0_0(int)` is assigned bool `1 < 2`",
+    ),
+    (
+      "main(): int\n0: t0_0(int) = 1\n1: t0_1(bool) = true\n2: ret 0",
+      "in `main`, line 1: `t0` is used as both int and bool",
+    ),
+    (
+      "main(): int\n0: t0_0(int) = call len(1)\n1: ret 0",
+      "in `main`, line 0: `len` takes string, but `1` is int",
+    ),
+    (
+      "main(): int\n0: t0_0(int) = call main(1)\n1: ret 0",
+      "in `main`, line 0: `main` takes 0 args, but is called with 1",
+    ),
+    (
+      "main(): int\n0: branch 1: .L0, .L0\n1: .L0\n2: ret 0",
+      "in `main`, line 0: branch on `1`, which is not a bool",
+    ),
+    (
+      "main(): int\n0: ret true",
+      "in `main`, line 0: `main` returns int, but `true` is bool",
+    ),
+  ];
+  for (src, expected) in cases.iter() {
+    assert_eq!(verify_error(src, false), *expected, "for\n{}", src);
+  }
+
+  let ssa_cases = [
+    (
+      "main(): int\n0: t0_1(int) = 1\n1: t0_1(int) = 2\n2: ret 0",
+      "in `main`, line 1: `t0_1(int)` is assigned more than once",
+    ),
+    (
+      "main(): int\n0: ret t0_0(int)",
+      "in `main`, line 0: `t0_0(int)` is used but never assigned",
+    ),
+    (
+      "main(): int\n0: t0_1(int) = t0_2(int)\n1: t0_2(int) = 1\n2: ret 0",
+      "in `main`, line 0: `t0_2(int)` is used where its assignment does not dominate",
+    ),
+    (
+      "\
+main(): int
+0: .L0
+1: branch true: .L1, .L2
+2: .L1
+3: t0_1(int) = 1
+4: goto .L2
+5: .L2
+6: ret t0_1(int)",
+      "in `main`, line 6: `t0_1(int)` is used where its assignment does not dominate",
+    ),
+    (
+      "main(): int\n0: t0_1(int) = phi()\n1: ret 0",
+      "in `main`, line 0: phi in the entry block",
+    ),
+    (
+      "main(): int\n0: .L0\n1: t0_1(int) = 1\n2: t0_2(int) = phi()\n3: ret 0",
+      "in `main`, line 2: phi after the start of its block",
+    ),
+    (
+      "\
+main(): int
+0: .L0
+1: t0_1(int) = 1
+2: goto .L1
+3: .L1
+4: t0_2(int) = phi([t0_1(int), .L0], [t0_1(int), .L0])
+5: ret t0_2(int)",
+      "in `main`, line 4: phi has 2 values coming from `.L0`, instead of one",
+    ),
+    (
+      "\
+main(): int
+0: .L0
+1: goto .L1
+2: .L1
+3: t0_2(int) = phi([t0_0(int), .L0], [t0_0(int), .L1])
+4: ret t0_2(int)",
+      "in `main`, line 3: phi has a value coming from `.L1`, which is not a predecessor",
+    ),
+    (
+      "\
+main(): int
+0: .L0
+1: branch true: .L1, .L2
+2: .L1
+3: t0_1(int) = 1
+4: goto .L2
+5: .L2
+6: t0_2(int) = phi([t0_1(int), .L0], [t0_1(int), .L1])
+7: ret t0_2(int)",
+      "in `main`, line 6: phi uses `t0_1(int)` coming from `.L0`, where it is not defined",
+    ),
+  ];
+  for (src, expected) in ssa_cases.iter() {
+    assert_eq!(verify_error(src, true), *expected, "for\n{}", src);
+  }
+}
+
+// runs sccp over the function in src, which is checked to be well-formed SSA before and after,
+// along with the errors found in it by `const_traps`
+#[allow(dead_code)]
+fn sccp(src: &str) -> (IrFunction, Vec<kai_common::diagnostics::Diagnostic>) {
+  let mut ir = crate::ir_parse::parse_ir_function(src).unwrap();
+  crate::verify::verify_function(&ir, true).unwrap();
+  let diags = crate::sccp::const_traps(&ir);
+  crate::sccp::sccp(&mut ir);
+  crate::verify::verify_function(&ir, true).unwrap_or_else(|err| panic!("{}\n{}", err, ir));
+  (ir, diags)
+}
+
+#[test]
+fn test_sccp_folds_constants() {
+  // 1 + 2 * 4 - 8 / 2
+  let (ir, diags) = sccp(
+    "\
+main(): int
+0: .L0
+1: t2_1(int) = 2 * 4
+2: t1_1(int) = 1 + t2_1(int)
+3: t3_1(int) = 8 / 2
+4: t0_1(int) = t1_1(int) - t3_1(int)
+5: t4_1(bool) = t0_1(int) == 5
+6: t5_1(int) = call f(t0_1(int), t4_1(bool))
+7: ret t0_1(int)",
+  );
+  assert!(diags.is_empty());
+  assert_eq!(
+    ir.to_string(),
+    "\
+main(): int
+0: .L0
+1: t2_1(int) = 8
+2: t1_1(int) = 9
+3: t3_1(int) = 4
+4: t0_1(int) = 5
+5: t4_1(bool) = true
+6: t5_1(int) = call f(5, true)
+7: ret 5"
+  );
+}
+
+#[test]
+fn test_sccp_folds_bitwise() {
+  // shift amounts are taken modulo 32, negative ones included
+  let (ir, diags) = sccp(
+    "\
+main(): int
+0: .L0
+1: t0_1(int) = 1 << 33
+2: t1_1(int) = -16 >> 2
+3: t2_1(int) = -16 >>> 28
+4: t3_1(int) = 1 << -1
+5: t4_1(int) = 12 & 10
+6: t5_1(int) = 12 | 10
+7: t6_1(int) = 12 ^ 10
+8: t7_1(int) = bitnot t6_1(int)
+9: t8_1(int) = neg -2147483648
+10: t9_1(bool) = not true
+11: ret t7_1(int)",
+  );
+  assert!(diags.is_empty());
+  assert_eq!(
+    ir.to_string(),
+    "\
+main(): int
+0: .L0
+1: t0_1(int) = 2
+2: t1_1(int) = -4
+3: t2_1(int) = 15
+4: t3_1(int) = -2147483648
+5: t4_1(int) = 8
+6: t5_1(int) = 14
+7: t6_1(int) = 6
+8: t7_1(int) = -7
+9: t8_1(int) = -2147483648
+10: t9_1(bool) = false
+11: ret -7"
+  );
+}
+
+#[test]
+fn test_sccp_folds_sized_ints() {
+  // wrapping, division, comparisons and shifts follow the type of the operands
+  let (ir, diags) = sccp(
+    "\
+main(): int
+0: .L0
+1: t0_1(u8) = 200u8 + 100u8
+2: t1_1(u32) = 0u32 - 1u32
+3: t2_1(u32) = t1_1(u32) / 2u32
+4: t3_1(bool) = t1_1(u32) > 1u32
+5: t4_1(i8) = -128i8 >> 1i8
+6: t5_1(u8) = 128u8 >> 1u8
+7: t6_1(i8) = 200u8 as i8
+8: t7_1(u64) = -1 as u64
+9: t8_1(i64) = t1_1(u32) as i64
+10: ret 0",
+  );
+  assert!(diags.is_empty());
+  assert_eq!(
+    ir.to_string(),
+    "\
+main(): int
+0: .L0
+1: t0_1(u8) = 44u8
+2: t1_1(u32) = 4294967295u32
+3: t2_1(u32) = 2147483647u32
+4: t3_1(bool) = true
+5: t4_1(i8) = -64i8
+6: t5_1(u8) = 64u8
+7: t6_1(i8) = -56i8
+8: t7_1(u64) = 18446744073709551615u64
+9: t8_1(i64) = 4294967295i64
+10: ret 0"
+  );
+}
+
+#[test]
+fn test_sccp_resolves_branches() {
+  // the else branch is never taken, so x is 1 after the if, not a phi
+  let (ir, diags) = sccp(
+    "\
+f(t9_0(int): int): int
+0: .L0
+1: t0_1(int) = 1
+2: t1_1(bool) = t0_1(int) < 2
+3: branch t1_1(bool): .L1, .L2
+4: .L1
+5: t2_1(int) = t9_0(int) * 2
+6: goto .L3
+7: .L2
+8: t0_2(int) = 2
+9: t2_2(int) = t9_0(int) / 0
+10: goto .L3
+11: .L3
+12: t0_3(int) = phi([t0_1(int), .L1], [t0_2(int), .L2])
+13: t2_3(int) = phi([t2_1(int), .L1], [t2_2(int), .L2])
+14: t3_1(int) = t0_3(int) + t2_3(int)
+15: ret t3_1(int)",
+  );
+  // the division by zero is never run
+  assert!(diags.is_empty());
+  assert_eq!(
+    ir.to_string(),
+    "\
+f(t9_0(int): int): int
+0: .L0
+1: t0_1(int) = 1
+2: t1_1(bool) = true
+3: goto .L1
+4: .L1
+5: t2_1(int) = t9_0(int) * 2
+6: goto .L3
+7: .L3
+8: t2_3(int) = phi([t2_1(int), .L1])
+9: t0_3(int) = 1
+10: t3_1(int) = 1 + t2_3(int)
+11: ret t3_1(int)"
+  );
+}
+
+#[test]
+fn test_sccp_loops() {
+  // i = 0; while i < 10 { x = 10 / i; i = i + 1; } return x + y, where y is always 7
+  let src = "\
+main(): int
+0: .L0
+1: t0_1(int) = 0
+2: t3_1(int) = 7
+3: goto .L1
+4: .L1
+5: t0_2(int) = phi([t0_1(int), .L0], [t0_3(int), .L2])
+6: t2_1(int) = phi([t2_0(int), .L0], [t2_2(int), .L2])
+7: t3_2(int) = phi([t3_1(int), .L0], [t3_2(int), .L2])
+8: t1_1(bool) = t0_2(int) < 10
+9: branch t1_1(bool): .L2, .L3
+10: .L2
+11: t2_2(int) = 10 / t0_2(int)
+12: t0_3(int) = t0_2(int) + 1
+13: goto .L1
+14: .L3
+15: t4_1(int) = t2_1(int) + t3_2(int)
+16: ret t4_1(int)";
+  let (ir, diags) = sccp(src);
+  // i is zero only on the first iteration, which is no reason for an error
+  assert!(diags.is_empty());
+  let body: Vec<String> = ir.body.iter().map(|cmd| cmd.to_string()).collect();
+  assert!(body.contains(&"t3_2(int) = 7".to_string()), "in\n{}", ir);
+  assert!(
+    body.contains(&"t4_1(int) = t2_1(int) + 7".to_string()),
+    "in\n{}",
+    ir
+  );
+  assert!(
+    body.contains(&"branch t1_1(bool): .L2, .L3".to_string()),
+    "in\n{}",
+    ir
+  );
+}
+
+#[test]
+fn test_sccp_division_errors() {
+  use kai_common::diagnostics::{E_DIV_BY_ZERO, E_DIV_OVERFLOW};
+
+  let (_, diags) = sccp(
+    "\
+f(t0_0(int): int): int
+0: .L0
+1: t1_1(int) = 0
+2: t2_1(int) = 7 % t1_1(int)
+3: t3_1(int) = t0_0(int) / t1_1(int)
+4: t4_1(int) = -2147483648 / -1
+5: ret 0",
+  );
+  let messages: Vec<(&str, &str)> = diags
+    .iter()
+    .map(|diag| (diag.code, diag.message.as_str()))
+    .collect();
+  assert_eq!(
+    messages,
+    vec![
+      (E_DIV_BY_ZERO, "attempt to calculate `7 % 0`"),
+      (E_DIV_BY_ZERO, "attempt to divide by zero"),
+      (
+        E_DIV_OVERFLOW,
+        "attempt to calculate `-2147483648 / -1`, which overflows"
+      ),
+    ]
+  );
+  assert_eq!(diags[0].notes.last().unwrap(), "in function `f`");
+}
+
+#[test]
+fn test_sccp_overflow_modes() {
+  use kai_common::diagnostics::E_ARITH_OVERFLOW;
+
+  let body = "\
+0: .L0
+1: t0_1(int) = 2147483647 + 1
+2: t1_1(i8) = neg -128i8
+3: t2_1(u8) = 0u8 - 1u8
+4: t3_1(u64) = 4294967296u64 * 4294967296u64
+5: t4_1(int) = 2 * 3
+6: ret 0";
+
+  let (ir, diags) = sccp(&format!("@overflow(saturate) main(): int\n{}", body));
+  assert!(diags.is_empty());
+  assert_eq!(
+    ir.to_string(),
+    "\
+@overflow(saturate) main(): int
+0: .L0
+1: t0_1(int) = 2147483647
+2: t1_1(i8) = 127i8
+3: t2_1(u8) = 0u8
+4: t3_1(u64) = 18446744073709551615u64
+5: t4_1(int) = 6
+6: ret 0"
+  );
+
+  // ops that always overflow are errors, and are left for the program to trap on
+  let (ir, diags) = sccp(&format!("@overflow(trap) main(): int\n{}", body));
+  let messages: Vec<(&str, &str)> = diags
+    .iter()
+    .map(|diag| (diag.code, diag.message.as_str()))
+    .collect();
+  assert_eq!(
+    messages,
+    vec![
+      (
+        E_ARITH_OVERFLOW,
+        "attempt to calculate `2147483647 + 1`, which overflows"
+      ),
+      (
+        E_ARITH_OVERFLOW,
+        "attempt to negate `-128i8`, which overflows"
+      ),
+      (
+        E_ARITH_OVERFLOW,
+        "attempt to calculate `0u8 - 1u8`, which overflows"
+      ),
+      (
+        E_ARITH_OVERFLOW,
+        "attempt to calculate `4294967296u64 * 4294967296u64`, which overflows"
+      ),
+    ]
+  );
+  assert!(
+    ir.to_string().contains("1: t0_1(int) = 2147483647 + 1"),
+    "{}",
+    ir
+  );
+  assert!(ir.to_string().contains("5: t4_1(int) = 6"), "{}", ir);
+}
+
+#[allow(dead_code)]
+fn run_pass(src: &str, pass: fn(&mut IrFunction)) -> IrFunction {
+  let mut ir = crate::ir_parse::parse_ir_function(src).unwrap();
+  crate::verify::verify_function(&ir, true).unwrap();
+  pass(&mut ir);
+  crate::verify::verify_function(&ir, true).unwrap_or_else(|err| panic!("{}\n{}", err, ir));
+  ir
+}
+
+#[test]
+fn test_dce() {
+  // t3 is only read by t4, which is never read, and the loop phi t5 only reads itself
+  let ir = run_pass(
+    "\
+f(t0_0(int): int): int
+0: .L0
+1: t1_1(int) = t0_0(int) + 1
+2: t2_1(int) = call f(t1_1(int))
+3: t3_1(int) = t0_0(int) * 2
+4: t4_1(int) = t3_1(int) - 1
+5: t6_1(int) = 10 / t0_0(int)
+6: t7_1(int) = 10 / 2
+7: goto .L1
+8: .L1
+9: t5_1(int) = phi([t0_0(int), .L0], [t5_1(int), .L1])
+10: branch true: .L1, .L2
+11: .L2
+12: ret 0",
+    crate::dce::remove_dead_code,
+  );
+  // calls and divisions that may trap are kept
+  assert_eq!(
+    ir.to_string(),
+    "\
+f(t0_0(int): int): int
+0: .L0
+1: t1_1(int) = t0_0(int) + 1
+2: t2_1(int) = call f(t1_1(int))
+3: t6_1(int) = 10 / t0_0(int)
+4: goto .L1
+5: .L1
+6: branch true: .L1, .L2
+7: .L2
+8: ret 0"
+  );
+
+  // arithmetic is only kept when it may trap on overflow
+  let ir = run_pass(
+    "\
+@overflow(trap) f(t0_0(int): int): int
+0: .L0
+1: t1_1(int) = t0_0(int) + 1
+2: t2_1(int) = t0_0(int) & 1
+3: t3_1(int) = neg t0_0(int)
+4: ret 0",
+    crate::dce::remove_dead_code,
+  );
+  assert_eq!(
+    ir.to_string(),
+    "\
+@overflow(trap) f(t0_0(int): int): int
+0: .L0
+1: t1_1(int) = t0_0(int) + 1
+2: t3_1(int) = neg t0_0(int)
+3: ret 0"
+  );
+}
+
+#[test]
+fn test_remove_unreachable_blocks() {
+  // .L2 and the .L3 only it jumps to are unreachable, so the input is not valid SSA yet
+  let mut ir = crate::ir_parse::parse_ir_function(
+    "\
+main(): int
+0: .L0
+1: t0_1(int) = 1
+2: goto .L1
+3: .L1
+4: goto .L4
+5: .L2
+6: t0_2(int) = 2
+7: goto .L3
+8: .L3
+9: goto .L4
+10: .L4
+11: t0_3(int) = phi([t0_1(int), .L1], [t0_2(int), .L3])
+12: ret t0_3(int)",
+  )
+  .unwrap();
+  crate::simplify_cfg::remove_unreachable_blocks(&mut ir, &mut Analyses::new());
+  crate::verify::verify_function(&ir, true).unwrap_or_else(|err| panic!("{}\n{}", err, ir));
+  assert_eq!(
+    ir.to_string(),
+    "\
+main(): int
+0: .L0
+1: t0_1(int) = 1
+2: goto .L1
+3: .L1
+4: goto .L4
+5: .L4
+6: t0_3(int) = phi([t0_1(int), .L1])
+7: ret t0_3(int)"
+  );
+}
+
+#[test]
+fn test_merge_blocks() {
+  // .L1 and .L4 merge into .L0, and the loop header .L2 into nothing
+  let ir = run_pass(
+    "\
+f(t0_0(int): int): int
+0: .L0
+1: goto .L1
+2: .L1
+3: t1_1(int) = t0_0(int) + 1
+4: goto .L4
+5: .L2
+6: t2_1(int) = phi([t1_2(int), .L4], [t2_2(int), .L2])
+7: t2_2(int) = t2_1(int) + 1
+8: branch true: .L2, .L3
+9: .L3
+10: ret t2_2(int)
+11: .L4
+12: t1_2(int) = phi([t1_1(int), .L1])
+13: goto .L2",
+    crate::simplify_cfg::merge_blocks,
+  );
+  assert_eq!(
+    ir.to_string(),
+    "\
+f(t0_0(int): int): int
+0: .L0
+1: t1_1(int) = t0_0(int) + 1
+2: t1_2(int) = t1_1(int)
+3: goto .L2
+4: .L2
+5: t2_1(int) = phi([t1_2(int), .L0], [t2_2(int), .L2])
+6: t2_2(int) = t2_1(int) + 1
+7: branch true: .L2, .L3
+8: .L3
+9: ret t2_2(int)"
+  );
+
+  // a phi of an undefined var cannot become a copy, so .L1 is left alone
+  let src = "\
+main(): int
+0: .L0
+1: goto .L1
+2: .L1
+3: t0_1(int) = phi([t0_0(int), .L0])
+4: ret 0";
+  let ir = run_pass(src, crate::simplify_cfg::merge_blocks);
+  assert_eq!(ir.to_string(), src);
+}
+
+#[test]
+fn test_gvn() {
+  // a * b + b * a, then a * b again in both arms of an if, with a > b as b < a
+  let ir = run_pass(
+    "\
+f(t0_0(int): int, t1_0(int): int): int
+0: .L0
+1: t2_1(int) = t0_0(int) * t1_0(int)
+2: t3_1(int) = t1_0(int) * t0_0(int)
+3: t4_1(int) = t2_1(int) + t3_1(int)
+4: t5_1(bool) = t0_0(int) > t1_0(int)
+5: branch t5_1(bool): .L1, .L2
+6: .L1
+7: t6_1(bool) = t1_0(int) < t0_0(int)
+8: t7_1(int) = t0_0(int) * t1_0(int)
+9: t8_1(int) = t7_1(int) - 1
+10: goto .L3
+11: .L2
+12: t7_2(int) = t1_0(int) * t0_0(int)
+13: t8_2(int) = t7_2(int) - 2
+14: goto .L3
+15: .L3
+16: t8_3(int) = phi([t8_1(int), .L1], [t8_2(int), .L2])
+17: t9_1(int) = phi([t8_1(int), .L1], [t8_2(int), .L2])
+18: t10_1(int) = t8_3(int) + t9_1(int)
+19: ret t10_1(int)",
+    |func| crate::gvn::gvn(func, &mut Analyses::new()),
+  );
+  assert_eq!(
+    ir.to_string(),
+    "\
+f(t0_0(int): int, t1_0(int): int): int
+0: .L0
+1: t2_1(int) = t0_0(int) * t1_0(int)
+2: t3_1(int) = t2_1(int)
+3: t4_1(int) = t2_1(int) + t2_1(int)
+4: t5_1(bool) = t0_0(int) > t1_0(int)
+5: branch t5_1(bool): .L1, .L2
+6: .L1
+7: t6_1(bool) = t5_1(bool)
+8: t7_1(int) = t2_1(int)
+9: t8_1(int) = t2_1(int) - 1
+10: goto .L3
+11: .L2
+12: t7_2(int) = t2_1(int)
+13: t8_2(int) = t2_1(int) - 2
+14: goto .L3
+15: .L3
+16: t8_3(int) = phi([t8_1(int), .L1], [t8_2(int), .L2])
+17: t9_1(int) = phi([t8_1(int), .L1], [t8_2(int), .L2])
+18: t10_1(int) = t8_3(int) + t8_3(int)
+19: ret t10_1(int)"
+  );
+}
+
+#[test]
+fn test_gvn_scopes_and_phis() {
+  // t3 in .L1 does not dominate .L2, so t4 is not redundant
+  let ir = run_pass(
+    "\
+f(t0_0(int): int): int
+0: .L0
+1: t1_1(int) = t0_0(int)
+2: branch true: .L1, .L2
+3: .L1
+4: t3_1(int) = t1_1(int) + 1
+5: goto .L3
+6: .L2
+7: t4_1(int) = t0_0(int) + 1
+8: goto .L3
+9: .L3
+10: t5_1(int) = phi([t1_1(int), .L1], [t0_0(int), .L2])
+11: t6_1(int) = phi([t6_0(int), .L1], [t4_1(int), .L2])
+12: t7_1(string) = \"a\" . \"b\"
+13: t8_1(string) = \"a\" . \"b\"
+14: t9_1(int) = t5_1(int) + t6_1(int)
+15: ret t9_1(int)",
+    |func| crate::gvn::gvn(func, &mut Analyses::new()),
+  );
+  let body: Vec<String> = ir.body.iter().map(|cmd| cmd.to_string()).collect();
+  assert!(
+    body.contains(&"t4_1(int) = t0_0(int) + 1".to_string()),
+    "in\n{}",
+    ir
+  );
+  // t5 only ever is t0, once the copy in t1 is propagated
+  assert!(
+    body.contains(&"t9_1(int) = t0_0(int) + t6_1(int)".to_string()),
+    "in\n{}",
+    ir
+  );
+  // each concatenation makes a new string
+  assert!(
+    body.contains(&"t8_1(string) = \"a\" . \"b\"".to_string()),
+    "in\n{}",
+    ir
+  );
+}
+
+#[test]
+fn test_liveness() {
+  // s = 0; i = 0; while i < n { s = s + i; i = i + 1; } return s
+  let ir = crate::ir_parse::parse_ir_function(
+    "\
+f(t0_0(int): int): int
+0: .L0
+1: t1_1(int) = 0
+2: t2_1(int) = 0
+3: goto .L1
+4: .L1
+5: t1_2(int) = phi([t1_1(int), .L0], [t1_3(int), .L2])
+6: t2_2(int) = phi([t2_1(int), .L0], [t2_3(int), .L2])
+7: t3_1(bool) = t2_2(int) < t0_0(int)
+8: branch t3_1(bool): .L2, .L3
+9: .L2
+10: t1_3(int) = t1_2(int) + t2_2(int)
+11: t2_3(int) = t2_2(int) + 1
+12: goto .L1
+13: .L3
+14: ret t1_2(int)",
+  )
+  .unwrap();
+  let mut analyses = Analyses::new();
+  let label_of = |bb: &crate::cfg::BasicBlock| bb.cmds()[0].to_string();
+  let bbs: Vec<String> = analyses
+    .cfg(&ir)
+    .basic_blocks()
+    .iter()
+    .map(label_of)
+    .collect();
+  let bb = |l: &str| bbs.iter().position(|bb| bb == l).unwrap();
+
+  let liveness = analyses.liveness(&ir);
+  let sorted = |vars: &std::collections::HashSet<IrVar>| {
+    let mut vars: Vec<String> = vars.iter().map(|var| var.to_string()).collect();
+    vars.sort();
+    vars
+  };
+  // the phi operands are live out of the blocks they come from, but not into the header
+  assert_eq!(
+    sorted(&liveness.live_out[bb(".L0")]),
+    vec!["t0_0(int)", "t1_1(int)", "t2_1(int)"]
+  );
+  assert_eq!(sorted(&liveness.live_in[bb(".L1")]), vec!["t0_0(int)"]);
+  assert_eq!(
+    sorted(&liveness.live_in[bb(".L2")]),
+    vec!["t0_0(int)", "t1_2(int)", "t2_2(int)"]
+  );
+  assert_eq!(
+    sorted(&liveness.live_out[bb(".L2")]),
+    vec!["t0_0(int)", "t1_3(int)", "t2_3(int)"]
+  );
+  assert_eq!(sorted(&liveness.live_in[bb(".L3")]), vec!["t1_2(int)"]);
+  assert!(liveness.live_out[bb(".L3")].is_empty());
+}
+
+#[test]
+fn test_pass_manager() {
+  use crate::pass_manager::{Analysis, PassManager};
+  use kai_common::opt_level::OptLevel;
+
+  // whether a program compiles does not depend on the level
+  assert_eq!(
+    PassManager::for_opt_level(OptLevel::O0).pass_names(),
+    vec!["const-traps"]
+  );
+  assert_eq!(
+    PassManager::for_opt_level(OptLevel::O1).pass_names(),
+    vec![
+      "const-traps",
+      "sccp",
+      "remove-unreachable-blocks",
+      "merge-blocks",
+      "gvn",
+      "dce"
+    ]
+  );
+  assert_eq!(
+    PassManager::for_opt_level(OptLevel::O2).pass_names().len(),
+    11
+  );
+
+  // analyses are cached until invalidated, even if the function changed
+  let mut ir = crate::ir_parse::parse_ir_function(
+    "\
+main(): int
+0: .L0
+1: t0_1(int) = 1
+2: ret t0_1(int)",
+  )
+  .unwrap();
+  let mut analyses = Analyses::new();
+  assert_eq!(analyses.liveness(&ir).live_in[0].len(), 0);
+  ir.body[1] = IrCmd::Return(IrLiteral::Var(IrVar::Temp(1, IrType::Int(IntTy::I32), 0)));
+  analyses.invalidate(&[Analysis::Liveness]);
+  assert_eq!(analyses.liveness(&ir).live_in[0].len(), 0);
+  analyses.invalidate(&[]);
+  assert_eq!(analyses.liveness(&ir).live_in[0].len(), 1);
+
+  // the passes after one reporting errors are not run, so t1 is not removed as dead
+  let mut program = crate::ir::IrProgram {
+    functions: vec![crate::ir_parse::parse_ir_function(
+      "\
+main(): int
+0: .L0
+1: t0_1(int) = 1 / 0
+2: t1_1(int) = 2
+3: ret 0",
+    )
+    .unwrap()],
+  };
+  let diags = PassManager::for_opt_level(OptLevel::O1).run(&mut program);
+  assert_eq!(diags.len(), 1);
+  assert!(program.functions[0].to_string().contains("t1_1(int) = 2"));
+}
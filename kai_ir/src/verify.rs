@@ -0,0 +1,534 @@
+use crate::cfg::ControlFlowGraph;
+use crate::ir::*;
+use crate::ir_utils;
+use crate::ssa;
+use kai_ast::ast;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/*
+ * Checks that IR is well-formed, to catch passes producing IR that later
+ * passes or the backend would silently miscompile:
+ * - every label is defined once, and every jump goes to a defined label
+ * - every block ends in a jump or return, and nothing else does
+ * - every operand has the type its operator, callee or command expects, and
+ *   every assignment assigns a value of the dest's type
+ * - every temp has the same type everywhere
+ *
+ * In SSA form also:
+ * - every temp version is assigned once, and its assignment dominates its
+ *   uses. version 0 of a temp that is not an arg is undefined, which only a
+ *   phi may use, for a var that is not assigned along that edge
+ * - phis are at the start of their block, with one value coming from each
+ *   predecessor of the block and from nothing else
+ *
+ * Outside SSA form there are no phis. Unreachable blocks are not checked
+ * for dominance, since they have no dominators.
+ */
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct VerifyError {
+  pub func: String,
+  // index of the offending command in the body, as the IR is printed
+  pub line: Option<usize>,
+  pub message: String,
+}
+
+// arg types and return type of each function that can be called
+type FnSigs = HashMap<String, (Vec<IrType>, IrType)>;
+
+struct Verifier<'a> {
+  func: &'a IrFunction,
+  sigs: &'a FnSigs,
+}
+
+pub fn verify_program(ir: &IrProgram, ssa: bool) -> Result<(), VerifyError> {
+  let mut sigs = builtin_sigs();
+  for func in &ir.functions {
+    sigs.insert(func.ident.clone(), fn_sig(func));
+  }
+
+  for func in &ir.functions {
+    Verifier { func, sigs: &sigs }.verify(ssa)?;
+  }
+
+  Ok(())
+}
+
+// calls to functions other than builtins and func itself are not checked
+pub fn verify_function(func: &IrFunction, ssa: bool) -> Result<(), VerifyError> {
+  let mut sigs = builtin_sigs();
+  sigs.insert(func.ident.clone(), fn_sig(func));
+
+  Verifier { func, sigs: &sigs }.verify(ssa)
+}
+
+// in debug builds, panics if the IR that came out of pass is malformed
+pub fn debug_verify_program(ir: &IrProgram, ssa: bool, pass: &str) {
+  if cfg!(debug_assertions) {
+    if let Err(err) = verify_program(ir, ssa) {
+      panic!("malformed IR after {}: {}\n{}", pass, err, ir);
+    }
+  }
+}
+
+pub fn debug_verify_function(func: &IrFunction, ssa: bool, pass: &str) {
+  if cfg!(debug_assertions) {
+    if let Err(err) = verify_function(func, ssa) {
+      panic!("malformed IR after {}: {}\n{}", pass, err, func);
+    }
+  }
+}
+
+fn builtin_sigs() -> FnSigs {
+  let ir_type = |ty: &ast::Type| match ty {
+    ast::Type::Int(int_ty) => IrType::Int(*int_ty),
+    ast::Type::Bool => IrType::Bool,
+    ast::Type::Str => IrType::Str,
+    _ => panic!("verify: builtins only take and return ints, bools and strings"),
+  };
+
+  let mut sigs = HashMap::new();
+  for (ident, fn_ty) in kai_ast::builtins::builtin_fn_types() {
+    if let ast::Type::FnType(arg_tys, ret_ty) = fn_ty {
+      let arg_tys = arg_tys.iter().map(|ty| ir_type(ty)).collect();
+      sigs.insert(ident, (arg_tys, ir_type(&ret_ty)));
+    }
+  }
+
+  sigs
+}
+
+fn fn_sig(func: &IrFunction) -> (Vec<IrType>, IrType) {
+  (func.args.iter().map(|arg| arg.ty).collect(), func.ret_ty)
+}
+
+// the vars cmd reads, not counting phi operands, which are read on the edge into the block
+fn uses(cmd: &IrCmd) -> Vec<&IrVar> {
+  match cmd {
+    IrCmd::Asgn(_, IrExpr::Phi(_)) => vec![],
+    _ => ir_utils::used_vars(cmd),
+  }
+}
+
+fn is_terminator(cmd: &IrCmd) -> bool {
+  matches!(cmd, IrCmd::Goto(_) | IrCmd::Cond(..) | IrCmd::Return(_))
+}
+
+// whether every path from the entry to v goes through u
+fn dominates(dominators: &[usize], u: usize, v: usize) -> bool {
+  let mut runner = v;
+  loop {
+    if runner == u {
+      return true;
+    }
+    if dominators[runner] == runner {
+      return false;
+    }
+    runner = dominators[runner];
+  }
+}
+
+impl<'a> Verifier<'a> {
+  fn error(&self, line: Option<usize>, message: String) -> VerifyError {
+    VerifyError {
+      func: self.func.ident.clone(),
+      line,
+      message,
+    }
+  }
+
+  fn verify(&self, ssa: bool) -> Result<(), VerifyError> {
+    self.verify_blocks(ssa)?;
+    self.verify_types()?;
+    if ssa {
+      // needs the blocks to be well-formed to build the CFG
+      self.verify_ssa()?;
+    }
+
+    Ok(())
+  }
+
+  fn verify_blocks(&self, ssa: bool) -> Result<(), VerifyError> {
+    let body = &self.func.body;
+    let mut labels = HashSet::new();
+    for (line, cmd) in body.iter().enumerate() {
+      if let IrCmd::Label(l) = cmd {
+        if !labels.insert(*l) {
+          return Err(self.error(
+            Some(line),
+            format!("label `{}` is defined more than once", l),
+          ));
+        }
+      }
+    }
+
+    // the label of the block being scanned, None for an unlabeled entry block
+    let mut block_label = None;
+    // phis may only come before anything else in their block
+    let mut at_block_start = true;
+    let mut ended = false;
+    for (line, cmd) in body.iter().enumerate() {
+      let targets = match cmd {
+        IrCmd::Goto(l) => vec![*l],
+        IrCmd::Cond(_, l1, l2) => vec![*l1, *l2],
+        _ => vec![],
+      };
+      if let Some(l) = targets.iter().find(|l| !labels.contains(*l)) {
+        return Err(self.error(Some(line), format!("jump to undefined label `{}`", l)));
+      }
+
+      match cmd {
+        IrCmd::Label(l) => {
+          if line > 0 && !ended {
+            return Err(self.error(
+              Some(line),
+              format!("block falls through into `{}` without a jump", l),
+            ));
+          }
+          block_label = Some(*l);
+          at_block_start = true;
+          ended = false;
+          continue;
+        }
+        _ if ended => {
+          return Err(self.error(
+            Some(line),
+            format!("`{}` comes after the end of its block", cmd),
+          ));
+        }
+        IrCmd::Asgn(_, IrExpr::Phi(_)) if !ssa => {
+          return Err(self.error(Some(line), "phi outside of SSA form".to_string()));
+        }
+        IrCmd::Asgn(_, IrExpr::Phi(_)) if block_label.is_none() => {
+          return Err(self.error(Some(line), "phi in the entry block".to_string()));
+        }
+        IrCmd::Asgn(_, IrExpr::Phi(_)) if !at_block_start => {
+          return Err(self.error(Some(line), "phi after the start of its block".to_string()));
+        }
+        IrCmd::Asgn(_, IrExpr::Phi(_)) => {}
+        _ => at_block_start = false,
+      }
+      ended = is_terminator(cmd);
+    }
+
+    if !ended {
+      return Err(self.error(None, "function ends without a jump or return".to_string()));
+    }
+
+    Ok(())
+  }
+
+  fn verify_types(&self) -> Result<(), VerifyError> {
+    // the type each temp (of any version) or ident was first seen with
+    let mut var_tys: HashMap<String, IrType> = HashMap::new();
+    let mut check_var = |line: Option<usize>, var: &IrVar| {
+      let name = match var {
+        IrVar::Ident(ident, _) => format!("${}", ident),
+        IrVar::Temp(n, _, _) => format!("t{}", n),
+      };
+      let ty = *var_tys.entry(name.clone()).or_insert(var_ty(var));
+      if ty != var_ty(var) {
+        return Err(self.error(
+          line,
+          format!("`{}` is used as both {} and {}", name, ty, var_ty(var)),
+        ));
+      }
+      Ok(())
+    };
+
+    for arg in &self.func.args {
+      if arg.ty != var_ty(&arg.ident) {
+        return Err(self.error(
+          None,
+          format!("arg `{}` is declared as {}", arg.ident, arg.ty),
+        ));
+      }
+      check_var(None, &arg.ident)?;
+    }
+
+    for (line, cmd) in self.func.body.iter().enumerate() {
+      for var in uses(cmd) {
+        check_var(Some(line), var)?;
+      }
+      match cmd {
+        IrCmd::Asgn(var, expr) => {
+          check_var(Some(line), var)?;
+          if let IrExpr::Phi(incoming) = expr {
+            for (src, _) in incoming {
+              check_var(Some(line), src)?;
+            }
+          }
+          self.verify_asgn_types(line, var, expr)?;
+        }
+        IrCmd::Cond(lit, _, _) if lit_ty(lit) != IrType::Bool => {
+          return Err(self.error(
+            Some(line),
+            format!("branch on `{}`, which is not a bool", lit),
+          ));
+        }
+        IrCmd::Return(lit) if lit_ty(lit) != self.func.ret_ty => {
+          return Err(self.error(
+            Some(line),
+            format!(
+              "`{}` returns {}, but `{}` is {}",
+              self.func.ident,
+              self.func.ret_ty,
+              lit,
+              lit_ty(lit)
+            ),
+          ));
+        }
+        _ => {}
+      }
+    }
+
+    Ok(())
+  }
+
+  fn verify_asgn_types(&self, line: usize, var: &IrVar, expr: &IrExpr) -> Result<(), VerifyError> {
+    let ty = match expr {
+      IrExpr::Literal(lit) => lit_ty(lit),
+      IrExpr::Binop(op, lit1, lit2) => {
+        let args = gen_op_args(*op);
+        if let Some(lit) = [lit1, lit2].iter().find(|lit| !args.accepts(lit_ty(lit))) {
+          return Err(self.error(
+            Some(line),
+            format!(
+              "`{}` takes {} operands, but `{}` is {}",
+              op,
+              args,
+              lit,
+              lit_ty(lit)
+            ),
+          ));
+        }
+        if lit_ty(lit1) != lit_ty(lit2) {
+          return Err(self.error(
+            Some(line),
+            format!(
+              "`{}` on operands of different types, {} and {}",
+              op,
+              lit_ty(lit1),
+              lit_ty(lit2)
+            ),
+          ));
+        }
+        gen_op_result_type(*op, lit_ty(lit1))
+      }
+      IrExpr::Unop(op, lit) => {
+        let args = gen_unop_args(*op);
+        if !args.accepts(lit_ty(lit)) {
+          return Err(self.error(
+            Some(line),
+            format!(
+              "`{}` takes {} operands, but `{}` is {}",
+              op,
+              args,
+              lit,
+              lit_ty(lit)
+            ),
+          ));
+        }
+        lit_ty(lit)
+      }
+      IrExpr::Cast(lit, ty) => {
+        if let Some(ty) = [lit_ty(lit), *ty]
+          .iter()
+          .find(|ty| !matches!(ty, IrType::Int(_)))
+        {
+          return Err(self.error(
+            Some(line),
+            format!("`as` only converts between integer types, not {}", ty),
+          ));
+        }
+        *ty
+      }
+      IrExpr::Call(ident, args) => match self.sigs.get(ident) {
+        Some((arg_tys, ret_ty)) => {
+          if args.len() != arg_tys.len() {
+            return Err(self.error(
+              Some(line),
+              format!(
+                "`{}` takes {} args, but is called with {}",
+                ident,
+                arg_tys.len(),
+                args.len()
+              ),
+            ));
+          }
+          for (arg, arg_ty) in args.iter().zip(arg_tys) {
+            if lit_ty(arg) != *arg_ty {
+              return Err(self.error(
+                Some(line),
+                format!(
+                  "`{}` takes {}, but `{}` is {}",
+                  ident,
+                  arg_ty,
+                  arg,
+                  lit_ty(arg)
+                ),
+              ));
+            }
+          }
+          *ret_ty
+        }
+        None => var_ty(var),
+      },
+      IrExpr::Phi(incoming) => match incoming.iter().find(|(src, _)| var_ty(src) != var_ty(var)) {
+        Some((src, _)) => var_ty(src),
+        None => var_ty(var),
+      },
+    };
+
+    if ty != var_ty(var) {
+      return Err(self.error(
+        Some(line),
+        format!("`{}` is assigned {} `{}`", var, ty, expr),
+      ));
+    }
+
+    Ok(())
+  }
+
+  fn verify_ssa(&self) -> Result<(), VerifyError> {
+    let mut cfg = ControlFlowGraph {
+      basic_blocks: &mut vec![],
+    };
+    cfg.build_cfg(self.func);
+    let dominators = ssa::dominators(&cfg);
+
+    // the block and line each temp version is assigned on, args on no line
+    let mut defs: HashMap<&IrVar, (usize, Option<usize>)> = HashMap::new();
+    for arg in &self.func.args {
+      defs.insert(&arg.ident, (0, None));
+    }
+    for (u, bb) in cfg.basic_blocks().iter().enumerate() {
+      let start = bb.cmd_range().0;
+      for (i, cmd) in self.func.body[start..start + bb.cmds().len()]
+        .iter()
+        .enumerate()
+      {
+        if let IrCmd::Asgn(var, _) = cmd {
+          if defs.insert(var, (u, Some(start + i))).is_some() {
+            return Err(self.error(
+              Some(start + i),
+              format!("`{}` is assigned more than once", var),
+            ));
+          }
+        }
+      }
+    }
+
+    for (u, bb) in cfg.basic_blocks().iter().enumerate() {
+      let start = bb.cmd_range().0;
+      for (i, cmd) in bb.cmds().iter().enumerate() {
+        let line = start + i;
+        for var in uses(cmd) {
+          let dominated = match defs.get(var) {
+            None => {
+              return Err(self.error(Some(line), format!("`{}` is used but never assigned", var)));
+            }
+            Some((_, None)) => true,
+            Some((def_bb, Some(def_line))) if *def_bb == u => *def_line < line,
+            Some((def_bb, _)) => dominates(&dominators, *def_bb, u),
+          };
+          if !dominated {
+            return Err(self.error(
+              Some(line),
+              format!("`{}` is used where its assignment does not dominate", var),
+            ));
+          }
+        }
+
+        if let IrCmd::Asgn(_, IrExpr::Phi(incoming)) = cmd {
+          self.verify_phi(&cfg, &dominators, &defs, u, line, incoming)?;
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  // checks a phi in block u has exactly one value coming from each of its predecessors
+  fn verify_phi(
+    &self,
+    cfg: &ControlFlowGraph,
+    dominators: &[usize],
+    defs: &HashMap<&IrVar, (usize, Option<usize>)>,
+    u: usize,
+    line: usize,
+    incoming: &[(IrVar, IrLabel)],
+  ) -> Result<(), VerifyError> {
+    let block_label = |v: usize| match cfg.at(v).cmds().first() {
+      Some(IrCmd::Label(l)) => Some(*l),
+      _ => None,
+    };
+
+    for pred in cfg.at(u).predecessors() {
+      let count = incoming
+        .iter()
+        .filter(|(_, l)| Some(*l) == block_label(*pred))
+        .count();
+      if count != 1 {
+        let pred = match block_label(*pred) {
+          Some(l) => format!("`{}`", l),
+          None => "the entry block".to_string(),
+        };
+        return Err(self.error(
+          Some(line),
+          format!(
+            "phi has {} values coming from {}, instead of one",
+            count, pred
+          ),
+        ));
+      }
+    }
+
+    for (src, l) in incoming {
+      let pred = match cfg
+        .at(u)
+        .predecessors()
+        .iter()
+        .find(|pred| block_label(**pred) == Some(*l))
+      {
+        Some(pred) => *pred,
+        None => {
+          return Err(self.error(
+            Some(line),
+            format!(
+              "phi has a value coming from `{}`, which is not a predecessor",
+              l
+            ),
+          ));
+        }
+      };
+
+      // the value is read at the end of pred, so it may be assigned anywhere in it
+      let defined = match defs.get(src) {
+        None => matches!(src, IrVar::Temp(_, _, 0)),
+        Some((def_bb, _)) => dominates(dominators, *def_bb, pred),
+      };
+      if !defined {
+        return Err(self.error(
+          Some(line),
+          format!(
+            "phi uses `{}` coming from `{}`, where it is not defined",
+            src, l
+          ),
+        ));
+      }
+    }
+
+    Ok(())
+  }
+}
+
+impl fmt::Display for VerifyError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self.line {
+      Some(line) => write!(f, "in `{}`, line {}: {}", self.func, line, self.message),
+      None => write!(f, "in `{}`: {}", self.func, self.message),
+    }
+  }
+}
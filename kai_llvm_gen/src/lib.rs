@@ -1,29 +1,149 @@
 extern crate llvm_sys as llvm;
 
+use std::collections::{HashMap, HashSet};
 use std::os::raw::c_ulong;
-use std::{collections::HashMap, path::Path};
 use std::{
   ffi::{CStr, CString},
-  path::PathBuf,
+  path::Path,
 };
 
 use llvm::core::*;
 use llvm::*;
 
-use kai_common::ops::Opcode;
+use kai_common::diagnostics::{Diagnostic, SourceMap, Span, E_EMIT};
+use kai_common::int_ty::IntTy;
+use kai_common::ops::{Opcode, UnOpcode};
+use kai_common::opt_level::OptLevel;
+use kai_common::overflow_mode::OverflowMode;
 use kai_ir::ir::*;
 
-struct KaiLlvmResult {
+pub mod link;
+
+// runtime helper used to lower `Opcode::Concat`
+const RUNTIME_STR_CONCAT: &str = "kai_str_concat";
+
+// runtime helper aborting the program with a message, see `gen_check`
+const RUNTIME_PANIC: &str = "kai_panic";
+
+// symbol of the shim calling the Kai `main`, see `gen_entry_shim`
+const ENTRY_SYMBOL: &str = "main";
+
+struct KaiLlvmResult<'a> {
   llctx: *mut LLVMContext,
   llmodule: *mut LLVMModule,
   llbuilder: *mut LLVMBuilder,
   ll_tmp_counter: usize,
+  // vars assigned more than once (i.e. IR that is not in SSA form) live in allocas,
+  // all others are mapped straight to the value that defines them
+  multi_def_vars: HashSet<IrVar>,
   var_to_alloca: HashMap<IrVar, *mut LLVMValue>,
+  var_to_value: HashMap<IrVar, *mut LLVMValue>,
+  // phis get their incoming values once the whole function has been generated,
+  // as they may refer to values defined further down
+  pending_phis: Vec<(*mut LLVMValue, Vec<(IrVar, IrLabel)>)>,
   label_to_block: HashMap<IrLabel, *mut LLVMBasicBlock>,
+  // the block each label's code ends in, which differs from its own when checks split it
+  block_ends: HashMap<IrLabel, *mut LLVMBasicBlock>,
+  fn_map: HashMap<String, *mut LLVMValue>,
+  // to point runtime errors at the source
+  source_map: &'a SourceMap,
+  // the overflow mode and spans of the function being generated
+  overflow: OverflowMode,
+  spans: HashMap<i32, Span>,
 }
 
-impl KaiLlvmResult {
-  pub unsafe fn gen_llvm_ir(&mut self, ir: &IrFunction) -> &KaiLlvmResult {
+impl<'a> KaiLlvmResult<'a> {
+  pub unsafe fn gen_llvm_program(&mut self, ir: &IrProgram) -> &Self {
+    self.declare_runtime_fns();
+
+    // declare every function up front, so that bodies can refer to functions
+    // defined later in the module
+    for ir_func in &ir.functions {
+      self.declare_llvm_function(ir_func);
+    }
+
+    for ir_func in &ir.functions {
+      self.gen_llvm_ir(ir_func);
+    }
+
+    if let Some(kai_main) = ir.functions.iter().find(|f| is_entry_point(f)) {
+      self.gen_entry_shim(kai_main);
+    }
+
+    self
+  }
+
+  /*
+   * The C `main` the program starts in, it calls the Kai `main` and exits
+   * with its result. Programs without a `function main(): int` get none, so
+   * that they can still be compiled to objects and linked with other code.
+   */
+  unsafe fn gen_entry_shim(&mut self, kai_main: &IrFunction) {
+    let int_ty = self.ir_to_ll_type(IrType::Int(IntTy::I32));
+    let fn_type = LLVMFunctionType(int_ty, std::ptr::null_mut(), 0, 0);
+    let shim = llvm::core::LLVMAddFunction(self.llmodule, c_str(ENTRY_SYMBOL), fn_type);
+
+    let bb = LLVMAppendBasicBlockInContext(self.llctx, shim, c_str("entry"));
+    LLVMPositionBuilderAtEnd(self.llbuilder, bb);
+    let callee = *self.fn_map.get(&kai_main.ident).unwrap();
+    let res = LLVMBuildCall(
+      self.llbuilder,
+      callee,
+      std::ptr::null_mut(),
+      0,
+      self.get_and_incr_ll_temp(),
+    );
+    LLVMBuildRet(self.llbuilder, res);
+  }
+
+  /*
+   * declares the functions implemented in runtime/kai_runtime.c
+   *
+   * builtins callable from Kai are registered under their Kai name, helpers
+   * only used by codegen under their symbol name
+   */
+  unsafe fn declare_runtime_fns(&mut self) {
+    let runtime_fns = vec![
+      (
+        "len",
+        "kai_str_len",
+        vec![IrType::Str],
+        IrType::Int(IntTy::I32),
+      ),
+      (
+        RUNTIME_STR_CONCAT,
+        RUNTIME_STR_CONCAT,
+        vec![IrType::Str, IrType::Str],
+        IrType::Str,
+      ),
+    ];
+
+    for (ident, symbol, arg_tys, ret_ty) in runtime_fns {
+      let mut ll_arg_tys: Vec<*mut LLVMType> =
+        arg_tys.iter().map(|ty| self.ir_to_ll_type(*ty)).collect();
+      let fn_type = LLVMFunctionType(
+        self.ir_to_ll_type(ret_ty),
+        ll_arg_tys.as_mut_ptr(),
+        ll_arg_tys.len() as u32,
+        0,
+      );
+      let ll_function = llvm::core::LLVMAddFunction(self.llmodule, c_str(symbol), fn_type);
+      self.fn_map.insert(ident.to_string(), ll_function);
+    }
+
+    // returns nothing, which no `IrType` stands for
+    let mut ll_arg_tys = vec![self.ir_to_ll_type(IrType::Str)];
+    let fn_type = LLVMFunctionType(
+      LLVMVoidTypeInContext(self.llctx),
+      ll_arg_tys.as_mut_ptr(),
+      ll_arg_tys.len() as u32,
+      0,
+    );
+    let ll_function = llvm::core::LLVMAddFunction(self.llmodule, c_str(RUNTIME_PANIC), fn_type);
+    self.fn_map.insert(RUNTIME_PANIC.to_string(), ll_function);
+  }
+
+  unsafe fn declare_llvm_function(&mut self, ir: &IrFunction) -> *mut LLVMValue {
     let ret_ty = self.ir_to_ll_type(ir.ret_ty);
     let mut arg_tys: Vec<*mut LLVMType> = (&ir.args)
       .into_iter()
@@ -31,7 +151,25 @@ impl KaiLlvmResult {
       .collect();
 
     let fn_type = LLVMFunctionType(ret_ty, arg_tys.as_mut_ptr(), arg_tys.len() as u32, 0);
-    let ll_function = llvm::core::LLVMAddFunction(self.llmodule, c_str(&ir.ident), fn_type);
+    let ll_function =
+      llvm::core::LLVMAddFunction(self.llmodule, c_str(&mangle(&ir.ident)), fn_type);
+    self.fn_map.insert(ir.ident.clone(), ll_function);
+
+    ll_function
+  }
+
+  pub unsafe fn gen_llvm_ir(&mut self, ir: &IrFunction) -> &Self {
+    let ll_function = *self.fn_map.get(&ir.ident).unwrap();
+
+    // temps and labels are only unique within a single function
+    self.multi_def_vars = find_multi_def_vars(ir);
+    self.var_to_alloca.clear();
+    self.var_to_value.clear();
+    self.pending_phis.clear();
+    self.label_to_block.clear();
+    self.block_ends.clear();
+    self.overflow = ir.overflow;
+    self.spans = ir.spans.clone();
 
     let alloca_bb = LLVMAppendBasicBlockInContext(
       self.llctx,
@@ -40,106 +178,378 @@ impl KaiLlvmResult {
     );
     LLVMPositionBuilderAtEnd(self.llbuilder, alloca_bb);
 
-    // allocate all fn args
+    // fn args are the version of each arg that is live on entry
     for i in 0..ir.args.len() {
-      self.new_alloca(&ir.args[i].ident, ll_function);
+      let param = LLVMGetParam(ll_function, i as u32);
+      self.define_var(&ir.args[i].ident, param, ll_function);
     }
 
-    // let entry_bb = LLVMAppendBasicBlockInContext(
-    //   self.llctx,
-    //   ll_function,
-    //   c_str(&["entry", ir.ident.as_str()].join("_")),
-    // );
-    // LLVMPositionBuilderAtEnd(self.llbuilder, entry_bb);
-    // then store all fn args into allocated slots
-    for i in 0..ir.args.len() {
-      let alloca_for_arg = self.var_to_alloca.get(&ir.args[i].ident).unwrap();
-      LLVMBuildStore(
-        self.llbuilder,
-        LLVMGetParam(ll_function, i as u32),
-        *alloca_for_arg,
+    self.gen_llvm_body(&ir.body, ll_function);
+
+    for (phi, incoming) in std::mem::take(&mut self.pending_phis) {
+      let mut values: Vec<*mut LLVMValue> = incoming
+        .iter()
+        .map(|(var, _)| self.ssa_value(var))
+        .collect();
+      let mut blocks: Vec<*mut LLVMBasicBlock> = incoming
+        .iter()
+        .map(|(_, label)| match self.block_ends.get(label) {
+          Some(bb) => *bb,
+          None => self.get_or_create_bb(label, ll_function),
+        })
+        .collect();
+      LLVMAddIncoming(
+        phi,
+        values.as_mut_ptr(),
+        blocks.as_mut_ptr(),
+        incoming.len() as u32,
       );
     }
 
-    self.gen_llvm_body(&ir.body, ll_function);
-
     self
   }
 
   unsafe fn gen_llvm_body(&mut self, ir_cmds: &Vec<IrCmd>, ll_function: *mut LLVMValue) {
     let mut current_bb = LLVMGetEntryBasicBlock(ll_function);
     LLVMPositionBuilderAtEnd(self.llbuilder, current_bb);
+    // whether current_bb already ends in a jump or return
+    let mut terminated = false;
+    // none in the entry block, before the first label
+    let mut current_label: Option<IrLabel> = None;
 
     for cmd in ir_cmds {
       match cmd {
         IrCmd::Asgn(var, expr) => self.gen_llvm_asgn(var, expr, ll_function),
         IrCmd::Label(l) => {
-          current_bb = self.get_or_create_bb(l, ll_function);
+          let bb = self.get_or_create_bb(l, ll_function);
+          // fall through into the labelled block, e.g. from the entry block
+          if !terminated {
+            self.end_block(current_label);
+            LLVMBuildBr(self.llbuilder, bb);
+          }
+          current_bb = bb;
+          current_label = Some(*l);
           LLVMPositionBuilderAtEnd(self.llbuilder, current_bb);
+          terminated = false;
         }
         IrCmd::Goto(l) => {
           let bb = self.get_or_create_bb(l, ll_function);
+          self.end_block(current_label);
           LLVMBuildBr(self.llbuilder, bb);
+          terminated = true;
         }
         IrCmd::Cond(c, l1, l2) => {
           let bb1 = self.get_or_create_bb(l1, ll_function);
           let bb2 = self.get_or_create_bb(l2, ll_function);
+          self.end_block(current_label);
           LLVMBuildCondBr(self.llbuilder, self.gen_llvm_lit(c, ll_function), bb1, bb2);
+          terminated = true;
         }
         IrCmd::Return(lit) => {
+          self.end_block(current_label);
           LLVMBuildRet(self.llbuilder, self.gen_llvm_lit(lit, ll_function));
+          terminated = true;
         }
       }
     }
+
+    // control can never reach the end of a function that typechecked
+    if !terminated {
+      LLVMBuildUnreachable(self.llbuilder);
+    }
   }
 
-  // unsafe fn gen_llvm_basic_block(
-  //   &mut self,
-  //   cfg: &ControlFlowGraph,
-  //   bb_index: usize,
-  //   ll_bbs: Vec<*mut LLVMBasicBlock>,
-  // ) {
-  //   let bb = cfg.at(bb_index);
-  //   LLVMPositionBuilderAtEnd(self.llbuilder, ll_bbs[bb_index]);
-  //   for cmd in bb.cmds() {
-  //     match cmd {
-  //       IrCmd::Asgn(var, expr) => self.gen_llvm_asgn(var, expr, bb_index, ll_bbs),
-  //       // IrCmd::Label(IrLabel), // keep a counter
-  //       // IrCmd::Goto(IrLabel),
-  //       // IrCmd::Cond(IrLiteral, IrLabel, IrLabel),
-  //       // IrCmd::Return(IrExpr),
-  //     }
-  //   }
-  // }
+  // records the block the builder is in as where the label's code ends, for phis
+  unsafe fn end_block(&mut self, label: Option<IrLabel>) {
+    if let Some(l) = label {
+      self
+        .block_ends
+        .insert(l, LLVMGetInsertBlock(self.llbuilder));
+    }
+  }
 
   unsafe fn gen_llvm_asgn(&mut self, var: &IrVar, expr: &IrExpr, ll_function: *mut LLVMValue) {
-    match expr {
-      IrExpr::Literal(lit) => {
-        let alloca = self.get_or_create_alloca(var, ll_function);
-        let lit = self.gen_llvm_lit(lit, ll_function);
-
-        LLVMBuildStore(self.llbuilder, lit, alloca);
+    let value = match expr {
+      IrExpr::Literal(lit) => self.gen_llvm_lit(lit, ll_function),
+      IrExpr::Binop(Opcode::Concat, lit1, lit2) => {
+        let concat_fn = *self.fn_map.get(RUNTIME_STR_CONCAT).unwrap();
+        let mut ll_args = vec![
+          self.gen_llvm_lit(lit1, ll_function),
+          self.gen_llvm_lit(lit2, ll_function),
+        ];
+        LLVMBuildCall(
+          self.llbuilder,
+          concat_fn,
+          ll_args.as_mut_ptr(),
+          ll_args.len() as u32,
+          tmp_c_str(var),
+        )
       }
       IrExpr::Binop(op, lit1, lit2) => {
+        let arg_ty = lit_ty(lit1);
+        let lit1 = self.gen_llvm_lit(lit1, ll_function);
+        let lit2 = self.gen_llvm_lit(lit2, ll_function);
+        let signed = is_signed(arg_ty);
         if is_predicate(*op) {
-          let alloca = self.get_or_create_alloca(var, ll_function);
-          let lit1 = self.gen_llvm_lit(lit1, ll_function);
-          let lit2 = self.gen_llvm_lit(lit2, ll_function);
-          let binop_tmp = self.get_and_incr_ll_temp();
-          let binop_inst =
-            LLVMBuildICmp(self.llbuilder, to_ll_predicate(*op), lit1, lit2, binop_tmp);
-          LLVMBuildStore(self.llbuilder, binop_inst, alloca);
+          LLVMBuildICmp(
+            self.llbuilder,
+            to_ll_predicate(*op, signed),
+            lit1,
+            lit2,
+            tmp_c_str(var),
+          )
+        } else if is_shift(*op) {
+          /*
+           * LLVM shifts by at least the number of bits are poison, so the
+           * amount is taken modulo it, the same as `wrapping_shl` in Rust
+           */
+          let bits = match arg_ty {
+            IrType::Int(int_ty) => int_ty.bits(),
+            ty => panic!("cannot shift {}", ty),
+          };
+          let mask = LLVMConstInt(self.ir_to_ll_type(arg_ty), (bits - 1) as c_ulong, 0);
+          let amount = LLVMBuildAnd(self.llbuilder, lit2, mask, self.get_and_incr_ll_temp());
+          LLVMBuildBinOp(
+            self.llbuilder,
+            to_ll_binop(*op, signed),
+            lit1,
+            amount,
+            tmp_c_str(var),
+          )
+        } else if matches!(op, Opcode::Add | Opcode::Sub | Opcode::Mul)
+          && self.overflow != OverflowMode::Wrap
+        {
+          self.gen_overflow_op(*op, lit1, lit2, arg_ty, var, overflow_message(*op))
         } else {
-          let alloca = self.get_or_create_alloca(var, ll_function);
-          let lit1 = self.gen_llvm_lit(lit1, ll_function);
-          let lit2 = self.gen_llvm_lit(lit2, ll_function);
-          let binop_tmp = self.get_and_incr_ll_temp();
-          let binop_inst = LLVMBuildBinOp(self.llbuilder, to_ll_binop(*op), lit1, lit2, binop_tmp);
-          LLVMBuildStore(self.llbuilder, binop_inst, alloca);
+          if matches!(op, Opcode::Div | Opcode::Mod) {
+            self.gen_div_checks(*op, lit1, lit2, arg_ty, var);
+          }
+          LLVMBuildBinOp(
+            self.llbuilder,
+            to_ll_binop(*op, signed),
+            lit1,
+            lit2,
+            tmp_c_str(var),
+          )
+        }
+      }
+      IrExpr::Unop(op, ir_lit) => {
+        let lit = self.gen_llvm_lit(ir_lit, ll_function);
+        match op {
+          UnOpcode::Neg if self.overflow != OverflowMode::Wrap => {
+            let ty = lit_ty(ir_lit);
+            let zero = LLVMConstInt(self.ir_to_ll_type(ty), 0, 0);
+            self.gen_overflow_op(
+              Opcode::Sub,
+              zero,
+              lit,
+              ty,
+              var,
+              "attempt to negate with overflow",
+            )
+          }
+          UnOpcode::Neg => LLVMBuildNeg(self.llbuilder, lit, tmp_c_str(var)),
+          // bools are i1, so flipping every bit is logical not
+          UnOpcode::Not | UnOpcode::BitNot => LLVMBuildNot(self.llbuilder, lit, tmp_c_str(var)),
         }
       }
-      IrExpr::Phi(_) => panic!("phi not supported"),
+      IrExpr::Cast(lit, ty) => {
+        let signed = is_signed(lit_ty(lit));
+        let lit = self.gen_llvm_lit(lit, ll_function);
+        let dest_ty = self.ir_to_ll_type(*ty);
+        // truncates, or extends with the sign of the source type
+        LLVMBuildIntCast2(self.llbuilder, lit, dest_ty, signed as i32, tmp_c_str(var))
+      }
+      IrExpr::Call(ident, args) => {
+        let callee = *self.fn_map.get(ident).unwrap();
+        let mut ll_args: Vec<*mut LLVMValue> = args
+          .iter()
+          .map(|arg| self.gen_llvm_lit(arg, ll_function))
+          .collect();
+        LLVMBuildCall(
+          self.llbuilder,
+          callee,
+          ll_args.as_mut_ptr(),
+          ll_args.len() as u32,
+          tmp_c_str(var),
+        )
+      }
+      IrExpr::Phi(incoming) => {
+        let phi = LLVMBuildPhi(
+          self.llbuilder,
+          self.ir_to_ll_type(var_ty(var)),
+          tmp_c_str(var),
+        );
+        self.pending_phis.push((phi, incoming.clone()));
+        phi
+      }
+    };
+
+    self.define_var(var, value, ll_function);
+  }
+
+  /*
+   * `+`, `-` or `*` checked for overflow, which aborts the program with msg
+   * in `OverflowMode::Trap`, or saturating in `OverflowMode::Saturate`, via
+   * the LLVM intrinsics overloaded on the operands' type.
+   */
+  unsafe fn gen_overflow_op(
+    &mut self,
+    op: Opcode,
+    lit1: *mut LLVMValue,
+    lit2: *mut LLVMValue,
+    ty: IrType,
+    var: &IrVar,
+    msg: &str,
+  ) -> *mut LLVMValue {
+    let sign = if is_signed(ty) { "s" } else { "u" };
+    let op_name = match op {
+      Opcode::Add => "add",
+      Opcode::Sub => "sub",
+      Opcode::Mul => "mul",
+      op => panic!("`{}` cannot overflow", op),
+    };
+    let mut args = vec![lit1, lit2];
+
+    if self.overflow == OverflowMode::Saturate {
+      // there is no `mul.sat`, but fixed point multiplication with no fractional bits is one
+      let name = if op == Opcode::Mul {
+        args.push(LLVMConstInt(LLVMInt32TypeInContext(self.llctx), 0, 0));
+        format!("llvm.{}mul.fix.sat", sign)
+      } else {
+        format!("llvm.{}{}.sat", sign, op_name)
+      };
+      let intrinsic = self.intrinsic(&name, ty);
+      return LLVMBuildCall(
+        self.llbuilder,
+        intrinsic,
+        args.as_mut_ptr(),
+        args.len() as u32,
+        tmp_c_str(var),
+      );
     }
+
+    // returns the wrapped result, and whether it overflowed
+    let intrinsic = self.intrinsic(&format!("llvm.{}{}.with.overflow", sign, op_name), ty);
+    let res = LLVMBuildCall(
+      self.llbuilder,
+      intrinsic,
+      args.as_mut_ptr(),
+      args.len() as u32,
+      self.get_and_incr_ll_temp(),
+    );
+    let overflowed = LLVMBuildExtractValue(self.llbuilder, res, 1, self.get_and_incr_ll_temp());
+    self.gen_check(overflowed, msg, var);
+
+    LLVMBuildExtractValue(self.llbuilder, res, 0, tmp_c_str(var))
+  }
+
+  // division by zero, or of the smallest signed int by -1, traps in every overflow mode
+  unsafe fn gen_div_checks(
+    &mut self,
+    op: Opcode,
+    lit1: *mut LLVMValue,
+    lit2: *mut LLVMValue,
+    ty: IrType,
+    var: &IrVar,
+  ) {
+    let ll_ty = self.ir_to_ll_type(ty);
+    let zero = LLVMConstInt(ll_ty, 0, 0);
+    let by_zero = LLVMBuildICmp(
+      self.llbuilder,
+      LLVMIntPredicate::LLVMIntEQ,
+      lit2,
+      zero,
+      self.get_and_incr_ll_temp(),
+    );
+    let msg = match op {
+      Opcode::Div => "attempt to divide by zero",
+      _ => "attempt to calculate the remainder with a divisor of zero",
+    };
+    self.gen_check(by_zero, msg, var);
+
+    let int_ty = match ty {
+      IrType::Int(int_ty) if int_ty.is_signed() => int_ty,
+      _ => return,
+    };
+    let min = LLVMConstInt(ll_ty, int_ty.min() as c_ulong, 1);
+    let minus_one = LLVMConstInt(ll_ty, -1i64 as c_ulong, 1);
+    let is_min = LLVMBuildICmp(
+      self.llbuilder,
+      LLVMIntPredicate::LLVMIntEQ,
+      lit1,
+      min,
+      self.get_and_incr_ll_temp(),
+    );
+    let is_minus_one = LLVMBuildICmp(
+      self.llbuilder,
+      LLVMIntPredicate::LLVMIntEQ,
+      lit2,
+      minus_one,
+      self.get_and_incr_ll_temp(),
+    );
+    let overflows = LLVMBuildAnd(
+      self.llbuilder,
+      is_min,
+      is_minus_one,
+      self.get_and_incr_ll_temp(),
+    );
+    let msg = match op {
+      Opcode::Div => "attempt to divide with overflow",
+      _ => "attempt to calculate the remainder with overflow",
+    };
+    self.gen_check(overflows, msg, var);
+  }
+
+  /*
+   * Branches to a block calling `kai_panic` with msg if failed is true, and
+   * carries on in a new block otherwise. msg is prefixed with where the op
+   * assigned to var is in the source, if its span is known.
+   */
+  unsafe fn gen_check(&mut self, failed: *mut LLVMValue, msg: &str, var: &IrVar) {
+    let location = match var {
+      IrVar::Temp(n, _, _) => self
+        .spans
+        .get(n)
+        .and_then(|span| self.source_map.location(*span)),
+      IrVar::Ident(_, _) => None,
+    };
+    let msg = match location {
+      Some(location) => format!("{}: {}", location, msg),
+      None => msg.to_string(),
+    };
+
+    let ll_function = LLVMGetBasicBlockParent(LLVMGetInsertBlock(self.llbuilder));
+    let panic_bb = LLVMAppendBasicBlockInContext(self.llctx, ll_function, c_str("panic"));
+    let cont_bb = LLVMAppendBasicBlockInContext(self.llctx, ll_function, c_str("cont"));
+    LLVMBuildCondBr(self.llbuilder, failed, panic_bb, cont_bb);
+
+    LLVMPositionBuilderAtEnd(self.llbuilder, panic_bb);
+    let panic_fn = *self.fn_map.get(RUNTIME_PANIC).unwrap();
+    let ll_temp = self.get_and_incr_ll_temp();
+    let mut ll_args = vec![LLVMBuildGlobalStringPtr(
+      self.llbuilder,
+      c_str(&msg),
+      ll_temp,
+    )];
+    // calls returning void cannot be named
+    LLVMBuildCall(
+      self.llbuilder,
+      panic_fn,
+      ll_args.as_mut_ptr(),
+      ll_args.len() as u32,
+      c_str(""),
+    );
+    LLVMBuildUnreachable(self.llbuilder);
+
+    LLVMPositionBuilderAtEnd(self.llbuilder, cont_bb);
+  }
+
+  // the declaration of an intrinsic overloaded on the type of its operands
+  unsafe fn intrinsic(&self, name: &str, ty: IrType) -> *mut LLVMValue {
+    let id = LLVMLookupIntrinsicID(c_str(name), name.len());
+    let mut ll_tys = vec![self.ir_to_ll_type(ty)];
+    LLVMGetIntrinsicDeclaration(self.llmodule, id, ll_tys.as_mut_ptr(), ll_tys.len())
   }
 
   unsafe fn gen_llvm_lit(
@@ -148,13 +558,40 @@ impl KaiLlvmResult {
     ll_function: *mut LLVMValue,
   ) -> *mut LLVMValue {
     match lit {
-      IrLiteral::Num(n) => LLVMConstInt(self.ir_to_ll_type(IrType::Int), *n as c_ulong, 0),
+      IrLiteral::Num(n, int_ty) => LLVMConstInt(
+        self.ir_to_ll_type(IrType::Int(*int_ty)),
+        *n as c_ulong,
+        int_ty.is_signed() as i32,
+      ),
       IrLiteral::Bool(b) => LLVMConstInt(self.ir_to_ll_type(IrType::Bool), *b as c_ulong, 0),
-      IrLiteral::Var(var) => {
+      IrLiteral::Str(s) => {
+        let ll_temp = self.get_and_incr_ll_temp();
+        LLVMBuildGlobalStringPtr(self.llbuilder, c_str(s), ll_temp)
+      }
+      IrLiteral::Var(var) if self.multi_def_vars.contains(var) => {
         let alloc = self.get_or_create_alloca(var, ll_function);
         let ll_temp = self.get_and_incr_ll_temp();
         LLVMBuildLoad(self.llbuilder, alloc, ll_temp)
       }
+      IrLiteral::Var(var) => self.ssa_value(var),
+    }
+  }
+
+  unsafe fn define_var(&mut self, var: &IrVar, value: *mut LLVMValue, ll_function: *mut LLVMValue) {
+    if self.multi_def_vars.contains(var) {
+      let alloca = self.get_or_create_alloca(var, ll_function);
+      LLVMBuildStore(self.llbuilder, value, alloca);
+    } else {
+      self.var_to_value.insert(var.clone(), value);
+    }
+  }
+
+  // a var with no definition is one that is not assigned on every path, e.g.
+  // version 0 of a temp flowing into a phi from the entry block
+  unsafe fn ssa_value(&self, var: &IrVar) -> *mut LLVMValue {
+    match self.var_to_value.get(var) {
+      Some(value) => *value,
+      None => LLVMGetUndef(self.ir_to_ll_type(var_ty(var))),
     }
   }
 
@@ -202,8 +639,9 @@ impl KaiLlvmResult {
 
   unsafe fn ir_to_ll_type(&self, ty: IrType) -> *mut LLVMType {
     match ty {
-      IrType::Int => LLVMInt32TypeInContext(self.llctx),
+      IrType::Int(int_ty) => LLVMIntTypeInContext(self.llctx, int_ty.bits()),
       IrType::Bool => LLVMInt1TypeInContext(self.llctx),
+      IrType::Str => LLVMPointerType(LLVMInt8TypeInContext(self.llctx), 0),
       IrType::Addr => LLVMInt64TypeInContext(self.llctx),
     }
   }
@@ -215,21 +653,35 @@ impl KaiLlvmResult {
   }
 }
 
-fn to_ll_binop(op: Opcode) -> LLVMOpcode {
+fn to_ll_binop(op: Opcode, signed: bool) -> LLVMOpcode {
   match op {
+    Opcode::Div if !signed => LLVMOpcode::LLVMUDiv,
+    Opcode::Mod if !signed => LLVMOpcode::LLVMURem,
+    // `>>` on unsigned ints is a logical shift
+    Opcode::Shr if !signed => LLVMOpcode::LLVMLShr,
     Opcode::Add => LLVMOpcode::LLVMAdd,
     Opcode::Sub => LLVMOpcode::LLVMSub,
     Opcode::Mul => LLVMOpcode::LLVMMul,
     Opcode::Div => LLVMOpcode::LLVMSDiv,
     Opcode::Mod => LLVMOpcode::LLVMSRem,
+    Opcode::BitAnd => LLVMOpcode::LLVMAnd,
+    Opcode::BitOr => LLVMOpcode::LLVMOr,
+    Opcode::BitXor => LLVMOpcode::LLVMXor,
+    Opcode::Shl => LLVMOpcode::LLVMShl,
+    Opcode::Shr => LLVMOpcode::LLVMAShr,
+    Opcode::LShr => LLVMOpcode::LLVMLShr,
     Opcode::LogAnd => panic!("LogAnd should be short circuited now"),
     Opcode::LogOr => panic!("LogOr should be short circuited now"),
     op => panic!("cannot convert `{:?}` to binop", op),
   }
 }
 
-fn to_ll_predicate(op: Opcode) -> LLVMIntPredicate {
+fn to_ll_predicate(op: Opcode, signed: bool) -> LLVMIntPredicate {
   match op {
+    Opcode::Lt if !signed => LLVMIntPredicate::LLVMIntULT,
+    Opcode::Leq if !signed => LLVMIntPredicate::LLVMIntULE,
+    Opcode::Gt if !signed => LLVMIntPredicate::LLVMIntUGT,
+    Opcode::Geq if !signed => LLVMIntPredicate::LLVMIntUGE,
     Opcode::Lt => LLVMIntPredicate::LLVMIntSLT,
     Opcode::Leq => LLVMIntPredicate::LLVMIntSLE,
     Opcode::Gt => LLVMIntPredicate::LLVMIntSGT,
@@ -240,6 +692,23 @@ fn to_ll_predicate(op: Opcode) -> LLVMIntPredicate {
   }
 }
 
+// what a program trapping on overflow reports, see `gen_overflow_op`
+fn overflow_message(op: Opcode) -> &'static str {
+  match op {
+    Opcode::Add => "attempt to add with overflow",
+    Opcode::Sub => "attempt to subtract with overflow",
+    Opcode::Mul => "attempt to multiply with overflow",
+    op => panic!("`{}` cannot overflow", op),
+  }
+}
+
+fn is_shift(op: Opcode) -> bool {
+  match op {
+    Opcode::Shl | Opcode::Shr | Opcode::LShr => true,
+    _ => false,
+  }
+}
+
 fn is_predicate(op: Opcode) -> bool {
   match op {
     Opcode::Lt => true,
@@ -252,6 +721,29 @@ fn is_predicate(op: Opcode) -> bool {
   }
 }
 
+// bools and strings count as unsigned, which only matters for their `==` and `!=`
+fn is_signed(ty: IrType) -> bool {
+  match ty {
+    IrType::Int(int_ty) => int_ty.is_signed(),
+    _ => false,
+  }
+}
+
+// fn args count as a definition, since they are live on entry
+fn find_multi_def_vars(ir: &IrFunction) -> HashSet<IrVar> {
+  let mut def_vars: HashSet<IrVar> = ir.args.iter().map(|arg| arg.ident.clone()).collect();
+  let mut multi_def_vars = HashSet::new();
+  for cmd in &ir.body {
+    if let IrCmd::Asgn(var, _) = cmd {
+      if !def_vars.insert(var.clone()) {
+        multi_def_vars.insert(var.clone());
+      }
+    }
+  }
+
+  multi_def_vars
+}
+
 fn tmp_c_str(var: &IrVar) -> *const i8 {
   match var {
     IrVar::Temp(var, _, version) => {
@@ -265,13 +757,40 @@ fn ll_tmp_c_str(tmp: usize) -> *const i8 {
   c_str(&format!("ll_{}", tmp))
 }
 
+/*
+ * Symbol of a Kai function. Kai idents cannot contain `.`, so the prefix
+ * keeps them apart from C symbols, e.g. `main`, libc or the runtime.
+ */
+pub fn mangle(ident: &str) -> String {
+  format!("kai.{}", ident)
+}
+
+fn is_entry_point(ir: &IrFunction) -> bool {
+  ir.ident == "main" && ir.args.is_empty() && ir.ret_ty == IrType::Int(IntTy::I32)
+}
+
 fn c_str(s: &str) -> *const i8 {
   let c_string = CString::new(s).expect("CString::new failed");
   c_string.into_raw()
 }
 
-pub unsafe fn llvm_ir_gen(ir: &IrFunction, filename: &str) -> *mut LLVMModule {
-  //*mut llvm_sys::LLVMModule {
+/**
+ * Generates an LLVM module for the program. source_map is only used to point
+ * runtime errors at the source, and may be empty.
+ *
+ * # Safety
+ *
+ * ir must pass `kai_ir::verify`, in or out of SSA form, since instructions
+ * are built from its vars and labels without checking them. The module is
+ * created in a context of its own that is never disposed, so the pointer
+ * returned stays valid for the rest of the process, and is the caller's to
+ * pass to the other functions here.
+ */
+pub unsafe fn llvm_ir_gen(
+  ir: &IrProgram,
+  filename: &str,
+  source_map: &SourceMap,
+) -> *mut LLVMModule {
   let context = LLVMContextCreate();
   let module = LLVMModuleCreateWithNameInContext(c_str(filename), context);
   let builder = LLVMCreateBuilderInContext(context);
@@ -281,41 +800,169 @@ pub unsafe fn llvm_ir_gen(ir: &IrFunction, filename: &str) -> *mut LLVMModule {
     llmodule: module,
     llbuilder: builder,
     ll_tmp_counter: 0,
+    multi_def_vars: HashSet::new(),
     var_to_alloca: HashMap::new(),
+    var_to_value: HashMap::new(),
+    pending_phis: vec![],
     label_to_block: HashMap::new(),
+    block_ends: HashMap::new(),
+    fn_map: HashMap::new(),
+    source_map,
+    overflow: OverflowMode::default(),
+    spans: HashMap::new(),
   };
 
-  let module = kai_llvm_result.gen_llvm_ir(ir);
-  // LLVMDumpModule(module.llmodule);
+  let module = kai_llvm_result.gen_llvm_program(ir);
 
   module.llmodule
 }
 
-pub unsafe fn llvm_gen(ir: &IrFunction, pathbuf: &mut PathBuf) {
-  let filename = pathbuf.file_name().unwrap().to_str().unwrap();
-  let ll_module = llvm_ir_gen(ir, filename);
+// the LLVM passes `run_llvm_passes` can run
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LlvmPass {
+  // promotes the allocas of vars assigned more than once to SSA values
+  Mem2Reg,
+  InstCombine,
+  SimplifyCfg,
+  Reassociate,
+  Gvn,
+  Inline,
+  AggressiveInstCombine,
+}
+
+/*
+ * The LLVM passes run at each `-O` level, after the IR passes. mem2reg,
+ * instruction combining and CFG simplification are cheap, and clean up
+ * what codegen leaves behind. `-O2` and up also inline functions and run
+ * GVN over the result, and `-O3` combines patterns spanning several
+ * instructions on top of that.
+ */
+pub fn llvm_pipeline(opt_level: OptLevel) -> Vec<LlvmPass> {
+  let mut passes = vec![];
+  if opt_level >= OptLevel::O1 {
+    passes.extend(&[
+      LlvmPass::Mem2Reg,
+      LlvmPass::InstCombine,
+      LlvmPass::SimplifyCfg,
+    ]);
+  }
+  if opt_level >= OptLevel::O2 {
+    passes.extend(&[
+      LlvmPass::Inline,
+      LlvmPass::Reassociate,
+      LlvmPass::InstCombine,
+      LlvmPass::Gvn,
+      LlvmPass::SimplifyCfg,
+    ]);
+  }
+  if opt_level >= OptLevel::O3 {
+    passes.extend(&[LlvmPass::AggressiveInstCombine, LlvmPass::InstCombine]);
+  }
+
+  passes
+}
+
+/**
+ * Runs the given passes over the whole module, in order.
+ *
+ * # Safety
+ *
+ * ll_module must point to a live module, e.g. one returned by `llvm_ir_gen`,
+ * that nothing else is using or changing while the passes run.
+ */
+pub unsafe fn run_llvm_passes(ll_module: *mut LLVMModule, passes: &[LlvmPass]) {
+  if passes.is_empty() {
+    return;
+  }
 
-  // let ll_pass_manager = llvm::core::LLVMCreateFunctionPassManagerForModule(ll_module);
-  // llvm::transforms::util::LLVMAddPromoteMemoryToRegisterPass(ll_pass_manager);
+  let pm = LLVMCreatePassManager();
+  for pass in passes {
+    match pass {
+      LlvmPass::Mem2Reg => llvm::transforms::util::LLVMAddPromoteMemoryToRegisterPass(pm),
+      LlvmPass::InstCombine => llvm::transforms::instcombine::LLVMAddInstructionCombiningPass(pm),
+      LlvmPass::SimplifyCfg => llvm::transforms::scalar::LLVMAddCFGSimplificationPass(pm),
+      LlvmPass::Reassociate => llvm::transforms::scalar::LLVMAddReassociatePass(pm),
+      LlvmPass::Gvn => llvm::transforms::scalar::LLVMAddGVNPass(pm),
+      LlvmPass::Inline => llvm::transforms::ipo::LLVMAddFunctionInliningPass(pm),
+      LlvmPass::AggressiveInstCombine => {
+        llvm::transforms::aggressive_instcombine::LLVMAddAggressiveInstCombinerPass(pm)
+      }
+    }
+  }
+  LLVMRunPassManager(pm, ll_module);
+  LLVMDisposePassManager(pm);
+}
+
+// what a target machine writes out
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileType {
+  Asm,
+  Obj,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TargetOptions {
+  // the host's triple if none is given
+  pub triple: Option<String>,
+  pub opt_level: OptLevel,
+}
 
-  // let mut ll_func = llvm::core::LLVMGetFirstFunction(ll_module);
-  // while ll_func != std::ptr::null_mut() {
-  //   llvm::core::LLVMRunFunctionPassManager(ll_pass_manager, ll_func);
-  //   ll_func = llvm::core::LLVMGetNextFunction(ll_func);
-  // }
+/**
+ * Writes the module as textual LLVM IR.
+ *
+ * # Safety
+ *
+ * ll_module must point to a live module, e.g. one returned by `llvm_ir_gen`.
+ */
+pub unsafe fn write_llvm_ir(ll_module: *mut LLVMModule, path: &Path) -> Result<(), Diagnostic> {
+  let mut err = std::ptr::null_mut();
+  if LLVMPrintModuleToFile(ll_module, c_str(&path.to_string_lossy()), &mut err) != 0 {
+    return Err(write_error(path, err));
+  }
 
-  let target_triple = llvm::target_machine::LLVMGetDefaultTargetTriple();
+  Ok(())
+}
 
+/**
+ * Writes the module as assembly or an object file for the given target, and
+ * sets the module's target and data layout to match.
+ *
+ * # Safety
+ *
+ * ll_module must point to a live module, e.g. one returned by `llvm_ir_gen`,
+ * that nothing else is using or changing while it is written.
+ */
+pub unsafe fn write_target_file(
+  ll_module: *mut LLVMModule,
+  path: &Path,
+  file_type: FileType,
+  options: &TargetOptions,
+) -> Result<(), Diagnostic> {
   llvm::target::LLVM_InitializeAllTargetInfos();
   llvm::target::LLVM_InitializeAllTargets();
   llvm::target::LLVM_InitializeAllTargetMCs();
   llvm::target::LLVM_InitializeAllAsmParsers();
   llvm::target::LLVM_InitializeAllAsmPrinters();
 
+  let target_triple = match &options.triple {
+    Some(triple) => c_str(triple) as *mut _,
+    None => llvm::target_machine::LLVMGetDefaultTargetTriple(),
+  };
+
   let mut err = std::ptr::null_mut();
-  let mut target = llvm::target_machine::LLVMGetFirstTarget();
-  llvm::target_machine::LLVMGetTargetFromTriple(target_triple, &mut target, &mut err);
+  let mut target = std::ptr::null_mut();
+  if llvm::target_machine::LLVMGetTargetFromTriple(target_triple, &mut target, &mut err) != 0 {
+    let triple = CStr::from_ptr(target_triple).to_string_lossy();
+    let msg = format!("could not generate code for target `{}`", triple);
+    return Err(Diagnostic::without_span(E_EMIT, msg).with_note(llvm_message(err)));
+  }
 
+  let opt_level = match options.opt_level {
+    OptLevel::O0 => llvm::target_machine::LLVMCodeGenOptLevel::LLVMCodeGenLevelNone,
+    OptLevel::O1 => llvm::target_machine::LLVMCodeGenOptLevel::LLVMCodeGenLevelLess,
+    OptLevel::O2 => llvm::target_machine::LLVMCodeGenOptLevel::LLVMCodeGenLevelDefault,
+    OptLevel::O3 => llvm::target_machine::LLVMCodeGenOptLevel::LLVMCodeGenLevelAggressive,
+  };
   let cpu = c_str("generic");
   let features = c_str("");
   let target_machine = llvm::target_machine::LLVMCreateTargetMachine(
@@ -323,40 +970,45 @@ pub unsafe fn llvm_gen(ir: &IrFunction, pathbuf: &mut PathBuf) {
     target_triple,
     cpu,
     features,
-    llvm::target_machine::LLVMCodeGenOptLevel::LLVMCodeGenLevelDefault,
-    llvm::target_machine::LLVMRelocMode::LLVMRelocDefault,
+    opt_level,
+    // the system linker produces position independent executables by default
+    llvm::target_machine::LLVMRelocMode::LLVMRelocPIC,
     llvm::target_machine::LLVMCodeModel::LLVMCodeModelDefault,
   );
   let data_layout = llvm::target_machine::LLVMCreateTargetDataLayout(target_machine);
   llvm::target::LLVMSetModuleDataLayout(ll_module, data_layout);
   LLVMSetTarget(ll_module, target_triple);
 
-  let filename_ll = filename.to_string().replace(".kai", ".s");
-  let filename_obj = filename.to_string().replace(".kai", ".o");
-  let mut err_ll = std::ptr::null_mut();
-  let mut err_obj = std::ptr::null_mut();
-
-  // println!("{}", filename);
-  // println!("{:?}", filename_ll);
-  // println!("{:?}", filename_obj);
-  pathbuf.pop();
-  pathbuf.push(filename_ll);
-  let did_emit_asm = llvm::target_machine::LLVMTargetMachineEmitToFile(
-    target_machine,
-    ll_module,
-    c_str(pathbuf.to_str().unwrap()) as *mut _,
-    llvm::target_machine::LLVMCodeGenFileType::LLVMAssemblyFile,
-    &mut err_ll,
-  );
-  pathbuf.pop();
-  pathbuf.push(filename_obj);
-  let did_emit_obj = llvm::target_machine::LLVMTargetMachineEmitToFile(
+  let ll_file_type = match file_type {
+    FileType::Asm => llvm::target_machine::LLVMCodeGenFileType::LLVMAssemblyFile,
+    FileType::Obj => llvm::target_machine::LLVMCodeGenFileType::LLVMObjectFile,
+  };
+  let failed = llvm::target_machine::LLVMTargetMachineEmitToFile(
     target_machine,
     ll_module,
-    c_str(pathbuf.to_str().unwrap()) as *mut _,
-    llvm::target_machine::LLVMCodeGenFileType::LLVMObjectFile,
-    &mut err_obj,
+    c_str(&path.to_string_lossy()) as *mut _,
+    ll_file_type,
+    &mut err,
   );
+  llvm::target_machine::LLVMDisposeTargetMachine(target_machine);
+  if failed != 0 {
+    return Err(write_error(path, err));
+  }
+
+  Ok(())
+}
+
+unsafe fn llvm_message(msg: *mut i8) -> String {
+  let s = CStr::from_ptr(msg).to_string_lossy().into_owned();
+  LLVMDisposeMessage(msg);
+  s
+}
 
-  // println!("{:?}, {:?}", did_emit_asm, did_emit_obj);
+unsafe fn write_error(path: &Path, msg: *mut i8) -> Diagnostic {
+  let msg = format!(
+    "could not write `{}`: {}",
+    path.display(),
+    llvm_message(msg)
+  );
+  Diagnostic::without_span(E_EMIT, msg)
 }
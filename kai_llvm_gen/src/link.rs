@@ -0,0 +1,126 @@
+use crate::{c_str, llvm_message, write_target_file, FileType, TargetOptions, ENTRY_SYMBOL};
+use kai_common::diagnostics::{Diagnostic, E_LINK, E_NO_MAIN};
+use llvm::core::LLVMGetNamedFunction;
+use llvm::target_machine::{LLVMGetDefaultTargetTriple, LLVMNormalizeTargetTriple};
+use llvm::LLVMModule;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{env, fs};
+
+// compiled along with every program, embedded so the compiler works wherever it is installed
+pub const RUNTIME_SRC: &str = include_str!("../../runtime/kai_runtime.c");
+
+// the C compiler driver, which knows where libc and the C startup files are
+const LINKER: &str = "cc";
+
+/**
+ * Writes the module to an object file and links it with the runtime into an
+ * executable. The object is removed afterwards unless keep_obj is set, in
+ * which case it is left next to the executable.
+ *
+ * Only executables for the host can be linked, an object for another target
+ * has to be emitted and linked with that target's toolchain instead. The
+ * program needs a `function main(): int` to start in, see `gen_entry_shim`.
+ *
+ * # Safety
+ *
+ * ll_module must point to a live module, as for `write_target_file`.
+ */
+pub unsafe fn build_executable(
+  ll_module: *mut LLVMModule,
+  exe: &Path,
+  options: &TargetOptions,
+  keep_obj: bool,
+) -> Result<(), Diagnostic> {
+  // the shim is only generated for a Kai `main` that `is_entry_point`
+  if LLVMGetNamedFunction(ll_module, c_str(ENTRY_SYMBOL)).is_null() {
+    let msg = "`main` function not found".to_string();
+    return Err(Diagnostic::without_span(E_NO_MAIN, msg).with_note(
+      "an executable starts in `main`, which takes no arguments and returns an `int`".to_string(),
+    ));
+  }
+
+  if let Some(triple) = &options.triple {
+    if !is_host_triple(triple) {
+      let msg = format!("cannot link an executable for target `{}`", triple);
+      return Err(Diagnostic::without_span(E_LINK, msg).with_note(format!(
+        "`{}` only links for the host, use `--emit=obj` and link the object for the target",
+        LINKER
+      )));
+    }
+  }
+
+  let obj = if keep_obj {
+    exe.with_extension("o")
+  } else {
+    temp_path(exe, "o")
+  };
+
+  let res = write_target_file(ll_module, &obj, FileType::Obj, options)
+    .and_then(|()| link_executable(&[obj.clone()], exe));
+  if !keep_obj {
+    let _ = fs::remove_file(&obj);
+  }
+
+  res
+}
+
+// links objects that define the `main` shim with the runtime
+pub fn link_executable(objs: &[PathBuf], exe: &Path) -> Result<(), Diagnostic> {
+  let runtime = temp_path(exe, "c");
+  fs::write(&runtime, RUNTIME_SRC).map_err(|err| {
+    let msg = format!("could not write the runtime to `{}`", runtime.display());
+    Diagnostic::without_span(E_LINK, msg).with_note(err.to_string())
+  })?;
+  let output = Command::new(LINKER)
+    .args(objs)
+    .arg(&runtime)
+    .arg("-o")
+    .arg(exe)
+    .output();
+  let _ = fs::remove_file(&runtime);
+  let output = output.map_err(|err| {
+    let msg = format!("could not run the linker `{}`", LINKER);
+    Diagnostic::without_span(E_LINK, msg).with_note(err.to_string())
+  })?;
+
+  if !output.status.success() {
+    let msg = format!("linking with `{}` failed: {}", LINKER, output.status);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    return Err(Diagnostic::without_span(E_LINK, msg).with_note(stderr.trim().to_string()));
+  }
+
+  Ok(())
+}
+
+// unique even when several programs are built at once, e.g. by the tests
+fn temp_path(exe: &Path, ext: &str) -> PathBuf {
+  static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+  let stem = exe
+    .file_stem()
+    .map_or("kai".into(), |stem| stem.to_string_lossy());
+  let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+  env::temp_dir().join(format!("{}-{}-{}.{}", stem, process::id(), n, ext))
+}
+
+/*
+ * The vendor is ignored, since it does not change the code and triples
+ * name the host in more than one way, e.g. `x86_64-linux-gnu` and
+ * `x86_64-pc-linux-gnu`. Triples of a single part, e.g. `x86_64`, have no
+ * vendor either.
+ */
+unsafe fn is_host_triple(triple: &str) -> bool {
+  let normalize = |triple| {
+    let parts: Vec<String> = llvm_message(LLVMNormalizeTargetTriple(triple))
+      .split('-')
+      .map(String::from)
+      .collect();
+    (
+      parts.first().cloned(),
+      parts.get(2..).map(<[String]>::to_vec),
+    )
+  };
+  normalize(c_str(triple)) == normalize(LLVMGetDefaultTargetTriple())
+}
@@ -1,4 +1,67 @@
-#[macro_use] extern crate lalrpop_util;
+#[macro_use]
+extern crate lalrpop_util;
+
+use kai_ast::ast::Program;
+use kai_common::diagnostics::{Diagnostic, Span, E_SYNTAX};
+use lalrpop_util::ParseError;
 
 pub mod tests;
 lalrpop_mod!(pub grammar);
+
+/*
+ * Parses a whole program, converting lalrpop's errors into diagnostics.
+ *
+ * `prog` should already have had its comments blanked out, so that spans
+ * line up with the original source.
+ */
+pub fn parse_program(prog: &str) -> Result<Program, Diagnostic> {
+  grammar::ProgramParser::new()
+    .parse(prog)
+    .map_err(|e| match e {
+      ParseError::InvalidToken { location } => Diagnostic::error(
+        E_SYNTAX,
+        "invalid token".to_string(),
+        Span::new(location, location + 1),
+      ),
+      ParseError::UnrecognizedEOF { location, expected } => with_expected(
+        Diagnostic::error(
+          E_SYNTAX,
+          "unexpected end of file".to_string(),
+          Span::new(location, location),
+        ),
+        &expected,
+      ),
+      ParseError::UnrecognizedToken {
+        token: (lo, tok, hi),
+        expected,
+      } => with_expected(
+        Diagnostic::error(
+          E_SYNTAX,
+          format!("unexpected token `{}`", tok.1),
+          Span::new(lo, hi),
+        ),
+        &expected,
+      ),
+      ParseError::ExtraToken {
+        token: (lo, tok, hi),
+      } => Diagnostic::error(
+        E_SYNTAX,
+        format!("unexpected extra token `{}`", tok.1),
+        Span::new(lo, hi),
+      ),
+      ParseError::User { error } => error,
+    })
+}
+
+// lalrpop reports expected terminals quoted, e.g. `"\";\""`
+fn with_expected(diag: Diagnostic, expected: &[String]) -> Diagnostic {
+  if expected.is_empty() {
+    return diag;
+  }
+
+  let expected: Vec<String> = expected
+    .iter()
+    .map(|tok| format!("`{}`", tok.trim_matches('"').replace("\\\"", "\"")))
+    .collect();
+  diag.with_note(format!("expected one of {}", expected.join(", ")))
+}
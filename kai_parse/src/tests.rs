@@ -1,4 +1,6 @@
 use crate::grammar::*;
+#[allow(unused_imports)]
+use kai_common::int_ty::IntTy;
 
 #[allow(dead_code)]
 fn test_expect_success(prog: &str) {
@@ -85,3 +87,271 @@ fn test_exprs() {
   test_expect_fail("function main(): int { let x = 5 + ; }");
   test_expect_fail("function main(): int { let x = 1 + 2 / 4 * 5 *; }");
 }
+
+#[allow(dead_code)]
+fn test_program_expect_success(prog: &str) {
+  let parser = ProgramParser::new();
+  match parser.parse(prog) {
+    Ok(_) => (),
+    Err(e) => panic!("Failed in parsing {:?}, original error: {:?}", prog, e,),
+  }
+}
+
+#[allow(dead_code)]
+fn test_program_expect_fail(prog: &str) {
+  let parser = ProgramParser::new();
+  match parser.parse(prog) {
+    Err(_) => (),
+    Ok(_) => panic!("Succeeded in parsing {:?}, should fail", prog),
+  }
+}
+
+#[test]
+fn test_program() {
+  test_program_expect_success("");
+  test_program_expect_success("function main(): int {}");
+  test_program_expect_success("function f(x: int): int {} function main(): int {}");
+  test_program_expect_success(
+    "function f(x: int): bool { return x < 2; }
+    function g(): int { return 0; }
+    function main(): int { return 1; }",
+  );
+
+  test_program_expect_fail("function f(): int {} let x = 5;");
+  test_program_expect_fail("function f(): int {};");
+}
+
+#[test]
+fn test_calls() {
+  test_expect_success("function main(): int { let x = f(); }");
+  test_expect_success("function main(): int { let x = f(1); }");
+  test_expect_success("function main(): int { let x = f(1, y,); }");
+  test_expect_success("function main(): int { let x = f(1 + 2, g(y), z < 3); }");
+  test_expect_success("function main(): int { return 1 - f(2) * f(f(3)); }");
+
+  test_expect_fail("function main(): int { let x = f(,); }");
+  test_expect_fail("function main(): int { let x = f(1; }");
+  test_expect_fail("function main(): int { let x = 5(1); }");
+}
+
+#[test]
+fn test_while() {
+  test_expect_success("function main(): int { while x < 5 {} }");
+  test_expect_success("function main(): int { while (x < 5) { x = x + 1; } }");
+  test_expect_success("function main(): int { while true { while false { let x = 1; } } }");
+  test_expect_success("function main(): int { while a && b { if a { return 1; } } return 0; }");
+
+  test_expect_fail("function main(): int { while x < 5 }");
+  test_expect_fail("function main(): int { while { x = 1; } }");
+  test_expect_fail("function main(): int { while x < 5 { x = 1; }; }");
+}
+
+#[test]
+fn test_break_continue() {
+  test_expect_success("function main(): int { while true { break; } }");
+  test_expect_success("function main(): int { while true { continue; } }");
+  test_expect_success("function main(): int { while a { if b { break; } else { continue; } } }");
+  // loop placement is checked by the typechecker, not the parser
+  test_expect_success("function main(): int { break; }");
+
+  test_expect_fail("function main(): int { while true { break } }");
+  test_expect_fail("function main(): int { while true { break 1; } }");
+  test_expect_fail("function main(): int { let break = 1; }");
+}
+
+#[test]
+fn test_strings() {
+  test_expect_success("function main(): int { let s = \"\"; }");
+  test_expect_success("function main(): int { let s = \"hello world\"; }");
+  test_expect_success("function main(): int { let s = \"a\\nb\\t\\\"c\\\"\\\\\"; }");
+  test_expect_success("function main(): int { let s = \"a\" . b . \"c\"; }");
+  test_expect_success("function f(s: string): string { return s . \"!\"; }");
+
+  test_expect_fail("function main(): int { let s = \"unterminated; }");
+  test_expect_fail("function main(): int { let s = \"bad \\q escape\"; }");
+  test_expect_fail("function main(): int { let s = \"a\" . ; }");
+}
+
+#[test]
+fn test_unops() {
+  test_expect_success("function main(): int { let x = -1; }");
+  test_expect_success("function main(): int { let x = - -a; }");
+  test_expect_success("function main(): int { let x = 1 - -a * -f(2); }");
+  test_expect_success("function main(): int { let b = !a && !(x < 2); }");
+  test_expect_success("function main(): int { if !b { return -(1 + 2); } }");
+
+  test_expect_fail("function main(): int { let x = 1 -; }");
+  test_expect_fail("function main(): int { let x = a!; }");
+
+  // unary ops bind tighter than any binop
+  let prog = "function main(): int { return -a * b; }";
+  let ast = crate::parse_program(prog).unwrap();
+  match &ast.functions[0].body[0].kind {
+    kai_ast::ast::StmtKind::Return(e) => match &e.kind {
+      kai_ast::ast::ExprKind::Binop(_, lhs, _) => {
+        assert_eq!(&prog[lhs.span.lo..lhs.span.hi], "-a")
+      }
+      _ => panic!("expected a binop, got {:?}", e),
+    },
+    stmt => panic!("expected a return, got {:?}", stmt),
+  }
+}
+
+#[test]
+fn test_bitwise_ops() {
+  test_expect_success("function main(): int { let x = a & b | c ^ d; }");
+  test_expect_success("function main(): int { let x = a << 2 >> 1 >>> 3; }");
+  test_expect_success("function main(): int { let x = ~a & ~(b | 1); }");
+
+  test_expect_fail("function main(): int { let x = a & ; }");
+  test_expect_fail("function main(): int { let x = a ~ b; }");
+  test_expect_fail("function main(): int { let x = a <<< b; }");
+
+  // C precedence, from loosest to tightest: `|`, `^`, `&`, `==`, `<`, `<<`, `+`
+  let prog = "function main(): int { return a | b ^ c & d == e < f << g + h; }";
+  let ast = crate::parse_program(prog).unwrap();
+  let mut e = match &ast.functions[0].body[0].kind {
+    kai_ast::ast::StmtKind::Return(e) => e,
+    stmt => panic!("expected a return, got {:?}", stmt),
+  };
+  let rhs_snippets = vec![
+    "b ^ c & d == e < f << g + h",
+    "c & d == e < f << g + h",
+    "d == e < f << g + h",
+    "e < f << g + h",
+    "f << g + h",
+    "g + h",
+  ];
+  for snippet in rhs_snippets {
+    e = match &e.kind {
+      kai_ast::ast::ExprKind::Binop(_, _, rhs) => rhs,
+      _ => panic!("expected a binop, got {:?}", e),
+    };
+    assert_eq!(&prog[e.span.lo..e.span.hi], snippet);
+  }
+}
+
+#[test]
+fn test_sized_ints() {
+  test_expect_success("function main(): int { let x = 1i8 + 2u64 * 0u8; }");
+  test_expect_success("function f(a: u16, b: i64): u32 { return a as u32; }");
+  test_expect_success("function main(): int { let x = -a as i64 as u8; }");
+
+  test_expect_fail("function main(): int { let x = 1 i8; }");
+  test_expect_fail("function main(): int { let x = 1i7; }");
+  test_expect_fail("function main(): int { let x = a as; }");
+
+  let prog = "function main(): int { return 255u8; }";
+  let ast = crate::parse_program(prog).unwrap();
+  match &ast.functions[0].body[0].kind {
+    kai_ast::ast::StmtKind::Return(e) => {
+      assert!(matches!(
+        e.kind,
+        kai_ast::ast::ExprKind::Num(255, IntTy::U8)
+      ))
+    }
+    stmt => panic!("expected a return, got {:?}", stmt),
+  }
+
+  // `as` binds tighter than binops, but looser than unary ops
+  let prog = "function main(): int { return a + -b as i64; }";
+  let ast = crate::parse_program(prog).unwrap();
+  match &ast.functions[0].body[0].kind {
+    kai_ast::ast::StmtKind::Return(e) => match &e.kind {
+      kai_ast::ast::ExprKind::Binop(_, _, rhs) => match &rhs.kind {
+        kai_ast::ast::ExprKind::Cast(operand, ty) => {
+          assert_eq!(&prog[operand.span.lo..operand.span.hi], "-b");
+          assert_eq!(*ty, kai_ast::ast::Type::Int(IntTy::I64));
+        }
+        _ => panic!("expected a cast, got {:?}", rhs),
+      },
+      _ => panic!("expected a binop, got {:?}", e),
+    },
+    stmt => panic!("expected a return, got {:?}", stmt),
+  }
+}
+
+#[test]
+fn test_overflow_attr() {
+  use kai_common::diagnostics::{Span, E_INVALID_ATTR};
+  use kai_common::overflow_mode::OverflowMode;
+
+  test_expect_success("@overflow(trap) function f(): int { return 1; }");
+  test_expect_success("@overflow(wrap) function f(trap: int): int { return trap; }");
+
+  test_expect_fail("@overflow function f(): int {}");
+  test_expect_fail("@overflow() function f(): int {}");
+  test_expect_fail("function @overflow(trap) f(): int {}");
+
+  let prog = "@overflow(saturate)\nfunction f(): int {}\nfunction main(): int {}";
+  let ast = crate::parse_program(prog).unwrap();
+  assert_eq!(ast.functions[0].overflow, Some(OverflowMode::Saturate));
+  assert_eq!(ast.functions[1].overflow, None);
+  // the function's span starts at `function`, not at the attribute
+  assert_eq!(ast.functions[0].span.lo, 20);
+
+  let diag = crate::parse_program("@overflow(panic) function f(): int {}").unwrap_err();
+  assert_eq!(diag.code, E_INVALID_ATTR);
+  assert_eq!(diag.span, Some(Span::new(10, 15)));
+}
+
+#[test]
+fn test_spans() {
+  let prog = "function main(): int { let x = 1 + y; return x; }";
+  let ast = crate::parse_program(prog).unwrap();
+  let func = &ast.functions[0];
+  assert_eq!(&prog[func.span.lo..func.span.hi], "function main(): int");
+
+  let decl = &func.body[0];
+  assert_eq!(&prog[decl.span.lo..decl.span.hi], "let x = 1 + y");
+  match &decl.kind {
+    kai_ast::ast::StmtKind::VarDecl(_, e) => assert_eq!(&prog[e.span.lo..e.span.hi], "1 + y"),
+    _ => panic!("expected a declaration, got {:?}", decl),
+  }
+}
+
+#[test]
+fn test_syntax_diagnostics() {
+  use kai_common::diagnostics::{Span, E_INVALID_LITERAL, E_SYNTAX};
+
+  let diag = crate::parse_program("function main(): int { let x = 5 }").unwrap_err();
+  assert_eq!(diag.code, E_SYNTAX);
+  assert_eq!(diag.span, Some(Span::new(33, 34)));
+  assert!(diag.notes[0].contains("`;`"), "{:?}", diag.notes);
+
+  let diag = crate::parse_program("function main(): int {").unwrap_err();
+  assert_eq!(diag.code, E_SYNTAX);
+  assert_eq!(diag.span, Some(Span::new(22, 22)));
+
+  let diag = crate::parse_program("function main(): int { let s = \"\\q\"; }").unwrap_err();
+  assert_eq!(diag.code, E_INVALID_LITERAL);
+  assert_eq!(diag.span, Some(Span::new(31, 35)));
+
+  let diag =
+    crate::parse_program("function main(): int { return 99999999999999999999; }").unwrap_err();
+  assert_eq!(diag.code, E_INVALID_LITERAL);
+  assert_eq!(diag.message, "integer literal is too large");
+  assert_eq!(diag.span, Some(Span::new(30, 50)));
+}
+
+#[test]
+fn test_int_literals() {
+  test_expect_success("function main(): int { let x = 0xff + 0o17 + 0b1010 + 1_000_000; }");
+  test_expect_success("function main(): int { let x = 0xffu8 + 0b1_0i64 + 1_u16; }");
+
+  // digits that do not belong to the radix start another token
+  test_expect_fail("function main(): int { let x = 0b102; }");
+  test_expect_fail("function main(): int { let x = 0x; }");
+
+  let prog = "function main(): int { return 0xffff_ffff_ffff_ffffu64; }";
+  let ast = crate::parse_program(prog).unwrap();
+  match &ast.functions[0].body[0].kind {
+    kai_ast::ast::StmtKind::Return(e) => {
+      assert!(matches!(
+        e.kind,
+        kai_ast::ast::ExprKind::Num(u64::MAX, IntTy::U64)
+      ))
+    }
+    stmt => panic!("expected a return, got {:?}", stmt),
+  }
+}
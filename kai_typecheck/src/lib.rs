@@ -1,5 +1,6 @@
 use kai_ast::ast;
-use std::collections::HashMap;
+use kai_common::diagnostics::Diagnostic;
+use std::collections::{HashMap, HashSet};
 
 pub mod tests;
 pub mod typecheck;
@@ -7,13 +8,58 @@ pub mod typecheck;
 struct TypeCheckCtx {
   fn_type_map: HashMap<String, ast::Type>,
   // var_map: HashMap<String, ast::Type>,
+  // every variable declared in the function being checked, by `ast::decl_key`
+  decl_map: HashMap<String, ast::Type>,
+  // names already reported as undeclared in the function being checked, later
+  // uses are taken to be declared with type `Invalid`
+  undeclared_vars: HashSet<String>,
+  diagnostics: Vec<Diagnostic>,
 }
 
-pub fn typecheck(ast_func: &ast::Function) -> Result<HashMap<String, ast::Type>, String> {
+/*
+ * Typechecks every function in the program.
+ *
+ * All function signatures are collected before any body is checked, so
+ * functions may refer to each other regardless of declaration order.
+ *
+ * Returns a map from function name to that function's variable type map, keyed
+ * by `ast::decl_key`, or every error found in the program, in source order. Warnings are dropped,
+ * see `typecheck_with_warnings`.
+ */
+pub fn typecheck(
+  ast_prog: &ast::Program,
+) -> Result<HashMap<String, HashMap<String, ast::Type>>, Vec<Diagnostic>> {
+  typecheck_with_warnings(ast_prog).map(|(fn_var_ty_maps, _)| fn_var_ty_maps)
+}
+
+// like `typecheck`, but also returns the warnings when there are no errors
+pub fn typecheck_with_warnings(
+  ast_prog: &ast::Program,
+) -> Result<(HashMap<String, HashMap<String, ast::Type>>, Vec<Diagnostic>), Vec<Diagnostic>> {
   let mut type_check_ctx = TypeCheckCtx {
-    fn_type_map: HashMap::new(),
+    fn_type_map: kai_ast::builtins::builtin_fn_types().into_iter().collect(),
+    decl_map: HashMap::new(),
+    undeclared_vars: HashSet::new(),
+    diagnostics: vec![],
   };
 
-  type_check_ctx.populate_fn_types(ast_func);
-  type_check_ctx.typecheck_function(ast_func)
+  for ast_func in &ast_prog.functions {
+    type_check_ctx.populate_fn_types(ast_func);
+  }
+
+  let mut fn_var_ty_maps = HashMap::new();
+  for ast_func in &ast_prog.functions {
+    let var_ty_map = type_check_ctx.typecheck_function(ast_func);
+    fn_var_ty_maps
+      .entry(ast_func.ident.clone())
+      .or_insert(var_ty_map);
+  }
+
+  let mut diagnostics = type_check_ctx.diagnostics;
+  // missing returns are only found once a whole function has been checked
+  diagnostics.sort_by_key(|diag| diag.span.map_or(0, |span| span.lo));
+  if !diagnostics.iter().any(|diag| diag.is_error()) {
+    return Ok((fn_var_ty_maps, diagnostics));
+  }
+  Err(diagnostics)
 }
@@ -8,6 +8,8 @@ use crate::typecheck;
 #[allow(unused_imports)]
 use kai_ast::ast;
 #[allow(unused_imports)]
+use kai_common::int_ty::IntTy;
+#[allow(unused_imports)]
 use kai_parse::grammar::*;
 #[allow(unused_imports)]
 use std::fs;
@@ -40,15 +42,17 @@ fn test_kai_arithmetic() -> io::Result<()> {
   (*dir).push("basic/kai_multi_variables.kai");
 
   let prog = fs::read_to_string(*dir)?;
-  let parser = FunctionParser::new();
+  let prog = kai_common::remove_comments::remove_comments(&prog).unwrap();
+  let parser = ProgramParser::new();
   let ast = parser.parse(prog.as_str()).unwrap();
-  let var_ty_map = typecheck(&ast);
+  let fn_var_ty_maps = typecheck(&ast).unwrap();
+  let var_ty_map = fn_var_ty_maps.get("main").unwrap();
 
-  assert!(*var_ty_map.get("x").unwrap() == ast::Type::Int);
-  assert!(*var_ty_map.get("y").unwrap() == ast::Type::Int);
+  assert!(*var_ty_map.get("x").unwrap() == ast::Type::Int(IntTy::I32));
+  assert!(*var_ty_map.get("y").unwrap() == ast::Type::Int(IntTy::I32));
   assert!(*var_ty_map.get("z").unwrap() == ast::Type::Bool);
   assert!(*var_ty_map.get("z0").unwrap() == ast::Type::Bool);
-  assert!(*var_ty_map.get("x1").unwrap() == ast::Type::Int);
+  assert!(*var_ty_map.get("x1").unwrap() == ast::Type::Int(IntTy::I32));
   assert!(*var_ty_map.get("x2").unwrap() == ast::Type::Bool);
   assert!(*var_ty_map.get("x3").unwrap() == ast::Type::Bool);
   assert!(*var_ty_map.get("x4").unwrap() == ast::Type::Bool);
@@ -62,12 +66,307 @@ fn test_kai_fn_args() -> io::Result<()> {
   (*dir).push("basic/kai_fn_args.kai");
 
   let prog = fs::read_to_string(*dir)?;
-  let parser = FunctionParser::new();
+  let prog = kai_common::remove_comments::remove_comments(&prog).unwrap();
+  let parser = ProgramParser::new();
   let ast = parser.parse(prog.as_str()).unwrap();
-  let var_ty_map = typecheck(&ast);
+  let fn_var_ty_maps = typecheck(&ast).unwrap();
+  let var_ty_map = fn_var_ty_maps.get("f").unwrap();
 
-  assert!(*var_ty_map.get("a").unwrap() == ast::Type::Int);
+  assert!(*var_ty_map.get("a").unwrap() == ast::Type::Int(IntTy::I32));
   assert!(*var_ty_map.get("b").unwrap() == ast::Type::Bool);
 
   Ok(())
 }
+
+#[test]
+fn test_kai_sibling_scopes() -> io::Result<()> {
+  let mut dir = get_example_dir();
+  (*dir).push("loops/kai_sibling_scopes.kai");
+
+  let prog = fs::read_to_string(*dir)?;
+  let prog = kai_common::remove_comments::remove_comments(&prog).unwrap();
+  let parser = ProgramParser::new();
+  let ast = parser.parse(prog.as_str()).unwrap();
+  let fn_var_ty_maps = typecheck(&ast).unwrap();
+  let var_ty_map = fn_var_ty_maps.get("main").unwrap();
+
+  // each type the name is declared with gets a variable of its own
+  assert!(*var_ty_map.get("y").unwrap() == ast::Type::Int(IntTy::I32));
+  assert!(*var_ty_map.get("y#1").unwrap() == ast::Type::Bool);
+  assert_eq!(var_ty_map.len(), 3);
+
+  Ok(())
+}
+
+#[allow(dead_code)]
+fn typecheck_errs(prog: &str) -> Vec<kai_common::diagnostics::Diagnostic> {
+  let ast = kai_parse::parse_program(prog).unwrap();
+  match typecheck(&ast) {
+    Ok(_) => panic!("typechecking {:?} succeeded, but should fail", prog),
+    Err(diags) => diags,
+  }
+}
+
+#[allow(dead_code)]
+fn typecheck_err(prog: &str) -> kai_common::diagnostics::Diagnostic {
+  let mut diags = typecheck_errs(prog);
+  assert_eq!(
+    diags.len(),
+    1,
+    "expected exactly one error, got {:?}",
+    diags
+  );
+  diags.remove(0)
+}
+
+#[test]
+fn test_diagnostics() {
+  use kai_common::diagnostics::*;
+
+  let prog = "function main(): int { let x = 1; let x = 2; return x; }";
+  let diag = typecheck_err(prog);
+  assert_eq!(diag.code, E_REDECLARED_VAR);
+  assert_eq!(
+    &prog[diag.span.unwrap().lo..diag.span.unwrap().hi],
+    "let x = 2"
+  );
+
+  let prog = "function main(): int { return 1 + true; }";
+  let diag = typecheck_err(prog);
+  assert_eq!(diag.code, E_MISMATCHED_TYPES);
+  assert_eq!(&prog[diag.span.unwrap().lo..diag.span.unwrap().hi], "true");
+
+  let prog = "function main(): int { if 1 { return 1; } return 0; }";
+  let diag = typecheck_err(prog);
+  assert_eq!(diag.code, E_NON_BOOL_COND);
+  assert_eq!(&prog[diag.span.unwrap().lo..diag.span.unwrap().hi], "1");
+
+  let prog = "function main(): int { if true { return 1; } }";
+  let diag = typecheck_err(prog);
+  assert_eq!(diag.code, E_MISSING_RETURN);
+  assert_eq!(
+    &prog[diag.span.unwrap().lo..diag.span.unwrap().hi],
+    "function main(): int"
+  );
+
+  let prog = "function main(): int { return f(1, 2); } function f(x: int): int { return x; }";
+  let diag = typecheck_err(prog);
+  assert_eq!(diag.code, E_ARG_COUNT);
+  assert_eq!(
+    &prog[diag.span.unwrap().lo..diag.span.unwrap().hi],
+    "f(1, 2)"
+  );
+
+  let prog = "function main(): int { if -1 < 2 { return -1; } return !true; }";
+  let diag = typecheck_err(prog);
+  assert_eq!(diag.code, E_MISMATCHED_TYPES);
+  assert_eq!(&prog[diag.span.unwrap().lo..diag.span.unwrap().hi], "!true");
+
+  let prog = "function main(): int { return -(1 < 2); }";
+  let diag = typecheck_err(prog);
+  assert_eq!(diag.code, E_MISMATCHED_TYPES);
+  assert_eq!(&prog[diag.span.unwrap().lo..diag.span.unwrap().hi], "1 < 2");
+  assert_eq!(
+    diag.notes,
+    vec!["the operand of `-` must be a signed integer"]
+  );
+
+  let prog = "function main(): int { return 1 << 2 | true; }";
+  let diag = typecheck_err(prog);
+  assert_eq!(diag.code, E_MISMATCHED_TYPES);
+  assert_eq!(&prog[diag.span.unwrap().lo..diag.span.unwrap().hi], "true");
+  assert_eq!(diag.notes, vec!["operands of `|` must be integers"]);
+
+  let prog = "function main(): int { break; }";
+  let diag = typecheck_err(prog);
+  assert_eq!(diag.code, E_JUMP_OUTSIDE_LOOP);
+  assert_eq!(diag.message, "`break` used outside of a loop");
+}
+
+#[test]
+fn test_sized_ints() {
+  use kai_common::diagnostics::*;
+
+  let prog = "function main(): int { let x = 1u8 + 2u8; let y = x as i64 * 3i64; return 0; }";
+  let ast = kai_parse::parse_program(prog).unwrap();
+  let fn_var_ty_maps = typecheck(&ast).unwrap();
+  let var_ty_map = fn_var_ty_maps.get("main").unwrap();
+  assert!(*var_ty_map.get("x").unwrap() == ast::Type::Int(IntTy::U8));
+  assert!(*var_ty_map.get("y").unwrap() == ast::Type::Int(IntTy::I64));
+
+  // int types never mix without a cast, `int` included
+  let prog = "function main(): int { let x = 1i64; let y = x + 1; return 0; }";
+  let diag = typecheck_err(prog);
+  assert_eq!(diag.code, E_MISMATCHED_TYPES);
+  assert_eq!(
+    diag.message,
+    "mismatched types: expected `i64`, found `int`"
+  );
+  assert_eq!(&prog[diag.span.unwrap().lo..diag.span.unwrap().hi], "1");
+
+  let prog = "function main(): int { return 1 << 2u8; }";
+  let diag = typecheck_err(prog);
+  assert_eq!(diag.code, E_MISMATCHED_TYPES);
+  assert_eq!(&prog[diag.span.unwrap().lo..diag.span.unwrap().hi], "2u8");
+
+  let prog = "function main(): int { let x = -1u32; return 0; }";
+  let diag = typecheck_err(prog);
+  assert_eq!(diag.code, E_MISMATCHED_TYPES);
+  assert_eq!(
+    diag.notes,
+    vec!["the operand of `-` must be a signed integer"]
+  );
+
+  let prog = "function main(): int { return true as int; }";
+  let diag = typecheck_err(prog);
+  assert_eq!(diag.code, E_INVALID_CAST);
+  assert_eq!(diag.message, "cannot cast `bool` as `int`");
+  assert_eq!(
+    &prog[diag.span.unwrap().lo..diag.span.unwrap().hi],
+    "true as int"
+  );
+}
+
+#[test]
+fn test_literal_ranges() {
+  use kai_common::diagnostics::*;
+
+  // the smallest signed ints are written as negated literals
+  let prog = "function main(): int { let x = -2147483648; let y = -128i8 + 127i8; return 0; }";
+  typecheck(&kai_parse::parse_program(prog).unwrap()).unwrap();
+  let prog = "function main(): int { let x = 0xffff_ffff_ffff_ffffu64; return 0; }";
+  typecheck(&kai_parse::parse_program(prog).unwrap()).unwrap();
+
+  let prog = "function main(): int { let x = 3000000000; return 0; }";
+  let diag = typecheck_err(prog);
+  assert_eq!(diag.code, E_LITERAL_OUT_OF_RANGE);
+  assert_eq!(diag.message, "literal out of range for `int`");
+  assert_eq!(
+    &prog[diag.span.unwrap().lo..diag.span.unwrap().hi],
+    "3000000000"
+  );
+  assert_eq!(
+    diag.notes,
+    vec!["the literal `3000000000` does not fit into the type `int` whose range is `-2147483648..=2147483647`"]
+  );
+
+  let prog = "function main(): int { let x = -129i8; return 0; }";
+  let diag = typecheck_err(prog);
+  assert_eq!(diag.code, E_LITERAL_OUT_OF_RANGE);
+  assert_eq!(
+    &prog[diag.span.unwrap().lo..diag.span.unwrap().hi],
+    "-129i8"
+  );
+
+  let prog = "function main(): int { let x = 0x100u8; return 0; }";
+  let diag = typecheck_err(prog);
+  assert_eq!(diag.code, E_LITERAL_OUT_OF_RANGE);
+  assert_eq!(diag.message, "literal out of range for `u8`");
+
+  // only `-` right before a literal makes a negative literal
+  let prog = "function main(): int { let x = -(-2147483648); let y = - -2147483648; return 0; }";
+  typecheck(&kai_parse::parse_program(prog).unwrap()).unwrap();
+  let prog = "function main(): int { let x = -(2147483648 + 0); return 0; }";
+  let diag = typecheck_err(prog);
+  assert_eq!(diag.code, E_LITERAL_OUT_OF_RANGE);
+  assert_eq!(
+    &prog[diag.span.unwrap().lo..diag.span.unwrap().hi],
+    "2147483648"
+  );
+}
+
+#[test]
+fn test_multiple_diagnostics() {
+  use kai_common::diagnostics::*;
+
+  let prog = "
+function f(x: int): bool {
+  let y = x + true;
+  y = \"s\";
+  return z;
+}
+
+function main(): int {
+  if f(1, 2) {
+    return 1;
+  }
+}";
+  let diags = typecheck_errs(prog);
+  let codes: Vec<&str> = diags.iter().map(|diag| diag.code).collect();
+  assert_eq!(
+    codes,
+    vec![
+      E_MISMATCHED_TYPES,
+      E_MISMATCHED_TYPES,
+      E_UNDECLARED_VAR,
+      E_MISSING_RETURN,
+      E_ARG_COUNT
+    ],
+  );
+}
+
+#[test]
+fn test_poisoned_types_do_not_cascade() {
+  use kai_common::diagnostics::*;
+
+  // `x` is poisoned by the bad call, so its later uses are not reported again
+  let prog = "
+function main(): int {
+  let x = g(1);
+  let y = x + 1;
+  if x {
+    x = true;
+  }
+  return x;
+}";
+  let diag = typecheck_err(prog);
+  assert_eq!(diag.code, E_UNDEFINED_FN);
+
+  // an undeclared variable is only reported at its first use
+  let prog = "function main(): int { let y = z * 2; if y > z { return 1; } return y - z; }";
+  let diags = typecheck_errs(prog);
+  assert_eq!(diags.len(), 1);
+  assert_eq!(diags[0].code, E_UNDECLARED_VAR);
+  assert_eq!(
+    &prog[diags[0].span.unwrap().lo..diags[0].span.unwrap().hi],
+    "z"
+  );
+}
+
+#[test]
+fn test_unreachable_warnings() {
+  use kai_common::diagnostics::*;
+
+  // warned about once per block, from the first statement that cannot run
+  let prog = "
+function main(): int {
+  let x = 1;
+  while x < 10 {
+    x = x + 1;
+    continue;
+    x = 2;
+    x = 3;
+  }
+  if x < 5 {
+    return 1;
+  } else {
+    return 2;
+  }
+  return x;
+}";
+  let ast = kai_parse::parse_program(prog).unwrap();
+  let (_, warnings) = crate::typecheck_with_warnings(&ast).unwrap();
+  let snippets: Vec<&str> = warnings
+    .iter()
+    .map(|diag| &prog[diag.span.unwrap().lo..diag.span.unwrap().hi])
+    .collect();
+  assert_eq!(snippets, vec!["x = 2", "return x"]);
+  assert!(warnings
+    .iter()
+    .all(|diag| diag.code == W_UNREACHABLE_CODE && !diag.is_error()));
+
+  // warnings come along with errors, which still fail typechecking
+  let diags = typecheck_errs("function main(): int { return 1; return true; }");
+  let codes: Vec<&str> = diags.iter().map(|diag| diag.code).collect();
+  assert_eq!(codes, vec![W_UNREACHABLE_CODE, E_MISMATCHED_TYPES]);
+}
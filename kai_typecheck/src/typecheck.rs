@@ -1,15 +1,46 @@
 use crate::TypeCheckCtx;
 use kai_ast::ast;
-use kai_common::ops::Opcode;
+use kai_common::diagnostics::*;
+use kai_common::int_ty::IntTy;
+use kai_common::ops::{Opcode, UnOpcode};
 use std::collections::HashMap;
 
+/*
+ * Errors are recorded in `self.diagnostics` rather than returned, so checking
+ * carries on past them. An expression that fails to typecheck is given the
+ * type `Invalid`, and any check involving an `Invalid` type is skipped, so one
+ * mistake does not cascade into a pile of follow-up errors.
+ */
+
 struct BlkCheckResult {
+  // control never reaches the end of the block: every path through it returns,
+  // or leaves the enclosing loop via `break`/`continue`
   did_return: bool,
+  // variables visible after the block
   type_map: HashMap<String, ast::Type>,
 }
 
+// an `if` statement, or an `else if` in its chain
+struct IfStmt<'a> {
+  cond: &'a ast::Expr,
+  if_stmts: &'a Vec<ast::Stmt>,
+  else_if: &'a ast::ElseIf,
+  // where a branch type mismatch is reported
+  span: Span,
+}
+
 impl TypeCheckCtx {
   pub fn populate_fn_types(&mut self, ast_func: &ast::Function) {
+    if self.fn_type_map.contains_key(&ast_func.ident) {
+      // keep the first signature, calls are checked against that one
+      self.report(Diagnostic::error(
+        E_DUPLICATE_FN,
+        format!("function `{}` is defined more than once", ast_func.ident),
+        ast_func.span,
+      ));
+      return;
+    }
+
     let func_args = &(*ast_func).args;
     self.fn_type_map.insert(
       ast_func.ident.clone(),
@@ -23,118 +54,214 @@ impl TypeCheckCtx {
     );
   }
 
-  pub fn typecheck_function(
-    &self,
-    ast_func: &ast::Function,
-  ) -> Result<HashMap<String, ast::Type>, String> {
+  pub fn typecheck_function(&mut self, ast_func: &ast::Function) -> HashMap<String, ast::Type> {
     let mut var_ty_map = HashMap::new();
-    let mut arg_ty_map = HashMap::new();
     for arg in &ast_func.args {
       var_ty_map.insert(arg.ident.clone(), arg.ty.clone());
-      arg_ty_map.insert(arg.ident.clone(), arg.ty.clone());
+      self.declare_var(&arg.ident, &arg.ty);
     }
-    let body_res = self.typecheck_stmt_list(&ast_func.body, &mut var_ty_map, &ast_func.ret_ty)?;
-    if !(body_res.did_return && ast_func.ret_ty != ast::Type::Unit) {
-      panic!(
-        "Error when checking function `{:?}`, not all branches return",
-        ast_func.ident
+    let body_res =
+      self.typecheck_stmt_list(&ast_func.body, &mut var_ty_map, &ast_func.ret_ty, false);
+    if !body_res.did_return && ast_func.ret_ty != ast::Type::Unit {
+      self.report(
+        Diagnostic::error(
+          E_MISSING_RETURN,
+          format!("not all paths through function `{}` return", ast_func.ident),
+          ast_func.span,
+        )
+        .with_note(format!(
+          "the function must return a value of type `{}`",
+          ast_func.ret_ty
+        )),
       );
     }
-    let mut body_map = body_res.type_map;
-    body_map.extend(arg_ty_map);
-    Ok(body_map)
+    self.undeclared_vars.clear();
+    std::mem::take(&mut self.decl_map)
+  }
+
+  // records a declaration in `self.decl_map`, see `ast::decl_key`
+  fn declare_var(&mut self, ident: &str, ty: &ast::Type) {
+    let mut n = 0;
+    loop {
+      let key = ast::decl_key(ident, n);
+      match self.decl_map.get(&key) {
+        Some(decl_ty) if decl_ty != ty => n += 1,
+        Some(_) => return,
+        None => {
+          self.decl_map.insert(key, ty.clone());
+          return;
+        }
+      }
+    }
+  }
+
+  // errors and warnings alike, `Diagnostic::severity` tells them apart
+  fn report(&mut self, diag: Diagnostic) {
+    self.diagnostics.push(diag);
   }
 
   fn typecheck_stmt_list(
-    &self,
+    &mut self,
     stmts: &Vec<ast::Stmt>,
     var_ty_map: &mut HashMap<String, ast::Type>,
     ret_ty: &ast::Type,
-  ) -> Result<BlkCheckResult, String> {
+    in_loop: bool,
+  ) -> BlkCheckResult {
     let mut current_scope_var_ty_map = HashMap::new();
     let mut did_return = false;
+    let mut warned_unreachable = false;
     for stmt in stmts {
-      match stmt {
-        ast::Stmt::VarDecl(ident, expr) => {
+      // the rest of the block is still checked, but only reported as unreachable once
+      let is_comment = matches!(stmt.kind, ast::StmtKind::Comment(_));
+      if did_return && !warned_unreachable && !is_comment {
+        self.report(
+          Diagnostic::warning(
+            W_UNREACHABLE_CODE,
+            "unreachable statement".to_string(),
+            stmt.span,
+          )
+          .with_note(
+            "any code following a `return`, `break` or `continue` is unreachable".to_string(),
+          ),
+        );
+        warned_unreachable = true;
+      }
+      match &stmt.kind {
+        ast::StmtKind::VarDecl(ident, expr) => {
+          let e_ty = self.infer_expr_type(var_ty_map, expr);
           if var_ty_map.contains_key(ident) {
-            return Err(format!(
-              "variable ```{:?}``` already defined in scope",
-              ident,
+            // the first declaration stays in effect
+            self.report(Diagnostic::error(
+              E_REDECLARED_VAR,
+              format!("variable `{}` is already defined in this scope", ident),
+              stmt.span,
             ));
+            continue;
           }
-          let e_ty = self.infer_expr_type(var_ty_map, expr)?;
           current_scope_var_ty_map.insert(ident.clone(), e_ty.clone());
+          self.declare_var(ident, &e_ty);
           var_ty_map.insert((*ident).clone(), e_ty);
         }
-        ast::Stmt::VarAsgn(ident, expr) => {
+        ast::StmtKind::VarAsgn(ident, expr) => {
+          let e_ty = self.infer_expr_type(var_ty_map, expr);
           if !var_ty_map.contains_key(ident) {
-            return Err(format!(
-              "trying to assign variable ```{:?}``` a value, but ```{:?}``` has not been defined",
-              ident, ident,
-            ));
+            // only report the first use of this name
+            if self.undeclared_vars.insert(ident.clone()) {
+              self.report(
+                Diagnostic::error(
+                  E_UNDECLARED_VAR,
+                  format!("cannot assign to undeclared variable `{}`", ident),
+                  stmt.span,
+                )
+                .with_note(format!("declare it first with `let {} = ...`", ident)),
+              );
+            }
+            continue;
           }
-          let e_ty = self.infer_expr_type(var_ty_map, expr)?;
-          let var_ty = var_ty_map.get(ident).unwrap_or(&ast::Type::Invalid);
-          if e_ty != *var_ty {
-            return Err(format!(
-              "trying to assign variable ```{:?}``` a value of a different type than was defined",
-              ident,
-            ));
+          let var_ty = var_ty_map.get(ident).unwrap_or(&ast::Type::Invalid).clone();
+          if !ty_mismatch(&e_ty, &var_ty) {
+            continue;
           }
+          self.report(
+            Diagnostic::error(
+              E_MISMATCHED_TYPES,
+              format!("mismatched types: expected `{}`, found `{}`", var_ty, e_ty),
+              expr.span,
+            )
+            .with_note(format!("`{}` was declared with type `{}`", ident, var_ty)),
+          );
         }
-        ast::Stmt::If(c, b, e) => {
-          let if_res = self.typecheck_if(var_ty_map, c, b, e, ret_ty)?;
+        ast::StmtKind::If(c, b, e) => {
+          let if_stmt = IfStmt {
+            cond: c,
+            if_stmts: b,
+            else_if: e,
+            span: stmt.span,
+          };
+          let if_res = self.typecheck_if(var_ty_map, if_stmt, ret_ty, in_loop);
           did_return = did_return || if_res.did_return;
           for (var, ty) in if_res.type_map.iter() {
             current_scope_var_ty_map.insert(var.clone(), ty.clone());
             var_ty_map.insert(var.clone(), ty.clone());
           }
         }
-        ast::Stmt::Return(expr) => {
-          let expr_type = self.infer_expr_type(var_ty_map, expr)?;
-          did_return = true;
-          if expr_type != *ret_ty {
-            return Err(format!(
-              "returning a value of a type ```{:?}``` that does not match function signature",
-              expr_type,
+        ast::StmtKind::While(c, b) => {
+          self.typecheck_while(var_ty_map, c, b, ret_ty);
+        }
+        ast::StmtKind::Break | ast::StmtKind::Continue => {
+          if !in_loop {
+            let keyword = match stmt.kind {
+              ast::StmtKind::Break => "break",
+              _ => "continue",
+            };
+            self.report(Diagnostic::error(
+              E_JUMP_OUTSIDE_LOOP,
+              format!("`{}` used outside of a loop", keyword),
+              stmt.span,
             ));
           }
+          // nothing after a jump out of the current iteration can run
+          did_return = true;
         }
-        ast::Stmt::Comment(_) => {}
+        ast::StmtKind::Return(expr) => {
+          let expr_type = self.infer_expr_type(var_ty_map, expr);
+          did_return = true;
+          if ty_mismatch(&expr_type, ret_ty) {
+            self.report(
+              Diagnostic::error(
+                E_MISMATCHED_TYPES,
+                format!(
+                  "mismatched types: expected `{}`, found `{}`",
+                  ret_ty, expr_type
+                ),
+                expr.span,
+              )
+              .with_note(format!("the function signature returns `{}`", ret_ty)),
+            );
+          }
+        }
+        ast::StmtKind::Comment(_) => {}
       }
     }
 
-    return Ok(BlkCheckResult {
+    return BlkCheckResult {
       did_return,
       type_map: current_scope_var_ty_map,
-    });
+    };
   }
 
   fn typecheck_if(
-    &self,
+    &mut self,
     var_ty_map: &mut HashMap<String, ast::Type>,
-    cond: &ast::Expr,
-    if_stmts: &Vec<ast::Stmt>,
-    else_if: &ast::ElseIf,
+    if_stmt: IfStmt,
     ret_ty: &ast::Type,
-  ) -> Result<BlkCheckResult, String> {
-    if self.infer_expr_type(var_ty_map, cond)? != ast::Type::Bool {
-      return Err("conditional in if block is not of type ```bool```".to_string());
-    }
+    in_loop: bool,
+  ) -> BlkCheckResult {
+    self.check_cond_type(var_ty_map, if_stmt.cond, "if");
 
-    let if_res = self.typecheck_stmt_list(if_stmts, var_ty_map, ret_ty)?;
+    let if_res = self.typecheck_stmt_list(if_stmt.if_stmts, var_ty_map, ret_ty, in_loop);
     let if_map = if_res.type_map;
     for (var, _) in if_map.iter() {
       // reset state
       var_ty_map.remove(var);
     }
-    let else_res = match else_if {
+    let else_res = match if_stmt.else_if {
       ast::ElseIf::Empty => BlkCheckResult {
         did_return: false,
         type_map: HashMap::new(),
       },
-      ast::ElseIf::Else(else_stmts) => self.typecheck_stmt_list(else_stmts, var_ty_map, ret_ty)?,
-      ast::ElseIf::ElseIf(c, b, e) => self.typecheck_if(var_ty_map, c, b, e, ret_ty)?,
+      ast::ElseIf::Else(else_stmts) => {
+        self.typecheck_stmt_list(else_stmts, var_ty_map, ret_ty, in_loop)
+      }
+      ast::ElseIf::ElseIf(c, b, e) => {
+        let else_if_stmt = IfStmt {
+          cond: c,
+          if_stmts: b,
+          else_if: e,
+          span: c.span,
+        };
+        self.typecheck_if(var_ty_map, else_if_stmt, ret_ty, in_loop)
+      }
     };
     let else_map = else_res.type_map;
     for (var, _) in else_map.iter() {
@@ -150,44 +277,108 @@ impl TypeCheckCtx {
 
       // var is in both maps
       let ty2 = else_map.get(var).unwrap();
-      if ty1 != ty2 {
-        return Err(format!(
-          "type for var ```{:?}``` do not match in if and else blocks",
-          var,
-        ));
+      if ty_mismatch(ty1, ty2) {
+        self.report(
+          Diagnostic::error(
+            E_BRANCH_TYPE_MISMATCH,
+            format!(
+              "variable `{}` has different types in if and else blocks",
+              var
+            ),
+            if_stmt.span,
+          )
+          .with_note(format!(
+            "declared as `{}` in one branch and `{}` in the other",
+            ty1, ty2
+          )),
+        );
+        both_map.insert(var.clone(), ast::Type::Invalid);
+        continue;
       }
 
       both_map.insert(var.clone(), ty1.clone());
     }
 
-    Ok(BlkCheckResult {
+    BlkCheckResult {
       did_return: if_res.did_return && else_res.did_return,
       type_map: both_map,
-    })
+    }
+  }
+
+  fn typecheck_while(
+    &mut self,
+    var_ty_map: &mut HashMap<String, ast::Type>,
+    cond: &ast::Expr,
+    body_stmts: &Vec<ast::Stmt>,
+    ret_ty: &ast::Type,
+  ) -> BlkCheckResult {
+    self.check_cond_type(var_ty_map, cond, "while");
+
+    let body_res = self.typecheck_stmt_list(body_stmts, var_ty_map, ret_ty, true);
+    for (var, _) in body_res.type_map.iter() {
+      // reset state
+      var_ty_map.remove(var);
+    }
+
+    // the body may run zero times, so the loop never guarantees a return, and
+    // nothing declared inside it is visible afterwards
+    BlkCheckResult {
+      did_return: false,
+      type_map: HashMap::new(),
+    }
+  }
+
+  fn check_cond_type(
+    &mut self,
+    var_ty_map: &HashMap<String, ast::Type>,
+    cond: &ast::Expr,
+    construct: &str,
+  ) {
+    let cond_ty = self.infer_expr_type(var_ty_map, cond);
+    if ty_mismatch(&cond_ty, &ast::Type::Bool) {
+      self.report(Diagnostic::error(
+        E_NON_BOOL_COND,
+        format!(
+          "condition of `{}` must be of type `bool`, found `{}`",
+          construct, cond_ty
+        ),
+        cond.span,
+      ));
+    }
   }
 
   fn infer_expr_type(
-    &self,
+    &mut self,
     var_ty_map: &HashMap<String, ast::Type>,
     expr: &ast::Expr,
-  ) -> Result<ast::Type, String> {
-    match expr {
-      ast::Expr::Num(_) => Ok(ast::Type::Int),
-      ast::Expr::Bool(_) => Ok(ast::Type::Bool),
-      ast::Expr::Ident(ident) => match var_ty_map.get(ident) {
-        None => Err(format!(
-          "Variable ```{:?}``` does not have a type in scope",
-          ident
-        )),
-        Some(ty) => Ok(ty.clone()),
+  ) -> ast::Type {
+    match &expr.kind {
+      ast::ExprKind::Num(n, int_ty) => self.check_literal(*n as i128, *int_ty, expr.span),
+      ast::ExprKind::Bool(_) => ast::Type::Bool,
+      ast::ExprKind::Str(_) => ast::Type::Str,
+      ast::ExprKind::Ident(ident) => match var_ty_map.get(ident) {
+        None => {
+          // only report the first use of this name
+          if self.undeclared_vars.insert(ident.clone()) {
+            self.report(Diagnostic::error(
+              E_UNDECLARED_VAR,
+              format!("cannot find variable `{}` in this scope", ident),
+              expr.span,
+            ));
+          }
+          ast::Type::Invalid
+        }
+        Some(ty) => ty.clone(),
       },
-      ast::Expr::Binop(op, e1_box, e2_box) => match op {
+      ast::ExprKind::Binop(op, e1_box, e2_box) => match op {
         Opcode::Add => self.infer_int_binop(var_ty_map, op, &**e1_box, &**e2_box),
         Opcode::Sub => self.infer_int_binop(var_ty_map, op, &**e1_box, &**e2_box),
         Opcode::Mul => self.infer_int_binop(var_ty_map, op, &**e1_box, &**e2_box),
         Opcode::Div => self.infer_int_binop(var_ty_map, op, &**e1_box, &**e2_box),
         Opcode::Mod => self.infer_int_binop(var_ty_map, op, &**e1_box, &**e2_box),
 
+        Opcode::Concat => self.infer_str_binop(var_ty_map, op, &**e1_box, &**e2_box),
+
         Opcode::Lt => self.infer_cmp_binop(var_ty_map, op, &**e1_box, &**e2_box),
         Opcode::Leq => self.infer_cmp_binop(var_ty_map, op, &**e1_box, &**e2_box),
         Opcode::Gt => self.infer_cmp_binop(var_ty_map, op, &**e1_box, &**e2_box),
@@ -197,53 +388,272 @@ impl TypeCheckCtx {
         Opcode::LogOr => self.infer_log_binop(var_ty_map, op, &**e1_box, &**e2_box),
         Opcode::LogEq => self.infer_poly_binop(var_ty_map, op, &**e1_box, &**e2_box),
         Opcode::LogNeq => self.infer_poly_binop(var_ty_map, op, &**e1_box, &**e2_box),
+
+        Opcode::BitAnd => self.infer_int_binop(var_ty_map, op, &**e1_box, &**e2_box),
+        Opcode::BitOr => self.infer_int_binop(var_ty_map, op, &**e1_box, &**e2_box),
+        Opcode::BitXor => self.infer_int_binop(var_ty_map, op, &**e1_box, &**e2_box),
+        Opcode::Shl => self.infer_int_binop(var_ty_map, op, &**e1_box, &**e2_box),
+        Opcode::Shr => self.infer_int_binop(var_ty_map, op, &**e1_box, &**e2_box),
+        Opcode::LShr => self.infer_int_binop(var_ty_map, op, &**e1_box, &**e2_box),
+      },
+      ast::ExprKind::Unop(op, e_box) => match expr.negated_literal() {
+        Some((n, int_ty)) => self.check_literal(n, int_ty, expr.span),
+        None => self.infer_unop(var_ty_map, op, &**e_box),
       },
+      ast::ExprKind::Cast(e_box, ty) => self.infer_cast(var_ty_map, &**e_box, ty, expr.span),
+      ast::ExprKind::Call(ident, args) => self.infer_call(var_ty_map, expr.span, ident, args),
+    }
+  }
+
+  // literals, including negated ones like `-128i8`, have to fit their type
+  fn check_literal(&mut self, n: i128, int_ty: IntTy, span: Span) -> ast::Type {
+    if n < int_ty.min() || n > int_ty.max() {
+      self.report(
+        Diagnostic::error(
+          E_LITERAL_OUT_OF_RANGE,
+          format!("literal out of range for `{}`", int_ty),
+          span,
+        )
+        .with_note(format!(
+          "the literal `{}` does not fit into the type `{}` whose range is `{}..={}`",
+          n,
+          int_ty,
+          int_ty.min(),
+          int_ty.max()
+        )),
+      );
     }
+
+    ast::Type::Int(int_ty)
+  }
+
+  fn infer_unop(
+    &mut self,
+    var_ty_map: &HashMap<String, ast::Type>,
+    opcode: &UnOpcode,
+    expr: &ast::Expr,
+  ) -> ast::Type {
+    let inferred_ty = self.infer_expr_type(var_ty_map, expr);
+    let (is_valid, expected) = match (opcode, &inferred_ty) {
+      (_, ast::Type::Invalid) => (true, ""),
+      (UnOpcode::Neg, ty) => (
+        matches!(ty, ast::Type::Int(int_ty) if int_ty.is_signed()),
+        "a signed integer",
+      ),
+      (UnOpcode::Not, ty) => (*ty == ast::Type::Bool, "`bool`"),
+      (UnOpcode::BitNot, ty) => (matches!(ty, ast::Type::Int(_)), "an integer"),
+    };
+    if !is_valid {
+      self.report(
+        Diagnostic::error(
+          E_MISMATCHED_TYPES,
+          format!(
+            "mismatched types: expected {}, found `{}`",
+            expected, inferred_ty
+          ),
+          expr.span,
+        )
+        .with_note(format!("the operand of `{}` must be {}", opcode, expected)),
+      );
+      return ast::Type::Invalid;
+    }
+
+    match opcode {
+      UnOpcode::Not => ast::Type::Bool,
+      UnOpcode::Neg | UnOpcode::BitNot => inferred_ty,
+    }
+  }
+
+  // ints can be cast to any other integer type, nothing else can be cast
+  fn infer_cast(
+    &mut self,
+    var_ty_map: &HashMap<String, ast::Type>,
+    expr: &ast::Expr,
+    ty: &ast::Type,
+    span: Span,
+  ) -> ast::Type {
+    let inferred_ty = self.infer_expr_type(var_ty_map, expr);
+    match (&inferred_ty, ty) {
+      (ast::Type::Int(_), ast::Type::Int(_)) | (ast::Type::Invalid, ast::Type::Int(_)) => {}
+      _ => self.report(
+        Diagnostic::error(
+          E_INVALID_CAST,
+          format!("cannot cast `{}` as `{}`", inferred_ty, ty),
+          span,
+        )
+        .with_note("only integers can be cast, and only to other integer types".to_string()),
+      ),
+    }
+
+    ty.clone()
+  }
+
+  fn infer_call(
+    &mut self,
+    var_ty_map: &HashMap<String, ast::Type>,
+    span: Span,
+    ident: &String,
+    args: &Vec<ast::Expr>,
+  ) -> ast::Type {
+    // check the arguments even if the call itself is bad, they may have errors of their own
+    let inferred_tys: Vec<ast::Type> = args
+      .iter()
+      .map(|arg| self.infer_expr_type(var_ty_map, arg))
+      .collect();
+
+    let fn_ty = match self.fn_type_map.get(ident) {
+      Some(fn_ty) => fn_ty.clone(),
+      None => {
+        self.report(Diagnostic::error(
+          E_UNDEFINED_FN,
+          format!("cannot find function `{}`", ident),
+          span,
+        ));
+        return ast::Type::Invalid;
+      }
+    };
+    let (arg_tys, ret_ty) = match &fn_ty {
+      ast::Type::FnType(arg_tys, ret_ty) => (arg_tys, ret_ty),
+      _ => unreachable!("fn_type_map only holds function types"),
+    };
+
+    if args.len() != arg_tys.len() {
+      self.report(
+        Diagnostic::error(
+          E_ARG_COUNT,
+          format!(
+            "function `{}` takes {} argument(s), but {} were supplied",
+            ident,
+            arg_tys.len(),
+            args.len(),
+          ),
+          span,
+        )
+        .with_note(format!("`{}` has type `{}`", ident, fn_ty)),
+      );
+      // the result type is still known, so the caller can carry on
+      return (**ret_ty).clone();
+    }
+
+    for ((arg, inferred_ty), arg_ty) in args.iter().zip(inferred_tys).zip(arg_tys.iter()) {
+      if ty_mismatch(&inferred_ty, arg_ty) {
+        self.report(
+          Diagnostic::error(
+            E_MISMATCHED_TYPES,
+            format!(
+              "mismatched types: expected `{}`, found `{}`",
+              arg_ty, inferred_ty
+            ),
+            arg.span,
+          )
+          .with_note(format!("`{}` has type `{}`", ident, fn_ty)),
+        );
+      }
+    }
+
+    (**ret_ty).clone()
   }
 
   fn infer_int_binop(
-    &self,
+    &mut self,
+    var_ty_map: &HashMap<String, ast::Type>,
+    opcode: &Opcode,
+    expr1: &ast::Expr,
+    expr2: &ast::Expr,
+  ) -> ast::Type {
+    self.infer_int_operands(var_ty_map, opcode, expr1, expr2)
+  }
+
+  fn infer_str_binop(
+    &mut self,
     var_ty_map: &HashMap<String, ast::Type>,
     opcode: &Opcode,
     expr1: &ast::Expr,
     expr2: &ast::Expr,
-  ) -> Result<ast::Type, String> {
+  ) -> ast::Type {
     return self.infer_binop(
       var_ty_map,
       opcode,
       expr1,
       expr2,
-      ast::Type::Int, // expr1 type
-      ast::Type::Int, // expr2 type
-      ast::Type::Int, // expected return type
+      ast::Type::Str, // expr1 type
+      ast::Type::Str, // expr2 type
+      ast::Type::Str, // expected return type
     );
   }
 
   fn infer_cmp_binop(
-    &self,
+    &mut self,
     var_ty_map: &HashMap<String, ast::Type>,
     opcode: &Opcode,
     expr1: &ast::Expr,
     expr2: &ast::Expr,
-  ) -> Result<ast::Type, String> {
-    return self.infer_binop(
-      var_ty_map,
-      opcode,
-      expr1,
-      expr2,
-      ast::Type::Int,  // expr1 type
-      ast::Type::Int,  // expr2 type
-      ast::Type::Bool, // expected return type
-    );
+  ) -> ast::Type {
+    self.infer_int_operands(var_ty_map, opcode, expr1, expr2);
+    ast::Type::Bool
+  }
+
+  /*
+   * Both operands of an int binop have to be of the same integer type, as
+   * ints of different types are never converted implicitly. Returns that
+   * type, or `Invalid` if neither operand is an int.
+   */
+  fn infer_int_operands(
+    &mut self,
+    var_ty_map: &HashMap<String, ast::Type>,
+    opcode: &Opcode,
+    expr1: &ast::Expr,
+    expr2: &ast::Expr,
+  ) -> ast::Type {
+    let ty1 = self.infer_expr_type(var_ty_map, expr1);
+    let ty2 = self.infer_expr_type(var_ty_map, expr2);
+
+    for (expr, ty, other_ty) in &[(expr1, &ty1, &ty2), (expr2, &ty2, &ty1)] {
+      if matches!(ty, ast::Type::Int(_) | ast::Type::Invalid) {
+        continue;
+      }
+      let expected = match other_ty {
+        ast::Type::Int(_) => format!("`{}`", other_ty),
+        _ => "an integer".to_string(),
+      };
+      self.report(
+        Diagnostic::error(
+          E_MISMATCHED_TYPES,
+          format!("mismatched types: expected {}, found `{}`", expected, ty),
+          expr.span,
+        )
+        .with_note(format!("operands of `{}` must be integers", opcode)),
+      );
+    }
+
+    match (&ty1, &ty2) {
+      (ast::Type::Int(int_ty1), ast::Type::Int(int_ty2)) if int_ty1 != int_ty2 => {
+        self.report(
+          Diagnostic::error(
+            E_MISMATCHED_TYPES,
+            format!("mismatched types: expected `{}`, found `{}`", ty1, ty2),
+            expr2.span,
+          )
+          .with_note(format!(
+            "both sides of `{}` must have the same integer type, convert one with `as`",
+            opcode
+          )),
+        );
+        ty1
+      }
+      (ast::Type::Int(_), _) => ty1,
+      (_, ast::Type::Int(_)) => ty2,
+      _ => ast::Type::Invalid,
+    }
   }
 
   fn infer_log_binop(
-    &self,
+    &mut self,
     var_ty_map: &HashMap<String, ast::Type>,
     opcode: &Opcode,
     expr1: &ast::Expr,
     expr2: &ast::Expr,
-  ) -> Result<ast::Type, String> {
+  ) -> ast::Type {
     return self.infer_binop(
       var_ty_map,
       opcode,
@@ -256,23 +666,37 @@ impl TypeCheckCtx {
   }
 
   fn infer_poly_binop(
-    &self,
+    &mut self,
     var_ty_map: &HashMap<String, ast::Type>,
     opcode: &Opcode,
     expr1: &ast::Expr,
     expr2: &ast::Expr,
-  ) -> Result<ast::Type, String> {
+  ) -> ast::Type {
     let ty1 = self.infer_expr_type(var_ty_map, expr1);
     let ty2 = self.infer_expr_type(var_ty_map, expr2);
 
-    if ty1 != ty2 {
-      return Err(format!(
-        "Error when typechecking binary operand: lhs type does not match rhs {:?}, {:?}",
-        ty1, ty2
+    if ty_mismatch(&ty1, &ty2) {
+      self.report(
+        Diagnostic::error(
+          E_MISMATCHED_TYPES,
+          format!("mismatched types: expected `{}`, found `{}`", ty1, ty2),
+          expr2.span,
+        )
+        .with_note(format!(
+          "both sides of `{}` must have the same type",
+          opcode
+        )),
+      );
+    } else if ty1 == ast::Type::Str {
+      // strings are pointers once compiled, so this would compare addresses
+      self.report(Diagnostic::error(
+        E_UNSUPPORTED_OP,
+        format!("operator `{}` is not supported on `string`", opcode),
+        expr1.span.to(expr2.span),
       ));
     }
 
-    Ok(self.op_result_ty(opcode))
+    ast::Type::Bool
   }
 
   /*
@@ -287,7 +711,7 @@ impl TypeCheckCtx {
    * - type to return if both sides typecheck
    */
   fn infer_binop(
-    &self,
+    &mut self,
     var_ty_map: &HashMap<String, ast::Type>,
     opcode: &Opcode,
     expr1: &ast::Expr,
@@ -295,51 +719,41 @@ impl TypeCheckCtx {
     ty1: ast::Type,
     ty2: ast::Type,
     ty_binop: ast::Type,
-  ) -> Result<ast::Type, String> {
-    let result1 = self.check_expr_type(var_ty_map, expr1, ty1);
-    let result2 = self.check_expr_type(var_ty_map, expr2, ty2);
+  ) -> ast::Type {
+    // the result type only depends on the operator, so it is valid even if an operand is not
+    self.check_expr_type(var_ty_map, opcode, expr1, &ty1);
+    self.check_expr_type(var_ty_map, opcode, expr2, &ty2);
 
-    match (result1, result2) {
-      (Ok(_), Ok(_)) => Ok(ty_binop),
-      _ => Err(format!("Wrong types to binary operand {:?}", opcode)),
-    }
+    ty_binop
   }
 
   fn check_expr_type(
-    &self,
+    &mut self,
     var_ty_map: &HashMap<String, ast::Type>,
+    opcode: &Opcode,
     expr: &ast::Expr,
-    ty: ast::Type,
-  ) -> Result<(), String> {
-    let inferred_ty = self.infer_expr_type(var_ty_map, expr)?;
-    // println!("{:?}, {:?}, {:?}", inferred_ty, ty, inferred_ty == ty);
-    if inferred_ty == ty {
-      return Ok(());
+    ty: &ast::Type,
+  ) {
+    let inferred_ty = self.infer_expr_type(var_ty_map, expr);
+    if !ty_mismatch(&inferred_ty, ty) {
+      return;
     }
 
-    panic!(
-      "Error when checking ```{:?}``` against type ```{:?}```",
-      expr, ty
+    self.report(
+      Diagnostic::error(
+        E_MISMATCHED_TYPES,
+        format!(
+          "mismatched types: expected `{}`, found `{}`",
+          ty, inferred_ty
+        ),
+        expr.span,
+      )
+      .with_note(format!("operands of `{}` must be of type `{}`", opcode, ty)),
     );
   }
+}
 
-  fn op_result_ty(&self, opcode: &Opcode) -> ast::Type {
-    match opcode {
-      Opcode::Add => ast::Type::Int,
-      Opcode::Sub => ast::Type::Int,
-      Opcode::Mul => ast::Type::Int,
-      Opcode::Div => ast::Type::Int,
-      Opcode::Mod => ast::Type::Int,
-
-      Opcode::Lt => ast::Type::Bool,
-      Opcode::Leq => ast::Type::Bool,
-      Opcode::Gt => ast::Type::Bool,
-      Opcode::Geq => ast::Type::Bool,
-
-      Opcode::LogAnd => ast::Type::Bool,
-      Opcode::LogOr => ast::Type::Bool,
-      Opcode::LogEq => ast::Type::Bool,
-      Opcode::LogNeq => ast::Type::Bool,
-    }
-  }
+// `Invalid` types come from errors that have already been reported, so they match anything
+fn ty_mismatch(ty1: &ast::Type, ty2: &ast::Type) -> bool {
+  *ty1 != ast::Type::Invalid && *ty2 != ast::Type::Invalid && ty1 != ty2
 }
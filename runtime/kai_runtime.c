@@ -0,0 +1,30 @@
+// Runtime support for compiled Kai programs.
+//
+// Strings are NUL-terminated and immutable. Concatenation allocates a new
+// string that is never freed, since Kai has no memory management yet.
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+char *kai_str_concat(const char *s1, const char *s2) {
+  size_t len1 = strlen(s1);
+  size_t len2 = strlen(s2);
+  char *res = malloc(len1 + len2 + 1);
+  if (res == NULL) {
+    fputs("kai: out of memory\n", stderr);
+    abort();
+  }
+
+  memcpy(res, s1, len1);
+  memcpy(res + len1, s2, len2 + 1);
+  return res;
+}
+
+int kai_str_len(const char *s) { return (int)strlen(s); }
+
+// Called by compiled programs on a runtime error, e.g. division by zero.
+void kai_panic(const char *msg) {
+  fprintf(stderr, "kai: %s\n", msg);
+  abort();
+}
@@ -0,0 +1,145 @@
+use kai_common::opt_level::OptLevel;
+use kai_common::overflow_mode::OverflowMode;
+use kai_ir::pass_manager::PassManager;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+usage: kailang [options] <file.kai>...
+
+options:
+  -o <path>          write the output to <path>
+  --emit=<kind>      what to produce: ast, ir, ssa, llvm-ir, asm, obj or exe (default)
+  -O<level>          optimization level, 0 (default) to 3
+  --overflow=<mode>  integer overflow in functions without `@overflow`: wrap (default), trap
+                     or saturate
+  --target=<triple>  generate code for the given target instead of the host
+  --save-temps       keep intermediate files, e.g. the object of an executable
+  --interpret        run the program in the AST interpreter instead of compiling it
+  --print-after=<pass>
+                     print the IR after each run of an optimization pass, to stderr
+  --time-passes      report the time spent in each optimization pass, to stderr
+  -h, --help         print this message";
+
+// the stage the compiler stops at, and what it writes out
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Emit {
+  Ast,
+  Ir,
+  Ssa,
+  LlvmIr,
+  Asm,
+  Obj,
+  Exe,
+}
+
+impl Emit {
+  fn from_str(s: &str) -> Result<Emit, String> {
+    match s {
+      "ast" => Ok(Emit::Ast),
+      "ir" => Ok(Emit::Ir),
+      "ssa" => Ok(Emit::Ssa),
+      "llvm-ir" => Ok(Emit::LlvmIr),
+      "asm" => Ok(Emit::Asm),
+      "obj" => Ok(Emit::Obj),
+      "exe" => Ok(Emit::Exe),
+      _ => Err(format!(
+        "unknown emit kind `{}`, expected one of ast, ir, ssa, llvm-ir, asm, obj, exe",
+        s
+      )),
+    }
+  }
+
+  // extension of the output file when no `-o` is given, None if it goes to stdout
+  pub fn default_extension(self) -> Option<&'static str> {
+    match self {
+      Emit::Ast | Emit::Ir | Emit::Ssa => None,
+      Emit::LlvmIr => Some("ll"),
+      Emit::Asm => Some("s"),
+      Emit::Obj => Some("o"),
+      Emit::Exe => Some(""),
+    }
+  }
+}
+
+#[derive(Debug)]
+pub struct Options {
+  pub inputs: Vec<PathBuf>,
+  pub output: Option<PathBuf>,
+  pub emit: Emit,
+  pub opt_level: OptLevel,
+  pub overflow: OverflowMode,
+  pub target: Option<String>,
+  pub save_temps: bool,
+  pub interpret: bool,
+  pub print_after: Option<String>,
+  pub time_passes: bool,
+}
+
+/*
+ * Parses the command line, without the program name. Returns None if only
+ * the usage was asked for.
+ *
+ * Options taking a value accept it both as `--emit=ir` and `--emit ir`.
+ */
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<Options>, String> {
+  let mut options = Options {
+    inputs: vec![],
+    output: None,
+    emit: Emit::Exe,
+    opt_level: OptLevel::default(),
+    overflow: OverflowMode::default(),
+    target: None,
+    save_temps: false,
+    interpret: false,
+    print_after: None,
+    time_passes: false,
+  };
+
+  let mut args = args.into_iter();
+  while let Some(arg) = args.next() {
+    let (flag, inline_value) = match arg.find('=') {
+      Some(i) if arg.starts_with("--") => (&arg[..i], Some(arg[i + 1..].to_string())),
+      _ => (arg.as_str(), None),
+    };
+    let mut value = || {
+      inline_value
+        .clone()
+        .or_else(|| args.next())
+        .ok_or_else(|| format!("option `{}` requires a value", flag))
+    };
+
+    match flag {
+      "-h" | "--help" => return Ok(None),
+      "-o" => options.output = Some(PathBuf::from(value()?)),
+      "--emit" => options.emit = Emit::from_str(&value()?)?,
+      "--overflow" => options.overflow = value()?.parse()?,
+      "--target" => options.target = Some(value()?),
+      "--save-temps" => options.save_temps = true,
+      "--interpret" => options.interpret = true,
+      "--print-after" => {
+        let pass = value()?;
+        let names = PassManager::all_pass_names();
+        if !names.contains(&pass.as_str()) {
+          return Err(format!(
+            "unknown pass `{}`, expected one of {}",
+            pass,
+            names.join(", ")
+          ));
+        }
+        options.print_after = Some(pass);
+      }
+      "--time-passes" => options.time_passes = true,
+      _ if flag.starts_with("-O") => options.opt_level = flag[2..].parse()?,
+      _ if flag.starts_with('-') => {
+        return Err(format!("unknown option `{}`", flag));
+      }
+      _ => options.inputs.push(PathBuf::from(arg)),
+    }
+  }
+
+  if options.inputs.is_empty() {
+    return Err("no input files".to_string());
+  }
+
+  Ok(Some(options))
+}
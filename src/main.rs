@@ -1,33 +1,168 @@
-use kai_ir::ir_gen;
-use kai_llvm_gen::llvm_gen;
-use kai_parse::grammar::*;
-use kai_typecheck::typecheck;
-use std::path::{Path, PathBuf};
-use std::{env, str::FromStr};
-use std::{fs::File, io::Read};
-
-fn main() -> std::io::Result<()> {
-  let args: Vec<String> = env::args().collect();
-  let filename = &args[1];
-
-  let mut dir = PathBuf::from_str(env!("CARGO_MANIFEST_DIR")).unwrap();
-  dir.push(Path::new(filename));
-  let mut file = File::open(Path::new(filename))?;
+use kai_common::diagnostics::{Diagnostic, SourceFile, SourceMap};
+use kai_common::remove_comments::remove_comments;
+use kai_ir::pass_manager::PassManager;
+use kai_llvm_gen::link::build_executable;
+use kai_llvm_gen::{
+  llvm_ir_gen, llvm_pipeline, run_llvm_passes, write_llvm_ir, write_target_file, FileType,
+  TargetOptions,
+};
+use kai_parse::parse_program;
+use kai_typecheck::typecheck_with_warnings;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process;
+use std::{env, fs};
+
+mod cli;
+
+use cli::{Emit, Options};
+
+fn main() {
+  let options = match cli::parse_args(env::args().skip(1)) {
+    Ok(Some(options)) => options,
+    Ok(None) => {
+      println!("{}", cli::USAGE);
+      return;
+    }
+    Err(msg) => {
+      eprintln!("error: {}\n\n{}", msg, cli::USAGE);
+      process::exit(2);
+    }
+  };
+
+  let (source_map, program) = read_sources(&options.inputs);
+  let mut ast =
+    parse_program(&program).unwrap_or_else(|diag| report_and_exit(&source_map, vec![diag]));
+  if options.emit == Emit::Ast {
+    return write_text(&options, format!("{:#?}", ast));
+  }
+  // an `@overflow` attribute takes precedence over `--overflow`
+  for func in ast.functions.iter_mut() {
+    func.overflow.get_or_insert(options.overflow);
+  }
+
+  let (fn_var_ty_maps, warnings) =
+    typecheck_with_warnings(&ast).unwrap_or_else(|diags| report_and_exit(&source_map, diags));
+  for warning in &warnings {
+    eprintln!("{}\n", source_map.render(warning));
+  }
+  // exits like the compiled program would, with the result of `main`
+  if options.interpret {
+    match kai_interp::ast_interp::run_main(&ast) {
+      Ok(res) => process::exit(res),
+      Err(diag) => report_and_exit(&source_map, vec![diag]),
+    }
+  }
+  if options.emit == Emit::Ir {
+    let ir = kai_ir::ir_gen_pre_ssa(&ast, fn_var_ty_maps);
+    return write_text(&options, ir.to_string());
+  }
+  let mut ir = kai_ir::ir_gen(&ast, fn_var_ty_maps);
+  let mut pass_manager = PassManager::for_opt_level(options.opt_level);
+  pass_manager.print_after = options.print_after.clone();
+  pass_manager.time_passes = options.time_passes;
+  let diags = pass_manager.run(&mut ir);
+  if !diags.is_empty() {
+    report_and_exit(&source_map, diags);
+  }
+  if options.emit == Emit::Ssa {
+    return write_text(&options, ir.to_string());
+  }
+
+  let output = output_path(&options);
+  let module_name = options.inputs[0]
+    .file_name()
+    .unwrap()
+    .to_string_lossy()
+    .into_owned();
+  let target_options = TargetOptions {
+    triple: options.target.clone(),
+    opt_level: options.opt_level,
+  };
+  let result = unsafe {
+    let ll_module = llvm_ir_gen(&ir, &module_name, &source_map);
+    run_llvm_passes(ll_module, &llvm_pipeline(options.opt_level));
+    match options.emit {
+      Emit::LlvmIr => write_llvm_ir(ll_module, &output),
+      Emit::Asm => write_target_file(ll_module, &output, FileType::Asm, &target_options),
+      Emit::Obj => write_target_file(ll_module, &output, FileType::Obj, &target_options),
+      _ => build_executable(ll_module, &output, &target_options, options.save_temps),
+    }
+  };
+  if let Err(diag) = result {
+    report_and_exit(&source_map, vec![diag]);
+  }
+}
+
+/*
+ * Reads every input into one program, see `SourceMap`. Comments are blanked
+ * per file, so an unterminated comment cannot swallow the files after it.
+ */
+fn read_sources(inputs: &[PathBuf]) -> (SourceMap, String) {
+  let mut source_map = SourceMap::new();
   let mut program = String::new();
-  // println!("filename {:?}", filename);
-  // println!("dir {:?}", dir);
-  file.read_to_string(&mut program)?;
-  program = kai_common::remove_comments::remove_comments(&program).unwrap();
-  // println!("program: {:?}", program);
-
-  let parser = FunctionParser::new();
-  let ast = parser.parse(&program).unwrap();
-  println!("ast: {:#?}", ast);
-  let var_ty_map = typecheck(&ast).unwrap();
-  let ir = ir_gen(&ast, var_ty_map);
-  // println!("ir: {}", ir.to_string());
-  unsafe {
-    llvm_gen(&ir, &mut dir);
-  }
-  Ok(())
+  let mut diags = vec![];
+  for path in inputs {
+    let src = fs::read_to_string(path)
+      .unwrap_or_else(|err| fail(&format!("could not read `{}`: {}", path.display(), err)));
+    match remove_comments(&src) {
+      Ok(blanked) => program.push_str(&blanked),
+      Err(mut diag) => {
+        diag.span = diag.span.map(|span| span.shift(program.len()));
+        diags.push(diag);
+        program.push_str(&src);
+      }
+    }
+    program.push('\n');
+    source_map.add_file(SourceFile::new(path.display().to_string(), src));
+  }
+
+  if !diags.is_empty() {
+    report_and_exit(&source_map, diags);
+  }
+  (source_map, program)
+}
+
+// `-o` if given, otherwise named after the first input, in the current directory
+fn output_path(options: &Options) -> PathBuf {
+  match (&options.output, options.emit.default_extension()) {
+    (Some(output), _) => output.clone(),
+    (None, ext) => {
+      let stem = options.inputs[0].file_stem().unwrap();
+      PathBuf::from(stem).with_extension(ext.unwrap_or(""))
+    }
+  }
+}
+
+// textual output goes to stdout unless `-o` is given
+fn write_text(options: &Options, text: String) {
+  match &options.output {
+    Some(output) => fs::write(output, text)
+      .unwrap_or_else(|err| fail(&format!("could not write `{}`: {}", output.display(), err))),
+    // e.g. `kailang --emit=ir a.kai | head` closing the pipe early is fine
+    None => match writeln!(io::stdout(), "{}", text) {
+      Err(err) if err.kind() != io::ErrorKind::BrokenPipe => {
+        fail(&format!("could not write to stdout: {}", err))
+      }
+      _ => {}
+    },
+  }
+}
+
+fn fail(msg: &str) -> ! {
+  eprintln!("error: {}", msg);
+  process::exit(1);
+}
+
+fn report_and_exit(source_map: &SourceMap, diags: Vec<Diagnostic>) -> ! {
+  for diag in &diags {
+    eprintln!("{}\n", source_map.render(diag));
+  }
+  let num_errors = diags.iter().filter(|diag| diag.is_error()).count();
+  if num_errors == 1 {
+    eprintln!("error: aborting due to previous error");
+  } else {
+    eprintln!("error: aborting due to {} previous errors", num_errors);
+  }
+  process::exit(1);
 }
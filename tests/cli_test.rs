@@ -0,0 +1,465 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+const KAILANG: &str = env!("CARGO_BIN_EXE_kailang");
+
+// a fresh directory per test, since tests run in parallel
+#[allow(dead_code)]
+fn scratch_dir(name: &str) -> PathBuf {
+  let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+  let _ = fs::remove_dir_all(&dir);
+  fs::create_dir_all(&dir).unwrap();
+  dir
+}
+
+#[allow(dead_code)]
+fn kailang(args: &[&str]) -> Output {
+  Command::new(KAILANG)
+    .args(args)
+    .output()
+    .expect("could not run kailang")
+}
+
+#[allow(dead_code)]
+fn example(path: &str) -> String {
+  format!("{}/example/{}", env!("CARGO_MANIFEST_DIR"), path)
+}
+
+#[test]
+fn test_emit_ir_and_ssa() {
+  let src = example("loops/kai_while_args.kai");
+
+  let out = kailang(&["--emit=ir", &src]);
+  assert!(out.status.success());
+  let ir = String::from_utf8(out.stdout).unwrap();
+  assert!(ir.contains("fact(") && !ir.contains("phi("), "{}", ir);
+
+  let out = kailang(&["--emit", "ssa", &src]);
+  assert!(out.status.success());
+  let ssa = String::from_utf8(out.stdout).unwrap();
+  assert!(ssa.contains("phi("), "{}", ssa);
+}
+
+#[test]
+fn test_emit_exe() {
+  let dir = scratch_dir("test_emit_exe");
+  let exe = dir.join("fact");
+
+  let out = kailang(&[
+    "-O2",
+    "-o",
+    exe.to_str().unwrap(),
+    &example("loops/kai_while_args.kai"),
+  ]);
+  assert!(
+    out.status.success(),
+    "{}",
+    String::from_utf8_lossy(&out.stderr)
+  );
+  let res = Command::new(&exe).status().unwrap();
+  assert_eq!(res.code(), Some(24));
+  // the object is an intermediate file
+  assert!(!dir.join("fact.o").exists());
+
+  let out = kailang(&[
+    "--save-temps",
+    "-o",
+    exe.to_str().unwrap(),
+    &example("loops/kai_while_args.kai"),
+  ]);
+  assert!(
+    out.status.success(),
+    "{}",
+    String::from_utf8_lossy(&out.stderr)
+  );
+  assert!(dir.join("fact.o").exists());
+}
+
+#[test]
+fn test_interpret() {
+  let out = kailang(&["--interpret", &example("loops/kai_while_args.kai")]);
+  assert_eq!(out.status.code(), Some(24));
+
+  let dir = scratch_dir("test_interpret");
+  let src = dir.join("div.kai");
+  fs::write(
+    &src,
+    "function main(): int {\n  let x = 0;\n  return 1 / x;\n}",
+  )
+  .unwrap();
+  let out = kailang(&["--interpret", src.to_str().unwrap()]);
+  assert_eq!(out.status.code(), Some(1));
+  let stderr = String::from_utf8(out.stderr).unwrap();
+  assert!(
+    stderr.contains("error[E0301]: attempt to calculate `1 / 0`"),
+    "{}",
+    stderr
+  );
+  assert!(
+    stderr.contains(&format!("--> {}:3:10", src.display())),
+    "{}",
+    stderr
+  );
+}
+
+#[test]
+fn test_constant_propagation() {
+  let src = example("basic/kai_arithmetic.kai");
+  let out = kailang(&["--emit=ssa", "-O1", &src]);
+  assert!(out.status.success());
+  let ssa = String::from_utf8(out.stdout).unwrap();
+  assert!(ssa.contains("ret 5"), "{}", ssa);
+
+  // a division known to trap is an error, whether optimizing or not
+  let dir = scratch_dir("test_constant_propagation");
+  let src = dir.join("div.kai");
+  fs::write(
+    &src,
+    "function main(): int {\n  let x = 0;\n  return 1 / x;\n}",
+  )
+  .unwrap();
+  for opt_level in &["-O0", "-O1"] {
+    let out = kailang(&[
+      opt_level,
+      "--emit=obj",
+      "-o",
+      dir.join("div.o").to_str().unwrap(),
+      src.to_str().unwrap(),
+    ]);
+    assert_eq!(out.status.code(), Some(1));
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(
+      stderr.contains("error[E0301]: attempt to calculate `1 / 0`"),
+      "{}",
+      stderr
+    );
+    assert!(
+      stderr.contains(&format!("--> {}:3:10", src.display())),
+      "{}",
+      stderr
+    );
+    assert!(stderr.contains("= note: in function `main`"), "{}", stderr);
+  }
+  assert!(!dir.join("div.o").exists());
+}
+
+#[test]
+fn test_runtime_errors() {
+  let dir = scratch_dir("test_runtime_errors");
+  let src = dir.join("overflow.kai");
+  fs::write(
+    &src,
+    "function div(x: int, y: int): int {\n  return x / y;\n}\nfunction add(x: int, y: int): int {\n  return x + y;\n}\nfunction main(): int {\n  return add(2147483647, 1) + div(1, 0);\n}",
+  )
+  .unwrap();
+  let exe = dir.join("overflow");
+  let run = |args: &[&str]| {
+    let out = kailang(&[args, &["-o", exe.to_str().unwrap(), src.to_str().unwrap()]].concat());
+    assert!(
+      out.status.success(),
+      "{}",
+      String::from_utf8_lossy(&out.stderr)
+    );
+    Command::new(&exe).output().unwrap()
+  };
+
+  // division by zero aborts in every overflow mode, pointing at the division
+  let out = run(&["-O2"]);
+  assert!(!out.status.success());
+  let stderr = String::from_utf8(out.stderr).unwrap();
+  let expected = format!("kai: {}:2:10: attempt to divide by zero", src.display());
+  assert!(stderr.contains(&expected), "{}", stderr);
+
+  let out = run(&["--overflow=trap"]);
+  assert!(!out.status.success());
+  let stderr = String::from_utf8(out.stderr).unwrap();
+  let expected = format!("kai: {}:5:10: attempt to add with overflow", src.display());
+  assert!(stderr.contains(&expected), "{}", stderr);
+
+  // an attribute takes precedence over `--overflow`
+  let src_text = fs::read_to_string(&src).unwrap();
+  fs::write(
+    &src,
+    src_text.replace("function add", "@overflow(wrap)\nfunction add"),
+  )
+  .unwrap();
+  let out = run(&["--overflow", "trap"]);
+  let stderr = String::from_utf8(out.stderr).unwrap();
+  assert!(stderr.contains("attempt to divide by zero"), "{}", stderr);
+
+  let out = kailang(&["--overflow=checked", src.to_str().unwrap()]);
+  assert_eq!(out.status.code(), Some(2));
+  assert!(String::from_utf8_lossy(&out.stderr).contains("invalid overflow mode `checked`"));
+}
+
+#[test]
+fn test_value_numbering() {
+  let dir = scratch_dir("test_value_numbering");
+  let src = dir.join("cse.kai");
+  fs::write(
+    &src,
+    "function f(a: int, b: int): int {\n  return a * b + b * a;\n}\nfunction main(): int {\n  return f(2, 3);\n}",
+  )
+  .unwrap();
+
+  // only with `-O1` is the product computed once
+  let products = |args: &[&str]| {
+    let out = kailang(args);
+    assert!(
+      out.status.success(),
+      "{}",
+      String::from_utf8_lossy(&out.stderr)
+    );
+    String::from_utf8(out.stdout)
+      .unwrap()
+      .matches(" * ")
+      .count()
+  };
+  assert_eq!(products(&["--emit=ssa", src.to_str().unwrap()]), 2);
+  assert_eq!(products(&["-O1", "--emit=ssa", src.to_str().unwrap()]), 1);
+}
+
+#[test]
+fn test_llvm_passes() {
+  let dir = scratch_dir("test_llvm_passes");
+  let src = dir.join("inline.kai");
+  fs::write(
+    &src,
+    "function double(x: int): int {\n  return x * 2;\n}\nfunction main(): int {\n  return double(21);\n}",
+  )
+  .unwrap();
+  let llvm_ir = |opt: &str| {
+    let ll = dir.join(format!("inline{}.ll", opt));
+    let out = kailang(&[
+      opt,
+      "--emit=llvm-ir",
+      "-o",
+      ll.to_str().unwrap(),
+      src.to_str().unwrap(),
+    ]);
+    assert!(
+      out.status.success(),
+      "{}",
+      String::from_utf8_lossy(&out.stderr)
+    );
+    fs::read_to_string(ll).unwrap()
+  };
+
+  // `double` is only inlined from `-O2` on
+  let ll = llvm_ir("-O1");
+  assert!(ll.contains("call i32 @kai.double(i32 21)"), "{}", ll);
+  let ll = llvm_ir("-O2");
+  assert!(!ll.contains("call i32 @kai.double"), "{}", ll);
+  assert!(ll.contains("ret i32 42"), "{}", ll);
+}
+
+#[test]
+fn test_pass_flags() {
+  let src = example("basic/kai_arithmetic.kai");
+  let out = kailang(&[
+    "-O2",
+    "--emit=ssa",
+    "--print-after=sccp",
+    "--time-passes",
+    &src,
+  ]);
+  assert!(
+    out.status.success(),
+    "{}",
+    String::from_utf8_lossy(&out.stderr)
+  );
+  let stderr = String::from_utf8(out.stderr).unwrap();
+  // `-O2` runs the pipeline twice
+  assert_eq!(
+    stderr.matches("*** IR after sccp ***\nmain(): int").count(),
+    2,
+    "{}",
+    stderr
+  );
+  assert!(stderr.contains("pass timings:"), "{}", stderr);
+  assert!(stderr.contains("  gvn "), "{}", stderr);
+
+  // nothing is printed if the pass is not in the pipeline
+  let out = kailang(&["--emit=ssa", "--print-after=sccp", &src]);
+  assert!(out.status.success());
+  assert!(
+    out.stderr.is_empty(),
+    "{}",
+    String::from_utf8_lossy(&out.stderr)
+  );
+
+  let out = kailang(&["--print-after=inline", &src]);
+  assert_eq!(out.status.code(), Some(2));
+  assert!(String::from_utf8_lossy(&out.stderr).contains("unknown pass `inline`"));
+}
+
+#[test]
+fn test_unreachable_code() {
+  let dir = scratch_dir("test_unreachable_code");
+  let src = dir.join("unreachable.kai");
+  fs::write(
+    &src,
+    "function main(): int {\n  let x = 1;\n  if x < 2 {\n    return 3;\n  }\n  return x;\n  x = 4;\n}",
+  )
+  .unwrap();
+
+  // a warning does not stop the program from being compiled
+  let out = kailang(&["-O1", "--emit=ssa", src.to_str().unwrap()]);
+  assert!(
+    out.status.success(),
+    "{}",
+    String::from_utf8_lossy(&out.stderr)
+  );
+  let stderr = String::from_utf8(out.stderr).unwrap();
+  assert!(
+    stderr.contains("warning[W0001]: unreachable statement"),
+    "{}",
+    stderr
+  );
+  assert!(stderr.contains("unreachable.kai:7:3"), "{}", stderr);
+
+  // the branch is resolved, so only the block returning 3 is left
+  let ssa = String::from_utf8(out.stdout).unwrap();
+  assert!(ssa.contains("ret 3"), "{}", ssa);
+  assert!(!ssa.contains("goto") && !ssa.contains("branch"), "{}", ssa);
+}
+
+#[test]
+fn test_link_without_main() {
+  let dir = scratch_dir("test_link_without_main");
+  let src = dir.join("lib.kai");
+  let exe = dir.join("lib");
+
+  // an object needs no `main`, an executable needs a `main(): int`
+  for prog in &[
+    "function f(): int { return 1; }",
+    "function main(x: int): int { return x; }",
+  ] {
+    fs::write(&src, prog).unwrap();
+    let out = kailang(&[
+      "--emit=obj",
+      "-o",
+      exe.to_str().unwrap(),
+      src.to_str().unwrap(),
+    ]);
+    assert!(
+      out.status.success(),
+      "{}",
+      String::from_utf8_lossy(&out.stderr)
+    );
+
+    let out = kailang(&["-o", exe.to_str().unwrap(), src.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(1));
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(
+      stderr.starts_with("error[E0300]: `main` function not found"),
+      "{}",
+      stderr
+    );
+  }
+}
+
+#[test]
+fn test_foreign_target() {
+  let dir = scratch_dir("test_foreign_target");
+  let src = example("loops/kai_while_args.kai");
+
+  // objects can be emitted for any target, but only linked for the host
+  let obj = dir.join("fact.o");
+  let out = kailang(&[
+    "--target=aarch64-unknown-linux-gnu",
+    "--emit=obj",
+    "-o",
+    obj.to_str().unwrap(),
+    &src,
+  ]);
+  assert!(
+    out.status.success(),
+    "{}",
+    String::from_utf8_lossy(&out.stderr)
+  );
+  assert!(obj.exists());
+
+  let exe = dir.join("fact");
+  let out = kailang(&[
+    "--target=aarch64-unknown-linux-gnu",
+    "-o",
+    exe.to_str().unwrap(),
+    &src,
+  ]);
+  assert_eq!(out.status.code(), Some(1));
+  let stderr = String::from_utf8(out.stderr).unwrap();
+  assert!(
+    stderr
+      .contains("error[E0201]: cannot link an executable for target `aarch64-unknown-linux-gnu`"),
+    "{}",
+    stderr
+  );
+  assert!(!exe.exists());
+
+  // triples without a vendor are not mistaken for the host either
+  for target in &["--target=bogus", "--target=x86_64"] {
+    let out = kailang(&[target, "--emit=exe", "-o", exe.to_str().unwrap(), &src]);
+    assert_eq!(out.status.code(), Some(1));
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(stderr.contains("error[E0201]"), "{}", stderr);
+    assert!(!exe.exists());
+  }
+}
+
+#[test]
+fn test_multiple_inputs() {
+  let dir = scratch_dir("test_multiple_inputs");
+  let lib = dir.join("lib.kai");
+  let main = dir.join("main.kai");
+  fs::write(&lib, "function double(x: int): int { return x * 2; }").unwrap();
+  fs::write(&main, "function main(): int {\n  return double(21);\n}").unwrap();
+
+  let exe = dir.join("main");
+  let out = kailang(&[
+    "-o",
+    exe.to_str().unwrap(),
+    lib.to_str().unwrap(),
+    main.to_str().unwrap(),
+  ]);
+  assert!(
+    out.status.success(),
+    "{}",
+    String::from_utf8_lossy(&out.stderr)
+  );
+  assert_eq!(Command::new(&exe).status().unwrap().code(), Some(42));
+
+  // errors point into the file they are in
+  fs::write(&main, "function main(): int {\n  return double(true);\n}").unwrap();
+  let out = kailang(&[
+    "--emit=llvm-ir",
+    lib.to_str().unwrap(),
+    main.to_str().unwrap(),
+  ]);
+  assert_eq!(out.status.code(), Some(1));
+  let stderr = String::from_utf8(out.stderr).unwrap();
+  assert!(
+    stderr.contains(&format!("--> {}:2:17", main.display())),
+    "{}",
+    stderr
+  );
+}
+
+#[test]
+fn test_usage_errors() {
+  let out = kailang(&[]);
+  assert_eq!(out.status.code(), Some(2));
+  assert!(String::from_utf8_lossy(&out.stderr).contains("no input files"));
+
+  let out = kailang(&["--emit=wasm", "a.kai"]);
+  assert_eq!(out.status.code(), Some(2));
+  assert!(String::from_utf8_lossy(&out.stderr).contains("unknown emit kind `wasm`"));
+
+  let out = kailang(&["-O4", "a.kai"]);
+  assert_eq!(out.status.code(), Some(2));
+
+  let out = kailang(&["does_not_exist.kai"]);
+  assert_eq!(out.status.code(), Some(1));
+  assert!(String::from_utf8_lossy(&out.stderr).contains("could not read `does_not_exist.kai`"));
+}
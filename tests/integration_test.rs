@@ -1,14 +1,22 @@
 use std::{fs::File, io::Read, path::PathBuf, process::Command, str::FromStr};
 
-use kai_ast;
-use kai_common;
-use kai_ir;
-use kai_llvm_gen;
-use kai_parse;
-use kai_typecheck;
+use kai_common::diagnostics::SourceMap;
+use kai_common::opt_level::OptLevel;
 use regex::Regex;
 
-fn test_compile(mut path: PathBuf) -> Result<(), String> {
+/*
+ * Compiles and runs the example at path, checking its `@expect`/`@fail` marker.
+ *
+ * With out_of_ssa set, phis are removed from the IR before it is handed to
+ * LLVM, to check that the result of out-of-SSA translation still computes
+ * the same values.
+ *
+ * Above `-O0`, the IR and LLVM passes of that level run before the
+ * executable is built, like they do in the driver.
+ *
+ * Both interpreters run the example as well, and have to agree.
+ */
+fn test_compile(mut path: PathBuf, out_of_ssa: bool, opt_level: OptLevel) -> Result<(), String> {
   let mut file = File::open(&path).unwrap();
   let mut program = String::new();
   let re = Regex::new(r"(?P<e>@expect\(\d*\))|(?P<f>@fail)|(?P<i>@ignore)").unwrap();
@@ -21,33 +29,64 @@ fn test_compile(mut path: PathBuf) -> Result<(), String> {
   let fail_cap = caps.name("f");
   let ignore_cap = caps.name("i");
 
-  if let Some(_) = ignore_cap {
+  if ignore_cap.is_some() {
     return Ok(());
   }
 
-  let prog = kai_common::remove_comments::remove_comments(&program).unwrap();
-
-  let parser = kai_parse::grammar::FunctionParser::new();
-  let ast = parser.parse(&prog).unwrap();
-  let typecheck_result = kai_typecheck::typecheck(&ast);
+  // syntax errors count as failures, like type errors do
+  let typecheck_result = kai_common::remove_comments::remove_comments(&program)
+    .and_then(|prog| kai_parse::parse_program(&prog))
+    .map_err(|diag| vec![diag])
+    .and_then(|ast| kai_typecheck::typecheck(&ast).map(|maps| (ast, maps)));
   match typecheck_result {
-    Ok(var_ty_map) => {
-      if let None = expect_cap {
+    Ok((ast, fn_var_ty_maps)) => {
+      if expect_cap.is_none() {
         return Err(format!("Expected failure, but got success on {:?}", path));
       }
-      let ir = kai_ir::ir_gen(&ast, var_ty_map);
-      unsafe {
-        kai_llvm_gen::llvm_gen(&ir, &mut path);
+      let mut ir = kai_ir::ir_gen(&ast, fn_var_ty_maps);
+      let diags = kai_ir::pass_manager::PassManager::for_opt_level(opt_level).run(&mut ir);
+      if !diags.is_empty() {
+        return Err(format!("optimizing {:?} failed: {:?}", path, diags));
+      }
+      if opt_level > OptLevel::O0 {
+        let stem = path.file_stem().unwrap().to_str().unwrap().to_string();
+        path.set_file_name(format!("{}_{:?}.kai", stem, opt_level));
+      }
+      if out_of_ssa {
+        for ir_func in ir.functions.iter_mut() {
+          kai_ir::out_of_ssa::destruct_ssa(ir_func);
+        }
+        // keep the executables of both runs of the same example apart
+        let stem = path.file_stem().unwrap().to_str().unwrap().to_string();
+        path.set_file_name(format!("{}_out_of_ssa.kai", stem));
+      }
+      // what is handed to LLVM has to survive being printed and read back
+      let ir_text = ir.to_string();
+      match kai_ir::ir_parse::parse_ir_program(&ir_text) {
+        Ok(parsed) if parsed.to_string() == ir_text => {}
+        res => {
+          return Err(format!(
+            "IR of {:?} does not round-trip: {:?}\n{}",
+            path,
+            res.err(),
+            ir_text
+          ))
+        }
       }
 
-      let obj_stem = path.file_stem().unwrap().to_str().unwrap();
-      let obj_name = format!("{}.o", obj_stem);
-      path.pop();
-      path.push(obj_name);
-      Command::new("gcc")
-        .arg(path.to_str().unwrap())
-        .status()
-        .expect("Could not invoke gcc");
+      // tests run in parallel, so each executable needs its own name
+      let exe_path = path.with_extension("out");
+      unsafe {
+        let ll_module = kai_llvm_gen::llvm_ir_gen(
+          &ir,
+          path.file_name().unwrap().to_str().unwrap(),
+          &SourceMap::new(),
+        );
+        kai_llvm_gen::run_llvm_passes(ll_module, &kai_llvm_gen::llvm_pipeline(opt_level));
+        let options = kai_llvm_gen::TargetOptions::default();
+        kai_llvm_gen::link::build_executable(ll_module, &exe_path, &options, false)
+          .map_err(|diag| format!("could not build {:?}: {}", path, diag.render_plain()))?;
+      }
 
       let expected_res = expect_re
         .captures(expect_cap.unwrap().to_owned().as_str())
@@ -57,7 +96,7 @@ fn test_compile(mut path: PathBuf) -> Result<(), String> {
         .as_str()
         .parse::<i32>()
         .unwrap();
-      let res = Command::new("./a.out")
+      let res = Command::new(exe_path.to_str().unwrap())
         .status()
         .expect("Could not run executable");
       if res.code().unwrap() != expected_res {
@@ -68,37 +107,138 @@ fn test_compile(mut path: PathBuf) -> Result<(), String> {
           path,
         ));
       }
-      return Ok(());
+
+      // the interpreters have to agree with the native program, the IR one
+      // running the same IR that was handed to LLVM
+      let ast_interp_res =
+        kai_interp::ast_interp::run_main(&ast).map_err(|diag| diag.render_plain());
+      let ir_interp_res = kai_interp::ir_interp::run_main(&ir).map_err(|diag| diag.render_plain());
+      if ast_interp_res != Ok(expected_res) || ir_interp_res != Ok(expected_res) {
+        return Err(format!(
+          "interpreters do not match the native result.\nnative: {:?}\nast: {:?}\nir: {:?}\npath: {:?}",
+          res.code(),
+          ast_interp_res,
+          ir_interp_res,
+          path,
+        ));
+      }
+      Ok(())
     }
     Err(_) => {
-      if let Some(_) = fail_cap {
+      if fail_cap.is_some() {
         return Ok(());
       }
 
-      return Err(format!(
+      Err(format!(
         "found failure parsing file {:?} when expecting success",
         path,
-      ));
+      ))
     }
   }
 }
 
-#[test]
-fn test_basic() {
-  let basic_path = "example/basic/";
+fn test_compile_dir(example_path: &str) {
+  test_compile_dir_with(example_path, false, OptLevel::O0);
+}
+
+fn test_compile_dir_with(example_path: &str, out_of_ssa: bool, opt_level: OptLevel) {
   let mut dir = PathBuf::from_str(env!("CARGO_MANIFEST_DIR")).unwrap();
-  dir.push(basic_path);
-
-  for entry in dir.read_dir().expect("could not read basic dir") {
-    if let Ok(entry) = entry {
-      let path = entry.path();
-      // println!("{:?}", path);
-      // println!("{:?}", path.to_str().unwrap().ends_with(".kai"));
-      if !path.to_str().unwrap().ends_with(".kai") {
-        continue;
-      }
+  dir.push(example_path);
 
-      test_compile(path).unwrap();
+  for entry in dir
+    .read_dir()
+    .expect("could not read example dir")
+    .flatten()
+  {
+    let path = entry.path();
+    // println!("{:?}", path);
+    // println!("{:?}", path.to_str().unwrap().ends_with(".kai"));
+    if !path.to_str().unwrap().ends_with(".kai") {
+      continue;
     }
+
+    test_compile(path, out_of_ssa, opt_level).unwrap();
+  }
+}
+
+#[test]
+fn test_basic() {
+  test_compile_dir("example/basic/");
+}
+
+#[test]
+fn test_functions() {
+  test_compile_dir("example/functions/");
+}
+
+#[test]
+fn test_loops() {
+  test_compile_dir("example/loops/");
+}
+
+#[test]
+fn test_strings() {
+  test_compile_dir("example/strings/");
+}
+
+#[test]
+fn test_controlflow() {
+  test_compile_dir("example/controlflow/");
+}
+
+#[test]
+fn test_controlflow_out_of_ssa() {
+  test_compile_dir_with("example/controlflow/", true, OptLevel::O0);
+  test_compile_dir_with("example/loops/", true, OptLevel::O0);
+}
+
+#[test]
+fn test_optimized() {
+  for dir in &["basic", "functions", "loops", "strings", "controlflow"] {
+    let example_path = format!("example/{}/", dir);
+    test_compile_dir_with(&example_path, false, OptLevel::O2);
+    // vars assigned more than once live in allocas, which mem2reg has to promote
+    test_compile_dir_with(&example_path, true, OptLevel::O3);
+  }
+}
+
+// the LLVM IR of the example at path, out of SSA, after the LLVM passes of opt_level
+fn llvm_ir_out_of_ssa(example_path: &str, opt_level: OptLevel) -> String {
+  let mut path = PathBuf::from_str(env!("CARGO_MANIFEST_DIR")).unwrap();
+  path.push(example_path);
+  let program = std::fs::read_to_string(&path).unwrap();
+  let program = kai_common::remove_comments::remove_comments(&program).unwrap();
+  let ast = kai_parse::parse_program(&program).unwrap();
+  let mut ir = kai_ir::ir_gen(&ast, kai_typecheck::typecheck(&ast).unwrap());
+  for ir_func in ir.functions.iter_mut() {
+    kai_ir::out_of_ssa::destruct_ssa(ir_func);
+  }
+
+  let ll_path = path.with_file_name(format!(
+    "{}_{:?}.ll",
+    path.file_stem().unwrap().to_str().unwrap(),
+    opt_level
+  ));
+  unsafe {
+    let ll_module = kai_llvm_gen::llvm_ir_gen(&ir, "test", &SourceMap::new());
+    kai_llvm_gen::run_llvm_passes(ll_module, &kai_llvm_gen::llvm_pipeline(opt_level));
+    kai_llvm_gen::write_llvm_ir(ll_module, &ll_path).unwrap();
+  }
+  let ll = std::fs::read_to_string(&ll_path).unwrap();
+  std::fs::remove_file(&ll_path).unwrap();
+  ll
+}
+
+#[test]
+fn test_llvm_passes_remove_allocas() {
+  for example in &[
+    "loops/kai_while_sum.kai",
+    "controlflow/kai_branched_var.kai",
+  ] {
+    let example_path = format!("example/{}", example);
+    let unoptimized = llvm_ir_out_of_ssa(&example_path, OptLevel::O0);
+    assert!(unoptimized.contains("alloca"), "{}", unoptimized);
+    let optimized = llvm_ir_out_of_ssa(&example_path, OptLevel::O2);
+    assert!(!optimized.contains("alloca"), "{}", optimized);
   }
 }